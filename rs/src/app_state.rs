@@ -19,8 +19,8 @@ pub fn update_dyanmic_data(time: f32, canvas_height: f32, canvas_width: f32) {
     let mut data = APP_STATE.lock().unwrap();
 
     *data = Arc::new(AppState {
-        canvas_height: canvas_height,
-        canvas_width: canvas_width,
+        canvas_height,
+        canvas_width,
 
         // Doing these calcs here is better than in render, only happen once
         control_bottom: half_canvas_height - half_display_size,
@@ -28,7 +28,7 @@ pub fn update_dyanmic_data(time: f32, canvas_height: f32, canvas_width: f32) {
         control_left: half_canvas_width - half_display_size,
         control_right: half_canvas_width + half_display_size,
 
-        time: time,
+        time,
         ..*data.clone()
     });
 }
@@ -118,3 +118,16 @@ pub fn update_mouse_position(x: f32, y: f32) {
         ..*data.clone()
     })
 }
+
+/**
+ * Points the camera from the given angles, as if the mouse had dragged it there.
+ */
+pub fn update_rotation(rotation_x_axis: f32, rotation_y_axis: f32) {
+    let mut data = APP_STATE.lock().unwrap();
+
+    *data = Arc::new(AppState {
+        rotation_x_axis,
+        rotation_y_axis,
+        ..*data.clone()
+    })
+}
@@ -0,0 +1,83 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::*;
+use crate::error::MVisualError;
+use crate::visual::Visual;
+
+// Dragging rotates the camera, a press which moved further than this (in pixels) before the click was a drag and selects nothing
+const CLICK_TOLERANCE: f32 = 4.;
+
+type MouseHandler = Closure<dyn FnMut(MouseEvent)>;
+
+/*
+Selects whatever is clicked on the canvas, shift-click adds to or takes from the selection. Stops listening when dropped.
+*/
+pub struct ClickSelect {
+    canvas: HtmlCanvasElement,
+    on_mouse_down: MouseHandler,
+    on_click: MouseHandler,
+}
+
+impl ClickSelect {
+    pub fn attach(visual: Rc<RefCell<Visual>>) -> Result<Self, MVisualError> {
+        let canvas = visual
+            .borrow()
+            .canvas()
+            .ok_or_else(|| MVisualError::Context(String::from("The context has no canvas")))?;
+
+        let pressed_at = Rc::new(Cell::new((0f32, 0f32)));
+        let on_mouse_down = {
+            let pressed_at = pressed_at.clone();
+            move |event: MouseEvent| pressed_at.set(position(&event))
+        };
+        let on_mouse_down = Closure::wrap(Box::new(on_mouse_down) as Box<dyn FnMut(MouseEvent)>);
+
+        let on_click = move |event: MouseEvent| {
+            let (x, y) = position(&event);
+            let (pressed_x, pressed_y) = pressed_at.get();
+            if (x - pressed_x).hypot(y - pressed_y) > CLICK_TOLERANCE {
+                return;
+            }
+
+            let event = visual.borrow_mut().click_select(x, y, event.shift_key());
+            if let Err(error) = crate::util::selection::notify(event) {
+                console::error_1(&error.into());
+            }
+        };
+        let on_click = Closure::wrap(Box::new(on_click) as Box<dyn FnMut(MouseEvent)>);
+
+        canvas
+            .add_event_listener_with_callback("mousedown", on_mouse_down.as_ref().unchecked_ref())
+            .map_err(MVisualError::context)?;
+        canvas
+            .add_event_listener_with_callback("click", on_click.as_ref().unchecked_ref())
+            .map_err(MVisualError::context)?;
+
+        Ok(Self {
+            canvas,
+            on_mouse_down,
+            on_click,
+        })
+    }
+}
+
+impl Drop for ClickSelect {
+    fn drop(&mut self) {
+        // Only fails for listeners which were never added
+        let _ = self
+            .canvas
+            .remove_event_listener_with_callback("mousedown", self.on_mouse_down.as_ref().unchecked_ref());
+        let _ = self
+            .canvas
+            .remove_event_listener_with_callback("click", self.on_click.as_ref().unchecked_ref());
+    }
+}
+
+/*
+Where the event happened in canvas pixels from the top-left, which is what `Visual::pick` takes as long as the drawing buffer is the size the canvas is laid out at.
+*/
+fn position(event: &MouseEvent) -> (f32, f32) {
+    (event.offset_x() as f32, event.offset_y() as f32)
+}
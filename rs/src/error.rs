@@ -0,0 +1,64 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+/*
+Everything that can go wrong in the crate. Crossing into JS each variant becomes an `Error` whose `name` says which kind it was, so the host page can tell a missing WebGL context (show a fallback) from bad input (fix the call).
+*/
+#[derive(Debug)]
+pub enum MVisualError {
+    // No window, document, canvas or WebGL context
+    Context(String),
+    // A shader failed to preprocess, compile or link, or is missing an attribute
+    Shader(String),
+    // A buffer or texture could not be created
+    Buffer(String),
+    // Arguments passed in through the API don't make sense
+    Input(String),
+    // Rendered frames could not be encoded into an image or animation
+    Export(String),
+}
+
+impl MVisualError {
+    /**
+     * Wraps an exception thrown by a browser API.
+     */
+    pub fn context(value: JsValue) -> Self {
+        MVisualError::Context(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MVisualError::Context(_) => "MVisualContextError",
+            MVisualError::Shader(_) => "MVisualShaderError",
+            MVisualError::Buffer(_) => "MVisualBufferError",
+            MVisualError::Input(_) => "MVisualInputError",
+            MVisualError::Export(_) => "MVisualExportError",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            MVisualError::Context(message)
+            | MVisualError::Shader(message)
+            | MVisualError::Buffer(message)
+            | MVisualError::Input(message)
+            | MVisualError::Export(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for MVisualError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name(), self.message())
+    }
+}
+
+impl std::error::Error for MVisualError {}
+
+impl From<MVisualError> for JsValue {
+    fn from(error: MVisualError) -> Self {
+        let js_error = js_sys::Error::new(error.message());
+        js_error.set_name(error.name());
+        js_error.into()
+    }
+}
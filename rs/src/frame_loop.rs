@@ -0,0 +1,182 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::*;
+use crate::error::MVisualError;
+use crate::visual::Visual;
+
+// Most simulation steps run for one frame. After a long stall (a hidden tab gets no frames) the rest are dropped so the animation carries on from where it was instead of jumping ahead
+const MAX_STEPS_PER_FRAME: f64 = 10.;
+
+// Animation frame timestamps jitter by a fraction of a ms, a frame that much early for the cap is still drawn
+const FRAME_CAP_TOLERANCE: f64 = 1.;
+
+// The frame callback, shared with itself so it can schedule the next frame
+type FrameCallback = Rc<RefCell<Option<Closure<dyn FnMut(f64)>>>>;
+
+#[derive(Clone, Copy)]
+pub struct LoopSettings {
+    // Wall clock ms per simulation step
+    pub time_step: f64,
+    // Fewest ms between drawn frames, 0 draws on every animation frame
+    pub min_frame_interval: f64,
+}
+
+impl Default for LoopSettings {
+    fn default() -> Self {
+        Self {
+            time_step: 1000. / 60.,
+            min_frame_interval: 0.,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Timing {
+    // Timestamp of the last drawn frame
+    last_frame: Option<f64>,
+    // Wall clock ms which have passed but not been stepped yet, always less than a step after a frame
+    unsimulated: f64,
+    // Wall clock time handed to `Visual::update`, advanced in whole steps only
+    simulation_time: f64,
+}
+
+/*
+A `requestAnimationFrame` loop drawing a `Visual` on its canvas, plus a `ResizeObserver` keeping the canvas's drawing buffer the size it is laid out at. Everything stops when it is dropped.
+
+The only state anything animates from is the animation clock, so the simulation steps of a frame are all taken at once by handing `update` the wall clock time moved on by whole steps.
+*/
+pub struct FrameLoop {
+    // `None` when no frame is scheduled, after an error
+    request_id: Rc<Cell<Option<i32>>>,
+    // Taken out on drop to break the cycle
+    frame: FrameCallback,
+    resize_observer: ResizeObserver,
+    _on_resize: Closure<dyn FnMut(js_sys::Array)>,
+}
+
+impl FrameLoop {
+    pub fn start(visual: Rc<RefCell<Visual>>, settings: Rc<Cell<LoopSettings>>) -> Result<Self, MVisualError> {
+        let window = window().ok_or_else(|| MVisualError::Context(String::from("No window")))?;
+        let canvas = visual
+            .borrow()
+            .canvas()
+            .ok_or_else(|| MVisualError::Context(String::from("The context has no canvas")))?;
+        let timing = Rc::new(RefCell::new(Timing::default()));
+
+        let request_id = Rc::new(Cell::new(None));
+        let frame: FrameCallback = Rc::new(RefCell::new(None));
+        {
+            let visual = visual.clone();
+            let canvas = canvas.clone();
+            let timing = timing.clone();
+            let request_id = request_id.clone();
+            let next_frame = frame.clone();
+            let window = window.clone();
+
+            let on_frame = move |now: f64| {
+                let result = draw_frame(&mut visual.borrow_mut(), &canvas, settings.get(), &mut timing.borrow_mut(), now);
+                match result {
+                    Ok(()) => request_id.set(request_frame(&window, &next_frame)),
+                    Err(error) => {
+                        request_id.set(None);
+                        console::error_1(&error.into());
+                    }
+                }
+            };
+            *frame.borrow_mut() = Some(Closure::wrap(Box::new(on_frame) as Box<dyn FnMut(f64)>));
+        }
+
+        let observed = canvas.clone();
+        let on_resize = move |entries: js_sys::Array| {
+            // Only the canvas is observed
+            let entry: ResizeObserverEntry = match entries.get(0).dyn_into() {
+                Ok(entry) => entry,
+                Err(_) => return,
+            };
+            let rect = entry.content_rect();
+            let width = (rect.width().round() as u32).max(1);
+            let height = (rect.height().round() as u32).max(1);
+            if width == canvas.width() && height == canvas.height() {
+                return;
+            }
+            canvas.set_width(width);
+            canvas.set_height(height);
+
+            // Resizing clears the canvas, redraw now rather than show it blank until the next frame
+            let timing = timing.borrow();
+            if timing.last_frame.is_some() {
+                let mut visual = visual.borrow_mut();
+                match visual.update(timing.simulation_time as f32, height as f32, width as f32) {
+                    Ok(()) => visual.render(),
+                    Err(error) => console::error_1(&error.into()),
+                }
+            }
+        };
+        let on_resize = Closure::wrap(Box::new(on_resize) as Box<dyn FnMut(js_sys::Array)>);
+        let resize_observer = ResizeObserver::new(on_resize.as_ref().unchecked_ref()).map_err(MVisualError::context)?;
+        resize_observer.observe(&observed);
+
+        request_id.set(request_frame(&window, &frame));
+        Ok(Self {
+            request_id,
+            frame,
+            resize_observer,
+            _on_resize: on_resize,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.request_id.get().is_some()
+    }
+}
+
+impl Drop for FrameLoop {
+    fn drop(&mut self) {
+        if let (Some(id), Some(window)) = (self.request_id.take(), window()) {
+            // Can only fail for ids which were never handed out
+            let _ = window.cancel_animation_frame(id);
+        }
+        self.resize_observer.disconnect();
+        self.frame.borrow_mut().take();
+    }
+}
+
+fn request_frame(window: &Window, frame: &FrameCallback) -> Option<i32> {
+    let frame = frame.borrow();
+    let callback = frame.as_ref()?;
+    window.request_animation_frame(callback.as_ref().unchecked_ref()).ok()
+}
+
+/*
+Steps the simulation up to `now` and draws, unless the frame cap says it is too soon.
+*/
+fn draw_frame(
+    visual: &mut Visual,
+    canvas: &HtmlCanvasElement,
+    settings: LoopSettings,
+    timing: &mut Timing,
+    now: f64,
+) -> Result<(), MVisualError> {
+    let elapsed = match timing.last_frame {
+        Some(last) if now - last < settings.min_frame_interval - FRAME_CAP_TOLERANCE => return Ok(()),
+        Some(last) => now - last,
+        None => 0.,
+    };
+    timing.last_frame = Some(now);
+
+    timing.unsimulated += elapsed;
+    let steps = (timing.unsimulated / settings.time_step).floor();
+    if steps > MAX_STEPS_PER_FRAME {
+        timing.simulation_time += MAX_STEPS_PER_FRAME * settings.time_step;
+        timing.unsimulated = 0.;
+    } else {
+        timing.simulation_time += steps * settings.time_step;
+        timing.unsimulated -= steps * settings.time_step;
+    }
+
+    visual.update(timing.simulation_time as f32, canvas.height() as f32, canvas.width() as f32)?;
+    visual.render();
+    Ok(())
+}
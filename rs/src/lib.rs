@@ -1,15 +1,21 @@
 extern crate wasm_bindgen;
 use wasm_bindgen::prelude::*;
-use web_sys::WebGlRenderingContext as GL;
-use web_sys::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use error::MVisualError;
 
 #[macro_use]
 extern crate lazy_static;
 
 mod app_state;
+mod click_select;
+mod error;
+mod frame_loop;
 mod programs;
+mod scene;
 mod shaders;
 mod util;
+mod visual;
 
 #[wasm_bindgen]
 extern "C" {
@@ -17,44 +23,584 @@ extern "C" {
     fn log(s: &str);
 }
 
+/*
+The host page's handle. The state lives in a shared `Visual` so that the frame loop started by `start` can drive it between calls from the page.
+*/
 #[wasm_bindgen]
 pub struct MVisual {
-    gl: WebGlRenderingContext,
-    programs: Vec<Box<dyn programs::Program>>,
+    visual: Rc<RefCell<visual::Visual>>,
+    // Kept while stopped, so settings made before `start` apply once it runs
+    loop_settings: Rc<Cell<frame_loop::LoopSettings>>,
+    // `None` until `start` and after `stop`
+    frame_loop: Option<frame_loop::FrameLoop>,
+    // `None` unless clicking selects
+    click_select: Option<click_select::ClickSelect>,
 }
 
 #[wasm_bindgen]
 impl MVisual {
+    /**
+     * Throws (instead of panicking) when WebGL isn't available or a shader fails to build, check the error's `name` to tell which.
+     *
+     * Uses WebGL2 when the browser has it and WebGL1 otherwise.
+     */
     #[wasm_bindgen(constructor)]
-    pub fn new() -> Self {
+    pub fn new() -> Result<MVisual, MVisualError> {
+        Self::with_backend("webgl2")
+    }
+
+    /**
+     * Like the constructor but picks the context, "webgl2" (falls back to WebGL1 when unavailable) or "webgl1".
+     */
+    pub fn with_backend(backend: &str) -> Result<MVisual, MVisualError> {
         console_error_panic_hook::set_once();
-        let gl = util::webgl::initialize_webgl_context().unwrap();
+        let backend = util::gl::Backend::from_name(backend)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown backend: {}, expected webgl1 or webgl2", backend)))?;
 
-        let mut programs: Vec<Box<dyn programs::common::Program>> = Vec::new();
-        // programs.push(Box::new(programs::Graph3D::new(&gl)));
-        programs.push(Box::new(programs::Sphere3D::new(&gl, true)));
+        Ok(Self {
+            visual: Rc::new(RefCell::new(visual::Visual::new(backend)?)),
+            loop_settings: Rc::new(Cell::new(frame_loop::LoopSettings::default())),
+            frame_loop: None,
+            click_select: None,
+        })
+    }
 
-        Self {
-            gl: gl,
-            programs: programs,
+    /**
+     * Starts drawing on every animation frame, with the canvas's drawing buffer kept the size the page lays the canvas out at. Give the canvas its size with CSS.
+     *
+     * The animation clock moves in fixed steps (`set_time_step`) however often frames come, and frames are drawn as often as the display refreshes up to `set_max_fps`. The loop stops itself and logs the error if a frame fails, and stops when the visual is freed, so keep a reference to it. Does nothing while already running.
+     */
+    pub fn start(&mut self) -> Result<(), MVisualError> {
+        if self.is_running() {
+            return Ok(());
         }
+        // A loop stopped by an error is replaced
+        self.frame_loop = None;
+        self.frame_loop = Some(frame_loop::FrameLoop::start(self.visual.clone(), self.loop_settings.clone())?);
+        Ok(())
+    }
+
+    /**
+     * Stops the loop `start` started. The canvas keeps the last frame.
+     */
+    pub fn stop(&mut self) {
+        self.frame_loop = None;
     }
 
-    pub fn update(&mut self, time: f32, height: f32, width: f32) -> Result<(), JsValue> {
-        app_state::update_dyanmic_data(time, height, width);
-        return Ok(());
+    pub fn is_running(&self) -> bool {
+        self.frame_loop.as_ref().is_some_and(|frame_loop| frame_loop.is_running())
     }
 
+    /**
+     * Most frames the loop draws per second, 0 (the default) for as many as the display shows.
+     */
+    pub fn set_max_fps(&mut self, fps: f32) -> Result<(), MVisualError> {
+        if !(fps >= 0. && fps.is_finite()) {
+            return Err(MVisualError::Input(format!("Frame cap must be 0 or more, got {}", fps)));
+        }
+        let settings = self.loop_settings.get();
+        self.loop_settings.set(frame_loop::LoopSettings {
+            min_frame_interval: if fps == 0. { 0. } else { 1000. / fps as f64 },
+            ..settings
+        });
+        Ok(())
+    }
+
+    /**
+     * Wall clock ms the loop moves the animation clock by at a time, 1000 / 60 by default. Frames which come sooner don't move it, frames which come later move it by as many whole steps as fit.
+     */
+    pub fn set_time_step(&mut self, ms: f32) -> Result<(), MVisualError> {
+        if !(ms > 0. && ms.is_finite()) {
+            return Err(MVisualError::Input(format!("Time step must be positive, got {}", ms)));
+        }
+        let settings = self.loop_settings.get();
+        self.loop_settings.set(frame_loop::LoopSettings {
+            time_step: ms as f64,
+            ..settings
+        });
+        Ok(())
+    }
+
+    /**
+     * The context actually in use, "webgl2" or "webgl1".
+     */
+    pub fn backend(&self) -> String {
+        self.visual.borrow().backend()
+    }
+
+    /**
+     * `time` is the wall clock time in ms, it also moves the animation clock on while playing.
+     *
+     * Also notices when the GPU context comes back after being lost and rebuilds everything, throws if that fails.
+     *
+     * Only needed by pages driving their own loop instead of calling `start`.
+     */
+    pub fn update(&mut self, time: f32, height: f32, width: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().update(time, height, width)
+    }
+
+    /**
+     * Draws a frame, see `update`.
+     */
     pub fn render(&self) {
-        self.gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+        self.visual.borrow_mut().render()
+    }
+
+    /**
+     * Adds a text label anchored to a point in the scene. Returns the index of the label.
+     */
+    pub fn add_label(&mut self, text: &str, x: f32, y: f32, z: f32) -> usize {
+        self.visual.borrow_mut().add_label(text, x, y, z)
+    }
+
+    /**
+     * Adds a text label at a fixed spot on the canvas, in pixels from the bottom left corner. Returns the index of the label.
+     */
+    pub fn add_screen_label(&mut self, text: &str, x: f32, y: f32) -> usize {
+        self.visual.borrow_mut().add_screen_label(text, x, y)
+    }
+
+    /**
+     * Removes every label, including the axis labels.
+     */
+    pub fn clear_labels(&mut self) {
+        self.visual.borrow_mut().clear_labels()
+    }
+
+    /**
+     * Shows a colorbar for the active color map, labelled from `min` to `max`.
+     */
+    pub fn set_colorbar(&mut self, min: f32, max: f32, title: &str) {
+        self.visual.borrow_mut().set_colorbar(min, max, title)
+    }
+
+    /**
+     * Switches the active color map to one of "viridis", "magma", "plasma", "cividis" or "coolwarm".
+     */
+    pub fn set_color_map(&mut self, name: &str) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_color_map(name)
+    }
+
+    /**
+     * Uses a custom gradient as the active color map. `colors` holds evenly spaced RGB triples on [0, 1].
+     */
+    pub fn set_custom_color_map(&mut self, colors: Vec<f32>) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_custom_color_map(colors)
+    }
+
+    /**
+     * Adds a surface z = f(x, y) from sampled heights.
+     *
+     * `heights` is a square grid with x changing fastest, spread over `domain` = [x_min, x_max, y_min, y_max]. `scalars` is either empty or one value per height. `color_mode` is "height", "scalar" or "slope".
+     *
+     * The colorbar is set to the range of the values the surface is colored by. Returns the id of the surface.
+     */
+    pub fn add_surface(
+        &mut self,
+        heights: Vec<f32>,
+        scalars: Vec<f32>,
+        domain: Vec<f32>,
+        color_mode: &str,
+    ) -> Result<u32, MVisualError> {
+        self.visual.borrow_mut().add_surface(heights, scalars, domain, color_mode)
+    }
+
+    /**
+     * Adds a surface z = f(x, y, t) from an expression such as "sin(3 * x - t) * cos(y)".
+     *
+     * It is sampled on a `resolution` by `resolution` grid over `domain` = [x_min, x_max, y_min, y_max], and sampled again every frame while the animation plays when it uses t. `color_mode` is "height" or "slope". Colors and the colorbar keep the range of values the surface starts with.
+     *
+     * Throws on a malformed expression, saying where. Returns the id of the surface.
+     */
+    pub fn add_surface_expression(
+        &mut self,
+        expression: &str,
+        domain: Vec<f32>,
+        resolution: usize,
+        color_mode: &str,
+    ) -> Result<u32, MVisualError> {
+        self.visual.borrow_mut().add_surface_expression(expression, domain, resolution, color_mode)
+    }
+
+    /**
+     * Like `add_surface_expression` but the expression is compiled to GLSL and evaluated in the vertex shader, so resolutions the CPU couldn't sample every frame animate smoothly.
+     *
+     * `normals` is "analytic" (from the expression's derivatives) or "finite_difference" (from neighbouring heights one grid step apart, like the CPU surfaces). Returns the id of the surface.
+     */
+    pub fn add_gpu_surface(
+        &mut self,
+        expression: &str,
+        domain: Vec<f32>,
+        resolution: usize,
+        color_mode: &str,
+        normals: &str,
+    ) -> Result<u32, MVisualError> {
+        self.visual.borrow_mut().add_gpu_surface(expression, domain, resolution, color_mode, normals)
+    }
 
-        let curr_state = app_state::get_curr_state();
+    /**
+     * Adds a curve (x(s, t), y(s, t), z(s, t)) for s from `range[0]` to `range[1]`, e.g. a helix "cos(s)", "sin(s)", "s / 10". It is drawn through `samples` evenly spaced values of s in the material color.
+     *
+     * Curves which use t are sampled again every frame while the animation plays. Returns the id of the curve.
+     */
+    pub fn add_curve(
+        &mut self,
+        x: &str,
+        y: &str,
+        z: &str,
+        range: Vec<f32>,
+        samples: usize,
+    ) -> Result<u32, MVisualError> {
+        self.visual.borrow_mut().add_curve(x, y, z, range, samples)
+    }
 
-        for program in self.programs.iter() {
-            program.render(
-                &self.gl,
-                &curr_state,
-            );
+    /**
+     * Starts (or resumes) the animation clock which drives everything defined in terms of t. It starts out playing.
+     */
+    pub fn play(&mut self) {
+        self.visual.borrow_mut().play()
+    }
+
+    /**
+     * Stops the animation clock where it is, `play` carries on from there.
+     */
+    pub fn pause(&mut self) {
+        self.visual.borrow_mut().pause()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.visual.borrow().is_playing()
+    }
+
+    /**
+     * Jumps the animation to `time` seconds, playing or not. Call it as a slider moves to scrub.
+     */
+    pub fn seek(&mut self, time: f32) {
+        self.visual.borrow_mut().seek(time)
+    }
+
+    /**
+     * How many animation seconds pass per second, 1 by default. Negative values play backwards.
+     */
+    pub fn set_speed(&mut self, speed: f32) {
+        self.visual.borrow_mut().set_speed(speed)
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.visual.borrow().speed()
+    }
+
+    /**
+     * The current value of t, in seconds.
+     */
+    pub fn animation_time(&self) -> f32 {
+        self.visual.borrow().animation_time()
+    }
+
+    /**
+     * Adds a keyframe turning the camera to `rotation_x`, `rotation_y` (radians, as `camera_rotation` gives them) at animation time `time`. The camera turns the shortest way round between keyframes.
+     *
+     * `easing` is "linear", "step", "ease-in", "ease-out" or "ease-in-out", and shapes the move from the keyframe before. A keyframe at the same time as another replaces it.
+     */
+    pub fn add_camera_keyframe(&mut self, time: f32, rotation_x: f32, rotation_y: f32, easing: &str) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().add_camera_keyframe(time, rotation_x, rotation_y, easing)
+    }
+
+    /**
+     * The camera's current [rotation_x, rotation_y], to keyframe the view as it is.
+     */
+    pub fn camera_rotation(&self) -> Vec<f32> {
+        self.visual.borrow().camera_rotation()
+    }
+
+    /**
+     * Adds a keyframe for a setting of object `id`: "color", "emissive", "wireframe-color" or "scale" with 3 `values`, or "opacity", "ambient", "diffuse", "specular" or "shininess" with one. `easing` is as for `add_camera_keyframe`. Only surfaces, curves and spheres can be scaled.
+     *
+     * A surface's or curve's function can't be keyframed, use `t` in it to animate its shape.
+     *
+     * Each setting follows its keyframes while the animation time is between its first and last one and is left alone outside of them, so it can still be changed by hand. Keyframes take effect the next time the animation time changes.
+     */
+    pub fn add_keyframe(
+        &mut self,
+        id: u32,
+        property: &str,
+        time: f32,
+        values: Vec<f32>,
+        easing: &str,
+    ) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().add_keyframe(id, property, time, values, easing)
+    }
+
+    /**
+     * Removes every keyframe. The camera and objects stay as the timeline last left them.
+     */
+    pub fn clear_timeline(&mut self) {
+        self.visual.borrow_mut().clear_timeline()
+    }
+
+    /**
+     * Animation time of the last keyframe, for the end of a scrub slider.
+     */
+    pub fn timeline_duration(&self) -> f32 {
+        self.visual.borrow().timeline_duration()
+    }
+
+    /**
+     * Exports `duration` seconds of the animation, from t = 0, as an animated "gif" or "apng" of `width` by `height` pixels. Returns the file's bytes.
+     *
+     * Frames are rendered offscreen and the animation is stepped exactly 1 / `fps` seconds between them however long they take, so exporting the same scene gives the same file. GIFs are limited to 256 colors and 50 fps. The page is unresponsive until the export finishes.
+     */
+    pub fn export_animation(
+        &mut self,
+        format: &str,
+        fps: f32,
+        duration: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, MVisualError> {
+        self.visual.borrow_mut().export_animation(format, fps, duration, width, height)
+    }
+
+    /**
+     * The frames `export_animation` would render, as an array of PNG files (`Uint8Array`s) in frame order, for numbering frame_0000.png, frame_0001.png, ...
+     */
+    pub fn export_frames(&mut self, fps: f32, duration: f32, width: u32, height: u32) -> Result<js_sys::Array, MVisualError> {
+        self.visual.borrow_mut().export_frames(fps, duration, width, height)
+    }
+
+    /**
+     * Adds spheres of one `radius` centered on `positions` (x, y, z triples, in the same space as the axes and surfaces), drawn with a single instanced draw call when supported. Returns the id of the spheres, they share one material.
+     */
+    pub fn add_spheres(&mut self, positions: Vec<f32>, radius: f32) -> Result<u32, MVisualError> {
+        self.visual.borrow_mut().add_spheres(positions, radius)
+    }
+
+    /**
+     * Switches the lighting of object `id` between "gouraud" (per vertex) and "phong" (per fragment Blinn-Phong).
+     */
+    pub fn set_shading(&mut self, id: u32, shading: &str) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_shading(id, shading)
+    }
+
+    /**
+     * Stretches object `id` by (x, y, z) along its own axes, a sphere scaled unevenly becomes an ellipsoid.
+     */
+    pub fn set_scale(&mut self, id: u32, x: f32, y: f32, z: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_scale(id, x, y, z)
+    }
+
+    /**
+     * Base color of object `id`. Objects colored by a color map ignore it.
+     */
+    pub fn set_material_color(&mut self, id: u32, r: f32, g: f32, b: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_material_color(id, r, g, b)
+    }
+
+    /**
+     * How strongly object `id` reflects ambient, diffuse and specular light, and how tight the specular highlight is.
+     */
+    pub fn set_material_reflectance(
+        &mut self,
+        id: u32,
+        ambient: f32,
+        diffuse: f32,
+        specular: f32,
+        shininess: f32,
+    ) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_material_reflectance(id, ambient, diffuse, specular, shininess)
+    }
+
+    pub fn set_material_opacity(&mut self, id: u32, opacity: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_material_opacity(id, opacity)
+    }
+
+    pub fn set_material_emissive(&mut self, id: u32, r: f32, g: f32, b: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_material_emissive(id, r, g, b)
+    }
+
+    pub fn set_wireframe_color(&mut self, id: u32, r: f32, g: f32, b: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_wireframe_color(id, r, g, b)
+    }
+
+    /**
+     * Adds a light shining from direction (x, y, z). Returns the index of the light.
+     */
+    pub fn add_directional_light(&mut self, x: f32, y: f32, z: f32, intensity: f32) -> Result<usize, MVisualError> {
+        self.visual.borrow_mut().add_directional_light(x, y, z, intensity)
+    }
+
+    /**
+     * Adds a light shining out from (x, y, z). Returns the index of the light.
+     */
+    pub fn add_point_light(&mut self, x: f32, y: f32, z: f32, intensity: f32) -> Result<usize, MVisualError> {
+        self.visual.borrow_mut().add_point_light(x, y, z, intensity)
+    }
+
+    /**
+     * Moves a light, (x, y, z) is the direction for directional lights and the position for point lights.
+     */
+    pub fn set_light_position(&mut self, index: usize, x: f32, y: f32, z: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_light_position(index, x, y, z)
+    }
+
+    pub fn set_light_color(&mut self, index: usize, r: f32, g: f32, b: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_light_color(index, r, g, b)
+    }
+
+    pub fn set_light_intensity(&mut self, index: usize, intensity: f32) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_light_intensity(index, intensity)
+    }
+
+    /**
+     * Removes every light except the ambient light.
+     */
+    pub fn clear_lights(&mut self) {
+        self.visual.borrow_mut().clear_lights()
+    }
+
+    pub fn set_ambient_light(&mut self, r: f32, g: f32, b: f32) {
+        self.visual.borrow_mut().set_ambient_light(r, g, b)
+    }
+
+    /**
+     * When enabled light positions are relative to the camera (x right, y up, z towards the viewer) instead of the scene, so the lighting doesn't change as the scene is rotated.
+     */
+    pub fn set_light_follows_camera(&mut self, follow_camera: bool) {
+        self.visual.borrow_mut().set_light_follows_camera(follow_camera)
+    }
+
+    pub fn hide_colorbar(&mut self) {
+        self.visual.borrow_mut().hide_colorbar()
+    }
+
+    /**
+     * Moves the colorbar to "top-left", "top-right", "bottom-left" or "bottom-right".
+     */
+    pub fn set_colorbar_corner(&mut self, corner: &str) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_colorbar_corner(corner)
+    }
+
+    /**
+     * Names object `id` in the legend, which lists every named object in its material's current color. An empty `name` takes it out again. Objects colored by a color map are left out, the colorbar explains those.
+     */
+    pub fn set_object_name(&mut self, id: u32, name: &str) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_object_name(id, name)
+    }
+
+    /**
+     * Moves the legend to "top-left", "top-right", "bottom-left" or "bottom-right".
+     */
+    pub fn set_legend_corner(&mut self, corner: &str) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().set_legend_corner(corner)
+    }
+
+    /**
+     * Shows frame stats in "top-left", "top-right", "bottom-left" or "bottom-right": frame and render times, draw calls, primitives drawn, bytes uploaded, state changes and a histogram of frame times.
+     */
+    pub fn show_stats(&mut self, corner: &str) -> Result<(), MVisualError> {
+        self.visual.borrow_mut().show_stats(corner)
+    }
+
+    pub fn hide_stats(&mut self) {
+        self.visual.borrow_mut().hide_stats()
+    }
+
+    /**
+     * Stats of the last frame drawn, whether or not they are shown: `{ frames, frameTime, renderTime, drawCalls, triangles, lines, points, bytesUploaded, stateChanges, culled, histogram: { edges, counts } }`. Times are in ms, `frameTime` runs from the start of the frame before. `counts` has one more entry than `edges` (the upper edges of its buckets) for the frames slower than all of them.
+     *
+     * Counted while drawing, so they cover everything going through the context, and cost next to nothing when not looked at.
+     */
+    pub fn frame_stats(&self) -> Result<js_sys::Object, MVisualError> {
+        self.visual.borrow().frame_stats()
+    }
+
+    /**
+     * Clears the frame time histogram and the frame count.
+     */
+    pub fn reset_stats(&mut self) {
+        self.visual.borrow_mut().reset_stats()
+    }
+
+    /**
+     * The nearest object under the canvas point (`x`, `y`) in pixels from the top-left, or `null` when there is none: `{ object, distance, position, normal }` plus, where they apply, `triangle`, `barycentric`, `instance`, `parameters` and `value`.
+     *
+     * `object` is the id the object was added with. `position` and `normal` are in the object's own coordinates. Surfaces report the `triangle` hit, the `barycentric` weights of its corners, `parameters` in [0, 1] across their x and y ranges and the `value` they are colored by. Spheres report which `instance` was hit and its longitude and latitude (radians) as `parameters`. Curves and labels can't be picked.
+     */
+    pub fn pick(&mut self, x: f32, y: f32) -> Result<JsValue, MVisualError> {
+        self.visual.borrow_mut().pick(x, y)
+    }
+
+    /**
+     * `pick` at the last known mouse position.
+     */
+    pub fn pick_at_mouse(&mut self) -> Result<JsValue, MVisualError> {
+        self.visual.borrow_mut().pick_at_mouse()
+    }
+
+    /**
+     * Removes object `id`, along with its keyframes and its place in the selection. Ids aren't reused, so those of the other objects still hold.
+     */
+    pub fn remove_object(&mut self, id: u32) -> Result<(), MVisualError> {
+        let event = self.visual.borrow_mut().remove_object(id);
+        util::selection::notify(event)
+    }
+
+    /**
+     * Ids of every object, in the order they were added. The demo sphere and the axes come first.
+     */
+    pub fn object_ids(&self) -> Vec<u32> {
+        self.visual.borrow().object_ids()
+    }
+
+    /**
+     * Whether clicking the canvas selects what is under the cursor (and a click on nothing clears the selection). Shift-click adds to the selection, or takes away what is already in it. Dragging to turn the camera doesn't count as a click. Off by default.
+     */
+    pub fn set_click_selection(&mut self, enabled: bool) -> Result<(), MVisualError> {
+        self.click_select = None;
+        if enabled {
+            self.click_select = Some(click_select::ClickSelect::attach(self.visual.clone())?);
         }
+        Ok(())
+    }
+
+    /**
+     * Selects object `id`, replacing the selection unless `additive`.
+     */
+    pub fn select(&mut self, id: u32, additive: bool) -> Result<(), MVisualError> {
+        let event = self.visual.borrow_mut().select(id, additive);
+        util::selection::notify(event)
+    }
+
+    pub fn deselect(&mut self, id: u32) -> Result<(), MVisualError> {
+        let event = self.visual.borrow_mut().deselect(id);
+        util::selection::notify(event)
+    }
+
+    pub fn clear_selection(&mut self) -> Result<(), MVisualError> {
+        let event = self.visual.borrow_mut().clear_selection();
+        util::selection::notify(event)
+    }
+
+    /**
+     * Ids of the selected objects, in the order they were selected.
+     */
+    pub fn selected(&self) -> Vec<u32> {
+        self.visual.borrow().selected()
+    }
+
+    /**
+     * Color added to the light selected objects give off, so they stand out whatever their own color. Curves add it to their color.
+     */
+    pub fn set_highlight_color(&mut self, r: f32, g: f32, b: f32) {
+        self.visual.borrow_mut().set_highlight_color(r, g, b)
+    }
+
+    /**
+     * Calls `listener` with `{ selected, added, removed, hit }` whenever the selection changes, by a click or by any of the calls above. `selected` is the whole selection and `added` and `removed` what changed, as ids. `hit` is what `pick` gave for the click, null otherwise. Pass null to stop.
+     *
+     * The listener is called from a microtask once the call which changed the selection has returned, so it can call back into the visual. What it throws is logged to the console.
+     */
+    pub fn set_selection_listener(&mut self, listener: Option<js_sys::Function>) {
+        self.visual.borrow_mut().set_selection_listener(listener)
     }
 }
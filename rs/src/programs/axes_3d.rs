@@ -0,0 +1,202 @@
+use crate::util::{Axes, AXIS_NAMES};
+use crate::util::ticks::format_tick;
+use crate::util::constants::*;
+use crate::util::math;
+use crate::util::webgl;
+use crate::util::gpu_buffer::GpuBuffer;
+use crate::app_state::AppState;
+use crate::error::MVisualError;
+use crate::scene::Scene;
+use super::common::Program;
+use super::text_3d::{Anchor, Label};
+use crate::util::bindings::{Attribute, Uniform};
+use crate::util::colormap::ColorMap;
+use crate::util::gl::Gl;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use nalgebra::{Matrix4, Perspective3, Vector3, Vector4};
+
+crate::bindings! {
+    struct AxesBindings {
+        mvp_transform: Uniform<Matrix4<f32>> = "uModelViewProjection",
+        opacity: Uniform<f32> = "uOpacity",
+        vertex_position: Attribute<[f32; 3]> = "aVertexPosition",
+        vertex_color: Attribute<[f32; 3]> = "aVertexColor",
+    }
+}
+
+pub struct Axes3D {
+    program: WebGlProgram,
+    axes: Axes,
+    bindings: AxesBindings,
+    // Data
+    buf_vertex_position: GpuBuffer<f32>,
+    buf_vertex_color: GpuBuffer<f32>,
+    buf_line_indices: GpuBuffer<u16>,
+    buf_arrow_indices: GpuBuffer<u16>,
+    buf_grid_indices: GpuBuffer<u16>,
+    // Settings
+    grid: bool,
+}
+
+impl Axes3D {
+    /**
+     * Axes covering the box from `min` to `max` (in world units).
+     *
+     * When `grid` is true, grid planes are drawn on the three walls of the box furthest from the camera.
+     */
+    pub fn new(gl: &Gl, min: Vector3<f32>, max: Vector3<f32>, grid: bool) -> Result<Self, MVisualError> {
+        Self::from_axes(gl, Axes::new(min, max), grid)
+    }
+
+    fn from_axes(gl: &Gl, axes: Axes, grid: bool) -> Result<Self, MVisualError> {
+        let program = webgl::link_program(
+            gl,
+            crate::shaders::vertex::axes_3d::SHADER,
+            crate::shaders::fragment::vary_color_from_vertex::SHADER,
+        )?;
+
+        // Buffer any data that will remain unchaged
+        let vertex_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ARRAY_BUFFER,
+            &axes.vertices,
+            GL::STATIC_DRAW
+        )?;
+        let color_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ARRAY_BUFFER,
+            &axes.colors,
+            GL::STATIC_DRAW
+        )?;
+        let lidx_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ELEMENT_ARRAY_BUFFER,
+            &axes.line_indices,
+            GL::STATIC_DRAW
+        )?;
+        let aidx_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ELEMENT_ARRAY_BUFFER,
+            &axes.arrow_indices,
+            GL::STATIC_DRAW
+        )?;
+        let gidx_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ELEMENT_ARRAY_BUFFER,
+            &axes.grid_indices,
+            GL::STATIC_DRAW
+        )?;
+
+        Ok(Self {
+            // Do everything that needs &program first
+            bindings: AxesBindings::new(gl, &program)?,
+            // Transfer program owner ship and finish
+            program,
+            buf_vertex_position: vertex_gpu_buffer,
+            buf_vertex_color: color_gpu_buffer,
+            buf_line_indices: lidx_gpu_buffer,
+            buf_arrow_indices: aidx_gpu_buffer,
+            buf_grid_indices: gidx_gpu_buffer,
+            // Settings
+            grid,
+            // Data
+            axes,
+        })
+    }
+
+    /**
+     * Axis names and tick values to hand to the text renderer.
+     */
+    pub fn labels(&self) -> Vec<Label> {
+        let mut labels = Vec::new();
+
+        for (axis, name) in AXIS_NAMES.iter().enumerate() {
+            let mut label = Label::new(name, Anchor::World(self.axes.name_position(axis)));
+            label.color = self.axes.axis_color(axis);
+            label.size = 18.;
+            // Names win over tick values when they overlap
+            label.priority = 1;
+            labels.push(label);
+
+            let ticks = &self.axes.ticks[axis];
+            for value in ticks.major.iter() {
+                let mut label = Label::new(
+                    &format_tick(*value, ticks.step),
+                    Anchor::World(self.axes.tick_label_position(axis, *value)),
+                );
+                label.color = self.axes.axis_color(axis);
+                labels.push(label);
+            }
+        }
+
+        labels
+    }
+}
+
+impl Program for Axes3D {
+    fn render(
+        &self,
+        gl: &Gl,
+        app_state: &AppState,
+        _scene: &Scene,
+    ) {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        let aspect_ratio = app_state.canvas_width / app_state.canvas_height;
+        let projection_matrix = Perspective3::new(
+            aspect_ratio,
+            FIELD_OF_VIEW,
+            Z_NEAR,
+            Z_FAR,
+        );
+
+        gl.use_program(Some(&self.program));
+
+        // Load verticies & colors
+        self.buf_vertex_position.bind(gl);
+        self.bindings.vertex_position.point(gl, 0, 0);
+
+        self.buf_vertex_color.bind(gl);
+        self.bindings.vertex_color.point(gl, 0, 0);
+
+        self.bindings.opacity.set(gl, &1.);
+        self.bindings.mvp_transform.set(gl, &(projection_matrix.as_matrix() * view_matrix));
+
+        // Axis lines and ticks
+        self.buf_line_indices.bind(gl);
+        gl.draw_elements_with_i32(GL::LINES, self.buf_line_indices.len() as i32, GL::UNSIGNED_SHORT, 0);
+
+        // Arrow heads
+        self.buf_arrow_indices.bind(gl);
+        gl.draw_elements_with_i32(GL::TRIANGLES, self.buf_arrow_indices.len() as i32, GL::UNSIGNED_SHORT, 0);
+
+        if self.grid {
+            // Camera position in world space is where the view matrix sends to the origin
+            let camera_position = view_matrix
+                .try_inverse()
+                .map(|inverse| (inverse * Vector4::new(0., 0., 0., 1.)).xyz())
+                .unwrap_or_else(Vector3::zeros);
+
+            self.buf_grid_indices.bind(gl);
+            for axis in 0..3 {
+                let side = self.axes.back_side(axis, &camera_position);
+                let (offset, count) = self.axes.grid_ranges[axis][side];
+
+                // Offset is in bytes, indices are u16
+                gl.draw_elements_with_i32(GL::LINES, count as i32, GL::UNSIGNED_SHORT, 2 * offset as i32);
+            }
+        }
+    }
+
+    fn restore(&mut self, gl: &Gl, _color_map: &ColorMap) -> Result<(), MVisualError> {
+        *self = Self::from_axes(gl, self.axes.clone(), self.grid)?;
+        Ok(())
+    }
+
+    fn release(&mut self, gl: &Gl) {
+        gl.delete_program(Some(&self.program));
+    }
+}
@@ -1,11 +1,361 @@
 use crate::app_state::AppState;
-use web_sys::WebGlRenderingContext;
-
+use crate::error::MVisualError;
+use crate::scene::Scene;
+use crate::util::bounds::Bounds;
+use crate::util::picking::{PickHit, Ray};
+use crate::util::colormap::ColorMap;
+use crate::util::lighting::{Lighting, MAX_LIGHTS};
+use crate::util::material::Material;
+use crate::util::gl::Gl;
+use crate::util::webgl;
+use web_sys::*;
+use crate::util::bindings::{Attribute, OptionalAttribute, Uniform};
+use crate::util::math;
+use nalgebra::{Matrix3, Matrix4, Vector3};
 
 pub trait Program {
     fn render(
         &self,
-        gl: &WebGlRenderingContext,
+        gl: &Gl,
         app_state: &AppState,
+        scene: &Scene,
     );
+
+    /*
+    Rebuilds every GPU resource (programs, buffers, textures) after the context was lost and restored, from the data kept on the CPU side. Settings like the material survive.
+    */
+    fn restore(&mut self, gl: &Gl, color_map: &ColorMap) -> Result<(), MVisualError>;
+
+    /*
+    Deletes the program's WebGL objects (programs, vertex arrays, textures) when it is removed from the scene. Buffers delete themselves when dropped. Not called after a context loss, the objects went with the old context.
+    */
+    fn release(&mut self, gl: &Gl);
+
+    /*
+    Called when the animation time (in seconds) changes. Programs whose geometry depends on time recompute and upload it here, the rest ignore it.
+    */
+    fn animate(&mut self, _gl: &Gl, _time: f32) -> Result<(), MVisualError> {
+        Ok(())
+    }
+
+    /*
+    Called when the active color map changes. Only programs which color by value need to do anything.
+    */
+    fn set_color_map(&mut self, _gl: &Gl, _color_map: &ColorMap) -> Result<(), MVisualError> {
+        Ok(())
+    }
+
+    /*
+    Switches how the program is lit. Programs without lighting ignore it.
+    */
+    fn set_shading(&mut self, _gl: &Gl, _shading: Shading) -> Result<(), MVisualError> {
+        Ok(())
+    }
+
+    /*
+    The material of the program's object, `None` when it doesn't have one.
+    */
+    fn material(&self) -> Option<Material> {
+        None
+    }
+
+    /*
+    Replaces the material, it is uploaded on the next render so no buffers are touched.
+    */
+    fn set_material(&mut self, _material: Material) {}
+
+    /*
+    The color the legend shows for the object, `None` leaves it out.
+    */
+    fn legend_color(&self) -> Option<[f32; 3]> {
+        self.material().map(|material| material.color)
+    }
+
+    /*
+    Stretches the object along its model's axes. Programs which can't be scaled ignore it.
+    */
+    fn set_scale(&mut self, _scale: Vector3<f32>) {}
+
+    /*
+    Whether `set_scale` does anything, so scale keyframes can be turned away up front.
+    */
+    fn is_scalable(&self) -> bool {
+        false
+    }
+
+    /*
+    Transparent programs are drawn after everything opaque, with blending and without writing depth.
+    */
+    fn is_transparent(&self) -> bool {
+        false
+    }
+
+    /*
+    Distance from the camera to the object along the view direction, used to draw transparent programs farthest first.
+    */
+    fn view_depth(&self, _app_state: &AppState) -> f32 {
+        0.
+    }
+
+    /*
+    Bounds of the object in view space this frame, objects outside the camera's frustum are skipped. `None` is always drawn, for programs which are always in view or whose extent isn't known on the CPU.
+    */
+    fn view_bounds(&self, _app_state: &AppState) -> Option<Bounds> {
+        None
+    }
+
+    /*
+    Called before each frame is drawn, programs with several levels of detail pick one for the object's size on screen here.
+    */
+    fn select_detail(&mut self, _app_state: &AppState) {}
+
+    /*
+    Where a view space `ray` first hits the object, `None` when it misses or the program can't be picked. Programs may build what they need to pick on the first call, so this is `&mut`.
+    */
+    fn pick(&mut self, _ray: &Ray, _app_state: &AppState) -> Option<PickHit> {
+        None
+    }
+
+    /*
+    Tints the object while it is selected, `None` once it no longer is. Programs without a material ignore it.
+    */
+    fn set_highlight(&mut self, _tint: Option<[f32; 3]>) {}
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Shading {
+    // Ambient + diffuse computed per vertex and interpolated, cheap but faceted on coarse meshes
+    Gouraud,
+    // Ambient + diffuse + specular computed per fragment
+    #[default]
+    BlinnPhong,
+}
+
+impl Shading {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gouraud" => Some(Shading::Gouraud),
+            "phong" | "blinn-phong" => Some(Shading::BlinnPhong),
+            _ => None,
+        }
+    }
+}
+
+/*
+Where a lit shader's base color comes from, which decides whether it has a `uMaterialColor` to bind.
+*/
+#[derive(Clone, Copy, PartialEq)]
+pub enum Coloring {
+    Material,
+    // The color map chunk, by whatever value the program passes it
+    ColorMap,
+}
+
+crate::bindings! {
+    /*
+    Uniforms and attributes every lit shader pair shares, whichever lights and shading it was linked for.
+    */
+    pub struct LitBindings {
+        pub model_view: Uniform<Matrix4<f32>> = "uModelView",
+        pub model_view_projection: Uniform<Matrix4<f32>> = "uModelViewProjection",
+        pub normal_matrix: Uniform<Matrix3<f32>> = "uNormalMatrix",
+        pub ambient: Uniform<f32> = "uAmbient",
+        pub diffuse: Uniform<f32> = "uDiffuse",
+        pub emissive: Uniform<[f32; 3]> = "uEmissive",
+        pub opacity: Uniform<f32> = "uOpacity",
+        pub vertex_position: Attribute<[f32; 3]> = "aVertexPosition",
+        // GPU evaluated surfaces compute their normals in the shader
+        pub vertex_normal: OptionalAttribute<[f32; 3]> = "aVertexNormal",
+    }
+}
+
+crate::bindings! {
+    // The lights as plain uniforms, on WebGL1
+    pub struct LightUniformBindings {
+        pub ambient_light_color: Uniform<[f32; 3]> = "uAmbientLightColor",
+        pub light_count: Uniform<i32> = "uLightCount",
+        pub light_position: Uniform<[[f32; 4]]> = "uLightPosition",
+        pub light_color: Uniform<[[f32; 3]]> = "uLightColor",
+    }
+}
+
+crate::bindings! {
+    // With the `Lighting` block (WebGL2) only the view, to move its world space lights into view space
+    pub struct LightBlockBindings {
+        pub view: Uniform<Matrix4<f32>> = "uView",
+    }
+}
+
+crate::bindings! {
+    // Only Blinn-Phong shading has highlights
+    pub struct SpecularBindings {
+        pub specular: Uniform<f32> = "uSpecular",
+        pub shininess: Uniform<f32> = "uShininess",
+    }
+}
+
+crate::bindings! {
+    pub struct MaterialColorBindings {
+        pub material_color: Uniform<[f32; 3]> = "uMaterialColor",
+    }
+}
+
+pub enum LightBindings {
+    Uniforms(LightUniformBindings),
+    UniformBuffer(LightBlockBindings),
+}
+
+/*
+A linked lit shader pair and its bindings, each part bound only for the variants which use it so a missing uniform is an error rather than silently skipped. Programs declare bindings for anything else (color map, instance offsets...) on `program` themselves.
+*/
+pub struct LitShader {
+    pub program: WebGlProgram,
+    pub bindings: LitBindings,
+    lights: LightBindings,
+    // Only with `Shading::BlinnPhong`
+    specular: Option<SpecularBindings>,
+    // Only with `Coloring::Material`
+    material_color: Option<MaterialColorBindings>,
+}
+
+impl LitShader {
+    /**
+     * Links the (vertex, fragment) pair for `shading`, `gouraud` for per vertex lighting and `blinn_phong` for per fragment lighting.
+     *
+     * On WebGL2 the pair is translated to GLSL ES 3.0 and reads its lights from the uniform buffer `Gl::upload_lighting` fills.
+     */
+    pub fn new(
+        gl: &Gl,
+        shading: Shading,
+        coloring: Coloring,
+        gouraud: (&str, &str),
+        blinn_phong: (&str, &str),
+    ) -> Result<Self, MVisualError> {
+        Self::with_defines(gl, shading, coloring, gouraud, blinn_phong, &[])
+    }
+
+    /**
+     * Like `new` with extra `defines` for both shaders, for programs which generate part of their GLSL.
+     */
+    pub fn with_defines(
+        gl: &Gl,
+        shading: Shading,
+        coloring: Coloring,
+        gouraud: (&str, &str),
+        blinn_phong: (&str, &str),
+        defines: &[(&str, &str)],
+    ) -> Result<Self, MVisualError> {
+        let (vert_source, frag_source) = match shading {
+            Shading::Gouraud => gouraud,
+            Shading::BlinnPhong => blinn_phong,
+        };
+        let max_lights = MAX_LIGHTS.to_string();
+        let mut all_defines = vec![("MAX_LIGHTS", max_lights.as_str())];
+        all_defines.extend_from_slice(defines);
+        let uses_uniform_buffer = gl.supports_uniform_buffers();
+        let program = if uses_uniform_buffer {
+            all_defines.push(("UNIFORM_BUFFERS", "1"));
+            let program = webgl::link_program_300_es(
+                gl,
+                vert_source,
+                frag_source,
+                &all_defines,
+            )?;
+            gl.bind_lighting_block(&program);
+            program
+        } else {
+            webgl::link_program_with_defines(
+                gl,
+                vert_source,
+                frag_source,
+                &all_defines,
+            )?
+        };
+
+        let lights = if uses_uniform_buffer {
+            LightBindings::UniformBuffer(LightBlockBindings::new(gl, &program)?)
+        } else {
+            LightBindings::Uniforms(LightUniformBindings::new(gl, &program)?)
+        };
+        let specular = match shading {
+            Shading::BlinnPhong => Some(SpecularBindings::new(gl, &program)?),
+            Shading::Gouraud => None,
+        };
+        let material_color = match coloring {
+            Coloring::Material => Some(MaterialColorBindings::new(gl, &program)?),
+            Coloring::ColorMap => None,
+        };
+
+        Ok(Self {
+            bindings: LitBindings::new(gl, &program)?,
+            lights,
+            specular,
+            material_color,
+            program,
+        })
+    }
+
+    /**
+     * Uploads the scene's lights, call after `use_program`. `view_matrix` places world space lights relative to the camera.
+     *
+     * With the uniform buffer only the view is uploaded, the lights themselves were uploaded once for every program.
+     */
+    pub fn set_lighting(&self, gl: &Gl, lighting: &Lighting, view_matrix: &Matrix4<f32>) {
+        match &self.lights {
+            LightBindings::UniformBuffer(bindings) => bindings.view.set(gl, view_matrix),
+            LightBindings::Uniforms(bindings) => {
+                bindings.ambient_light_color.set(gl, &lighting.ambient);
+                bindings.light_count.set(gl, &(lighting.lights.len() as i32));
+                bindings.light_position.set(gl, &lighting.view_positions(view_matrix));
+                bindings.light_color.set(gl, &lighting.colors());
+            }
+        }
+    }
+
+    /**
+     * Uploads the model view, model view projection and normal matrices, call after `use_program`.
+     */
+    pub fn set_transforms(&self, gl: &Gl, model_view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        self.bindings.model_view.set(gl, model_view);
+        self.bindings.model_view_projection.set(gl, &(projection * model_view));
+        self.bindings.normal_matrix.set(gl, &math::get_normal_matrix(model_view));
+    }
+
+    /**
+     * Uploads a material, call after `use_program`.
+     */
+    pub fn set_material(&self, gl: &Gl, material: &Material) {
+        let bindings = &self.bindings;
+        bindings.emissive.set(gl, &material.emissive);
+        bindings.ambient.set(gl, &material.ambient);
+        bindings.diffuse.set(gl, &material.diffuse);
+        bindings.opacity.set(gl, &material.opacity);
+        if let Some(specular) = &self.specular {
+            specular.specular.set(gl, &material.specular);
+            specular.shininess.set(gl, &material.shininess);
+        }
+        if let Some(color) = &self.material_color {
+            color.material_color.set(gl, &material.color);
+        }
+    }
+
+    /**
+     * Uploads a material which ignores the lights and shows `material.wireframe_color` as is.
+     */
+    pub fn set_wireframe_material(&self, gl: &Gl, material: &Material) {
+        self.set_material(gl, &Material {
+            ambient: 0.,
+            diffuse: 0.,
+            specular: 0.,
+            emissive: material.wireframe_color,
+            ..*material
+        });
+    }
+
+    /**
+     * Deletes the linked program, before the shader is replaced or its owner removed.
+     */
+    pub fn release(&self, gl: &Gl) {
+        gl.delete_program(Some(&self.program));
+    }
 }
@@ -0,0 +1,193 @@
+use crate::util::constants::*;
+use crate::util::curve::CurveFunction;
+use crate::util::math;
+use crate::util::webgl;
+use crate::util::gpu_buffer::GpuBuffer;
+use crate::util::material::Material;
+use crate::app_state::AppState;
+use crate::error::MVisualError;
+use crate::scene::Scene;
+use super::common::Program;
+use crate::util::bindings::{Attribute, Uniform};
+use crate::util::bounds::Bounds;
+use crate::util::colormap::ColorMap;
+use crate::util::gl::Gl;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use nalgebra::{Matrix4, Perspective3, Vector3, Vector4};
+
+crate::bindings! {
+    struct CurveBindings {
+        mvp_transform: Uniform<Matrix4<f32>> = "uModelViewProjection",
+        opacity: Uniform<f32> = "uOpacity",
+        vertex_position: Attribute<[f32; 3]> = "aVertexPosition",
+        vertex_color: Attribute<[f32; 3]> = "aVertexColor",
+    }
+}
+
+/*
+A parametric curve drawn as an unlit line strip, in the material's color.
+*/
+pub struct Curve3D {
+    program: WebGlProgram,
+    bindings: CurveBindings,
+    // Kept to sample again each frame and to rebuild the buffer after a context loss
+    function: CurveFunction,
+    positions: Vec<f32>,
+    // Model space bounds of `positions`, `None` when no sample is defined
+    bounds: Option<Bounds>,
+    // Identity unless the curve was stretched with `set_scale`
+    model_transform: Matrix4<f32>,
+    // Data
+    buf_vertex_position: GpuBuffer<f32>,
+    // Settings
+    material: Material,
+    // Added on top of the material while selected
+    highlight: Option<[f32; 3]>,
+}
+
+impl Curve3D {
+    /**
+     * The curve sampled at time `t`. When it depends on t it is sampled again on every `animate`.
+     */
+    pub fn new(gl: &Gl, function: CurveFunction, t: f32) -> Result<Self, MVisualError> {
+        let positions = function.positions(t);
+        Self::from_positions(gl, function, positions)
+    }
+
+    fn from_positions(gl: &Gl, function: CurveFunction, positions: Vec<f32>) -> Result<Self, MVisualError> {
+        let program = webgl::link_program(
+            gl,
+            crate::shaders::vertex::axes_3d::SHADER,
+            crate::shaders::fragment::vary_color_from_vertex::SHADER,
+        )?;
+
+        let usage = if function.is_animated() { GL::DYNAMIC_DRAW } else { GL::STATIC_DRAW };
+        let vertex_gpu_buffer = GpuBuffer::new(gl, GL::ARRAY_BUFFER, &positions, usage)?;
+
+        Ok(Self {
+            // Do everything that needs &program first
+            bindings: CurveBindings::new(gl, &program)?,
+            // Transfer program owner ship and finish
+            program,
+            buf_vertex_position: vertex_gpu_buffer,
+            model_transform: Matrix4::identity(),
+            material: Material::default(),
+            highlight: None,
+            // Data
+            bounds: Bounds::from_positions(&positions),
+            function,
+            positions,
+        })
+    }
+}
+
+impl Program for Curve3D {
+    fn render(
+        &self,
+        gl: &Gl,
+        app_state: &AppState,
+        _scene: &Scene,
+    ) {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        let aspect_ratio = app_state.canvas_width / app_state.canvas_height;
+        let projection_matrix = Perspective3::new(
+            aspect_ratio,
+            FIELD_OF_VIEW,
+            Z_NEAR,
+            Z_FAR,
+        );
+
+        gl.use_program(Some(&self.program));
+
+        self.buf_vertex_position.bind(gl);
+        self.bindings.vertex_position.point(gl, 0, 0);
+
+        // One color for the whole line, so it is a constant attribute rather than a buffer. Unlit, so a highlight just adds to it
+        let color = self.bindings.vertex_color.location();
+        let [r, g, b] = self.material.color;
+        let [tint_r, tint_g, tint_b] = self.highlight.unwrap_or([0., 0., 0.]);
+        gl.disable_vertex_attrib_array(color);
+        gl.vertex_attrib3f(color, r + tint_r, g + tint_g, b + tint_b);
+
+        self.bindings.opacity.set(gl, &self.material.opacity);
+        self.bindings.mvp_transform.set(gl, &(projection_matrix.as_matrix() * view_matrix * self.model_transform));
+
+        gl.draw_arrays(GL::LINE_STRIP, 0, (self.buf_vertex_position.len() / 3) as i32);
+    }
+
+    fn restore(&mut self, gl: &Gl, _color_map: &ColorMap) -> Result<(), MVisualError> {
+        let restored = Self::from_positions(gl, self.function.clone(), std::mem::take(&mut self.positions))?;
+        *self = Self {
+            model_transform: self.model_transform,
+            material: self.material,
+            highlight: self.highlight,
+            ..restored
+        };
+        Ok(())
+    }
+
+    fn release(&mut self, gl: &Gl) {
+        gl.delete_program(Some(&self.program));
+    }
+
+    fn animate(&mut self, gl: &Gl, time: f32) -> Result<(), MVisualError> {
+        if self.function.is_animated() {
+            self.positions = self.function.positions(time);
+            self.bounds = Bounds::from_positions(&self.positions);
+            self.buf_vertex_position.set(gl, &self.positions);
+        }
+        Ok(())
+    }
+
+    fn material(&self) -> Option<Material> {
+        Some(self.material)
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    fn set_highlight(&mut self, tint: Option<[f32; 3]>) {
+        self.highlight = tint;
+    }
+
+    fn set_scale(&mut self, scale: Vector3<f32>) {
+        self.model_transform = Matrix4::new_nonuniform_scaling(&scale);
+    }
+
+    fn is_scalable(&self) -> bool {
+        true
+    }
+
+    fn is_transparent(&self) -> bool {
+        self.material.opacity < 1.
+    }
+
+    fn view_depth(&self, app_state: &AppState) -> f32 {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        // The middle sample stands in for the whole curve
+        let middle = 3 * (self.positions.len() / 6);
+        let center = Vector4::new(
+            self.positions[middle],
+            self.positions[middle + 1],
+            self.positions[middle + 2],
+            1.,
+        );
+        -(view_matrix * self.model_transform * center).z
+    }
+
+    fn view_bounds(&self, app_state: &AppState) -> Option<Bounds> {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        self.bounds.map(|bounds| bounds.transformed(&(view_matrix * self.model_transform)))
+    }
+}
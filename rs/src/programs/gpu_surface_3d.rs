@@ -0,0 +1,391 @@
+use crate::util::bounds::Bounds;
+use crate::util::colormap::ColorMap;
+use crate::util::surface::*;
+use crate::util::constants::*;
+use crate::util::math;
+use crate::util::webgl;
+use crate::util::gpu_buffer::GpuBuffer;
+use crate::util::lod::GridLevels;
+use crate::util::picking::{self, Bvh, PickHit, Ray};
+use crate::app_state::AppState;
+use crate::error::MVisualError;
+use crate::scene::Scene;
+use super::common::{Coloring, LitShader, Program, Shading};
+use crate::util::material::Material;
+use crate::util::bindings::{OptionalUniform, TextureUnit, Uniform};
+use crate::util::gl::Gl;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use nalgebra::{Matrix4, Perspective3, Vector3, Vector4};
+
+crate::bindings! {
+    struct GpuSurfaceBindings {
+        color_map: Uniform<TextureUnit> = "uColorMap",
+        x_range: Uniform<[f32; 2]> = "uXRange",
+        y_range: Uniform<[f32; 2]> = "uYRange",
+        time: Uniform<f32> = "uTime",
+        value_range: Uniform<[f32; 2]> = "uValueRange",
+        // Only with finite difference normals
+        grid_step: OptionalUniform<[f32; 2]> = "uGridStep",
+    }
+}
+
+/*
+A surface z = f(x, y, t) computed in the vertex shader. The vertex data is the flat grid from `get_position_grid_n_by_n`, uploaded once, so animating only changes a uniform and grids far denser than the CPU could sample every frame stay cheap.
+
+`Surface3D::from_function` draws the same surfaces from CPU samples.
+*/
+pub struct GpuSurface3D {
+    shader: LitShader,
+    bindings: GpuSurfaceBindings,
+    function: SurfaceFunction,
+    normals: Normals,
+    color_mode: ColorMode,
+    shading: Shading,
+    time: f32,
+    // Range of the values at the time the surface was made, sampled on the CPU
+    value_range: (f32, f32),
+    // Middle of the surface's bounds, for depth sorting
+    center: Vector4<f32>,
+    // Model space bounds of the CPU sample, only good for culling while the surface stands still
+    bounds: Option<Bounds>,
+    // Identity unless the surface was stretched with `set_scale`
+    model_transform: Matrix4<f32>,
+    // The color comes from the color map, `material.color` is unused
+    material: Material,
+    // Added on top of the material while selected
+    highlight: Option<[f32; 3]>,
+    // Data
+    tex_color_map: WebGlTexture,
+    buf_grid_position: GpuBuffer<f32>,
+    // u32 when the context supports it, else u16
+    // Indices at each level of detail, u32 when the context supports it, else u16
+    grid_levels: GridLevels,
+    // The surface at `time` sampled on the CPU, built on the first pick after the time last changed
+    picking: Option<(Surface, Bvh)>,
+    // Attribute bindings recorded once, `None` when vertex array objects aren't supported
+    vertex_array: Option<WebGlVertexArrayObject>,
+}
+
+impl GpuSurface3D {
+    /**
+     * `t` is the time the colors are fitted to, values later on outside of that range are clamped to the ends of the color map.
+     */
+    pub fn new(
+        gl: &Gl,
+        function: SurfaceFunction,
+        normals: Normals,
+        t: f32,
+        color_mode: ColorMode,
+        color_map: &ColorMap,
+    ) -> Result<Self, MVisualError> {
+        // One CPU sample finds the color range and the bounds
+        let surface = function.sample(t)?;
+        let (_, value_range) = normalize_values(surface.color_values(color_mode));
+        let (_, (z_min, z_max)) = normalize_values(&surface.heights);
+        let center = Vector4::new(
+            (function.x_range.0 + function.x_range.1) / 2.,
+            (function.y_range.0 + function.y_range.1) / 2.,
+            (z_min + z_max) / 2.,
+            1.,
+        );
+
+        let mut value = Self::with_shading(gl, function, normals, t, color_mode, color_map, Shading::default(), value_range, center)?;
+        value.bounds = Bounds::from_positions(&surface.vertices);
+        Ok(value)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn with_shading(
+        gl: &Gl,
+        function: SurfaceFunction,
+        normals: Normals,
+        time: f32,
+        color_mode: ColorMode,
+        color_map: &ColorMap,
+        shading: Shading,
+        value_range: (f32, f32),
+        center: Vector4<f32>,
+    ) -> Result<Self, MVisualError> {
+        let shader = Self::link(gl, &function, normals, color_mode, shading)?;
+
+        let n = function.resolution;
+        let (grid, _) = math::get_position_grid_n_by_n(n);
+        let grid_gpu_buffer = GpuBuffer::new(gl, GL::ARRAY_BUFFER, &grid, GL::STATIC_DRAW)?;
+
+        // `get_position_grid_n_by_n`'s own indices are u16 whatever the size, these have the same winding
+        let grid_levels = GridLevels::new(gl, n)?;
+
+        let color_map_texture = webgl::fill_color_map_texture(gl, None, color_map)?;
+
+        let mut value = Self {
+            // Do everything that needs &program first
+            bindings: GpuSurfaceBindings::new(gl, &shader.program)?,
+            // Transfer program owner ship and finish
+            shader,
+            material: Material::default(),
+            highlight: None,
+            tex_color_map: color_map_texture,
+            buf_grid_position: grid_gpu_buffer,
+            grid_levels,
+            picking: None,
+            vertex_array: None,
+            // Data
+            value_range,
+            center,
+            bounds: None,
+            model_transform: Matrix4::identity(),
+            function,
+            normals,
+            color_mode,
+            shading,
+            time,
+        };
+        value.record_vertex_array(gl);
+
+        Ok(value)
+    }
+
+    /*
+    Points the shader's grid position at the buffer.
+    */
+    fn bind_attributes(&self, gl: &Gl) {
+        self.buf_grid_position.bind(gl);
+        self.shader.bindings.vertex_position.point(gl, 0, 0);
+    }
+
+    /*
+    Captures `bind_attributes` in a vertex array object, has to be redone whenever the shader (and so the attribute locations) changes.
+    */
+    fn record_vertex_array(&mut self, gl: &Gl) {
+        gl.delete_vertex_array(self.vertex_array.as_ref());
+        self.vertex_array = gl.create_vertex_array();
+        if let Some(vertex_array) = &self.vertex_array {
+            gl.bind_vertex_array(Some(vertex_array));
+            self.bind_attributes(gl);
+            gl.bind_vertex_array(None);
+        }
+    }
+
+    fn link(
+        gl: &Gl,
+        function: &SurfaceFunction,
+        normals: Normals,
+        color_mode: ColorMode,
+        shading: Shading,
+    ) -> Result<LitShader, MVisualError> {
+        let mut defines = function.glsl_defines(normals);
+        if color_mode == ColorMode::Slope {
+            defines.push(("COLOR_BY_SLOPE", String::from("1")));
+        }
+        let defines: Vec<(&str, &str)> = defines.iter().map(|(name, value)| (*name, value.as_str())).collect();
+
+        LitShader::with_defines(
+            gl,
+            shading,
+            Coloring::ColorMap,
+            (
+                crate::shaders::vertex::surface_3d_gpu::SHADER,
+                crate::shaders::fragment::color_map_lit::SHADER,
+            ),
+            (
+                crate::shaders::vertex::surface_3d_gpu_phong::SHADER,
+                crate::shaders::fragment::color_map_blinn_phong::SHADER,
+            ),
+            &defines,
+        )
+    }
+
+    /**
+     * The (min, max) of the values the surface is colored by, for the colorbar.
+     */
+    pub fn value_range(&self) -> (f32, f32) {
+        self.value_range
+    }
+}
+
+impl Program for GpuSurface3D {
+    fn render(
+        &self,
+        gl: &Gl,
+        app_state: &AppState,
+        scene: &Scene,
+    ) {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        let aspect_ratio = app_state.canvas_width / app_state.canvas_height;
+        let projection_matrix = Perspective3::new(
+            aspect_ratio,
+            FIELD_OF_VIEW,
+            Z_NEAR,
+            Z_FAR,
+        );
+
+        gl.use_program(Some(&self.shader.program));
+
+        match &self.vertex_array {
+            Some(vertex_array) => gl.bind_vertex_array(Some(vertex_array)),
+            None => self.bind_attributes(gl),
+        }
+
+        gl.active_texture(GL::TEXTURE0);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.tex_color_map));
+        self.bindings.color_map.set(gl, &TextureUnit(0));
+
+        // Where the surface is, and when
+        let function = &self.function;
+        self.bindings.x_range.set(gl, &[function.x_range.0, function.x_range.1]);
+        self.bindings.y_range.set(gl, &[function.y_range.0, function.y_range.1]);
+        self.bindings.time.set(gl, &self.time);
+        self.bindings.value_range.set(gl, &[self.value_range.0, self.value_range.1]);
+        self.bindings.grid_step.set(gl, &function.grid_step());
+
+        // Universal settings
+        self.shader.set_lighting(gl, &scene.lighting, &view_matrix);
+        self.shader.set_material(gl, &self.material.tinted(self.highlight));
+
+        let mv_matrix = view_matrix * self.model_transform;
+        self.shader.set_transforms(gl, &mv_matrix, projection_matrix.as_matrix());
+
+        // Both sides of the surface can be seen
+        gl.disable(GL::CULL_FACE);
+        // Bound here rather than in the vertex array, the level changes with the surface's size on screen
+        let indices = self.grid_levels.indices();
+        indices.bind(gl);
+        gl.draw_elements_with_i32(GL::TRIANGLES, indices.len() as i32, indices.gl_type(), 0);
+        gl.enable(GL::CULL_FACE);
+
+        // Leave the default attribute state to the programs which don't use vertex arrays
+        gl.bind_vertex_array(None);
+    }
+
+    fn set_color_map(&mut self, gl: &Gl, color_map: &ColorMap) -> Result<(), MVisualError> {
+        webgl::fill_color_map_texture(gl, Some(self.tex_color_map.clone()), color_map)?;
+        Ok(())
+    }
+
+    fn set_shading(&mut self, gl: &Gl, shading: Shading) -> Result<(), MVisualError> {
+        // Look everything up before replacing anything, so a failure leaves the old program working
+        let shader = Self::link(gl, &self.function, self.normals, self.color_mode, shading)?;
+        self.bindings = GpuSurfaceBindings::new(gl, &shader.program).inspect_err(|_| shader.release(gl))?;
+        self.shader.release(gl);
+        self.shader = shader;
+        self.shading = shading;
+        self.record_vertex_array(gl);
+        Ok(())
+    }
+
+    fn restore(&mut self, gl: &Gl, color_map: &ColorMap) -> Result<(), MVisualError> {
+        let restored = Self::with_shading(
+            gl,
+            self.function.clone(),
+            self.normals,
+            self.time,
+            self.color_mode,
+            color_map,
+            self.shading,
+            self.value_range,
+            self.center,
+        )?;
+        *self = Self {
+            bounds: self.bounds,
+            model_transform: self.model_transform,
+            material: self.material,
+            highlight: self.highlight,
+            ..restored
+        };
+        Ok(())
+    }
+
+    fn release(&mut self, gl: &Gl) {
+        self.shader.release(gl);
+        gl.delete_vertex_array(self.vertex_array.take().as_ref());
+        gl.delete_texture(Some(&self.tex_color_map));
+    }
+
+    fn animate(&mut self, _gl: &Gl, time: f32) -> Result<(), MVisualError> {
+        // Nothing to upload, the shader reads the time as a uniform
+        self.time = time;
+        if self.function.is_animated() {
+            self.picking = None;
+        }
+        Ok(())
+    }
+
+    fn material(&self) -> Option<Material> {
+        Some(self.material)
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    fn legend_color(&self) -> Option<[f32; 3]> {
+        // Colored by the color map, which the colorbar explains
+        None
+    }
+
+    fn set_highlight(&mut self, tint: Option<[f32; 3]>) {
+        self.highlight = tint;
+    }
+
+    fn set_scale(&mut self, scale: Vector3<f32>) {
+        self.model_transform = Matrix4::new_nonuniform_scaling(&scale);
+    }
+
+    fn is_scalable(&self) -> bool {
+        true
+    }
+
+    fn is_transparent(&self) -> bool {
+        self.material.opacity < 1.
+    }
+
+    fn view_depth(&self, app_state: &AppState) -> f32 {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        -(view_matrix * self.model_transform * self.center).z
+    }
+
+    fn view_bounds(&self, app_state: &AppState) -> Option<Bounds> {
+        // Where an animated surface is now is only known to the GPU, so it is always drawn
+        if self.function.is_animated() {
+            return None;
+        }
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        self.bounds.map(|bounds| bounds.transformed(&(view_matrix * self.model_transform)))
+    }
+
+    fn select_detail(&mut self, app_state: &AppState) {
+        // The CPU sample's bounds are close enough to size an animated surface by, even if not to cull it
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        let bounds = self.bounds.map(|bounds| bounds.transformed(&(view_matrix * self.model_transform)));
+        self.grid_levels.select(bounds, app_state.canvas_height);
+    }
+
+    fn pick(&mut self, ray: &Ray, app_state: &AppState) -> Option<PickHit> {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        let ray = ray.transformed(&(view_matrix * self.model_transform).try_inverse()?);
+        if self.picking.is_none() {
+            // The same grid and heights the shader computes, as long as the GPU's float math agrees closely enough
+            let surface = self.function.sample(self.time).ok()?;
+            let bvh = Bvh::new(&surface.vertices, &surface.indices);
+            self.picking = Some((surface, bvh));
+        }
+        let (surface, bvh) = self.picking.as_ref()?;
+        picking::pick_surface(surface, bvh, &ray, self.color_mode)
+    }
+}
@@ -3,85 +3,78 @@ use crate::util::webgl;
 use crate::util::constants::GRID_SIZE;
 //use crate::log;
 use crate::app_state::AppState;
+use crate::error::MVisualError;
+use crate::scene::Scene;
 use super::common::Program;
-use js_sys::WebAssembly;
-use wasm_bindgen::JsCast;
+use crate::util::bindings::{Attribute, Uniform};
+use crate::util::gpu_buffer::GpuBuffer;
+use nalgebra::Matrix4;
+use crate::util::colormap::ColorMap;
+use crate::util::gl::Gl;
 use web_sys::WebGlRenderingContext as GL;
 use web_sys::*;
 
+crate::bindings! {
+    #[allow(dead_code)]
+    pub struct GraphBindings {
+        pub opacity: Uniform<f32> = "uOpacity",
+        pub projection: Uniform<Matrix4<f32>> = "uProjection",
+        pub position: Attribute<[f32; 4]> = "aPosition",
+    }
+}
+
 #[allow(dead_code)]
 pub struct Graph3D {
     pub program: WebGlProgram,
     pub vertices: Vec<f32>,
     pub indices: Vec<u16>,
-    pub indices_buffer: WebGlBuffer,
-    pub index_count: i32,
-    pub position_buffer: WebGlBuffer,
-    pub u_opacity: WebGlUniformLocation,
-    pub u_projection: WebGlUniformLocation,
+    pub indices_buffer: GpuBuffer<u16>,
+    pub position_buffer: GpuBuffer<f32>,
+    pub bindings: GraphBindings,
 }
 
 #[allow(dead_code)]
 impl Graph3D {
-    pub fn new(gl: &WebGlRenderingContext) -> Self {
+    pub fn new(gl: &Gl) -> Result<Self, MVisualError> {
         let program = webgl::link_program(
-            &gl,
-            &crate::shaders::vertex::graph_3d::SHADER,
-            &crate::shaders::fragment::vary_color_from_vertex::SHADER,
-        )
-        .unwrap();
+            gl,
+            crate::shaders::vertex::graph_3d::SHADER,
+            crate::shaders::fragment::vary_color_from_vertex::SHADER,
+        )?;
 
         let positions_and_indices = math::get_position_grid_n_by_n(GRID_SIZE);
 
         // Bind data so webGL can use it
-        let memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        let vertices_location = positions_and_indices.0.as_ptr() as u32 / 4;
-        let vert_array = js_sys::Float32Array::new(&memory_buffer).subarray(
-            vertices_location,
-            vertices_location + positions_and_indices.0.len() as u32,
-        );
-        let buffer_position = gl.create_buffer().ok_or("Failed to create buffer").unwrap();
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&buffer_position));
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &vert_array, GL::STATIC_DRAW);
-
-        let indices_memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        let indices_location = positions_and_indices.1.as_ptr() as u32 / 2;
-        let indices_array = js_sys::Uint16Array::new(&indices_memory_buffer).subarray(
-            indices_location,
-            indices_location + positions_and_indices.1.len() as u32,
-        );
-        let buffer_indices = gl.create_buffer().ok_or("Failed to create buffer").unwrap();
-        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&buffer_indices));
-        gl.buffer_data_with_array_buffer_view(
+        let buffer_position = GpuBuffer::new(
+            gl,
+            GL::ARRAY_BUFFER,
+            &positions_and_indices.0,
+            GL::STATIC_DRAW,
+        )?;
+        let buffer_indices = GpuBuffer::new(
+            gl,
             GL::ELEMENT_ARRAY_BUFFER,
-            &indices_array,
+            &positions_and_indices.1,
             GL::STATIC_DRAW,
-        );
+        )?;
 
-        Self {
-            u_opacity: gl.get_uniform_location(&program, "uOpacity").unwrap(),
-            u_projection: gl.get_uniform_location(&program, "uProjection").unwrap(),
-            program: program,
+        Ok(Self {
+            bindings: GraphBindings::new(gl, &program)?,
+            program,
             vertices: positions_and_indices.0,
             indices: positions_and_indices.1,
             indices_buffer: buffer_indices,
-            index_count: indices_array.length() as i32,
             position_buffer: buffer_position,
-        }
+        })
     }
 }
 
 impl Program for Graph3D {
     fn render(
         &self,
-        gl: &WebGlRenderingContext,
+        gl: &Gl,
         app_state: &AppState,
+        _scene: &Scene,
     ) {
         gl.use_program(Some(&self.program));
 
@@ -97,20 +90,32 @@ impl Program for Graph3D {
             app_state.rotation_y_axis,
         );
 
-        gl.uniform_matrix4fv_with_f32_array(Some(&self.u_projection), false, &projection_matrix.as_slice());
-        gl.uniform1f(Some(&self.u_opacity), 1.);
+        self.bindings.projection.set(gl, &projection_matrix);
+        self.bindings.opacity.set(gl, &1.);
 
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.position_buffer));
-        gl.vertex_attrib_pointer_with_i32(0, 3, GL::FLOAT, false, 0, 0);
-        gl.enable_vertex_attrib_array(0);
+        // Only x, y, z are stored, w is filled in as 1
+        let position = self.bindings.position.location();
+        self.position_buffer.bind(gl);
+        gl.vertex_attrib_pointer_with_i32(position, 3, GL::FLOAT, false, 0, 0);
+        gl.enable_vertex_attrib_array(position);
 
         /*
         // For debugging to add a visual indicator of a specific point
-        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self.indices_buffer));
+        self.indices_buffer.bind(gl);
         gl.draw_elements_with_i32(GL::POINTS, 1, GL::UNSIGNED_SHORT, 0);
         */
 
-        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self.indices_buffer));
-        gl.draw_elements_with_i32(GL::TRIANGLES, self.index_count, GL::UNSIGNED_SHORT, 0)
+        self.indices_buffer.bind(gl);
+        gl.draw_elements_with_i32(GL::TRIANGLES, self.indices_buffer.len() as i32, GL::UNSIGNED_SHORT, 0)
+    }
+
+    fn restore(&mut self, gl: &Gl, _color_map: &ColorMap) -> Result<(), MVisualError> {
+        // The grid is the same every time, so it is simply built again
+        *self = Self::new(gl)?;
+        Ok(())
+    }
+
+    fn release(&mut self, gl: &Gl) {
+        gl.delete_program(Some(&self.program));
     }
 }
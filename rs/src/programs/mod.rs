@@ -1,8 +1,27 @@
 pub mod common;
 pub use common::*;
 
+pub mod axes_3d;
+pub use axes_3d::*;
+
+pub mod curve_3d;
+pub use curve_3d::*;
+
+pub mod overlay_2d;
+pub use overlay_2d::*;
+
+pub mod gpu_surface_3d;
+pub use gpu_surface_3d::*;
+
 pub mod graph_3d;
+#[allow(unused_imports)]
 pub use graph_3d::*;
 
 pub mod sphere_3d;
-pub use sphere_3d::*;
\ No newline at end of file
+pub use sphere_3d::*;
+
+pub mod surface_3d;
+pub use surface_3d::*;
+
+pub mod text_3d;
+pub use text_3d::*;
\ No newline at end of file
@@ -0,0 +1,481 @@
+use crate::util::bindings::{Attribute, Uniform};
+use crate::util::colormap::ColorMap;
+use crate::util::ticks::{format_tick, nice_ticks};
+use crate::util::webgl;
+use crate::util::perf::FRAME_TIME_BUCKETS;
+use crate::util::gpu_buffer::{GpuBuffer, VertexLayout};
+use crate::app_state::AppState;
+use crate::error::MVisualError;
+use crate::scene::Scene;
+use super::common::Program;
+use super::text_3d::{Anchor, Label, TextAlign};
+use crate::util::gl::Gl;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+
+// Layout, all in pixels
+const MARGIN: f32 = 16.;
+const GAP: f32 = 4.;
+const TEXT_SIZE: f32 = 13.;
+const TITLE_SIZE: f32 = 14.;
+// Room left for the text next to the colorbar / legend swatches, labels are not measured ahead of time
+const COLORBAR_LABEL_WIDTH: f32 = 48.;
+const LEGEND_LABEL_WIDTH: f32 = 100.;
+
+const BAR_WIDTH: f32 = 16.;
+// The colorbar takes up this much of the canvas height, but never less than `MIN_BAR_HEIGHT`
+const BAR_HEIGHT_FRACTION: f32 = 0.4;
+const MIN_BAR_HEIGHT: f32 = 80.;
+const BAR_SEGMENTS: usize = 64;
+const TICK_LENGTH: f32 = 5.;
+
+const SWATCH_SIZE: f32 = 12.;
+const LEGEND_ROW_HEIGHT: f32 = 18.;
+
+const HUD_WIDTH: f32 = 280.;
+const HUD_PADDING: f32 = 6.;
+const HUD_HISTOGRAM_HEIGHT: f32 = 40.;
+const HUD_BUCKET_TEXT_SIZE: f32 = 10.;
+const HUD_BACKGROUND_COLOR: [f32; 3] = [0.08, 0.08, 0.1];
+// Histogram bars by how their frames would feel: smooth, acceptable, janky
+const HUD_FAST_COLOR: [f32; 3] = [0.3, 0.8, 0.4];
+const HUD_MEDIUM_COLOR: [f32; 3] = [0.9, 0.75, 0.25];
+const HUD_SLOW_COLOR: [f32; 3] = [0.9, 0.3, 0.25];
+
+const TEXT_COLOR: [f32; 3] = [0.9, 0.9, 0.9];
+const OUTLINE_COLOR: [f32; 3] = [0.9, 0.9, 0.9];
+
+// Overlay text always wins over labels in the scene
+const OVERLAY_LABEL_PRIORITY: i32 = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /**
+     * Parses names like "top-left" or "bottom-right".
+     */
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top-left" => Some(Corner::TopLeft),
+            "top-right" => Some(Corner::TopRight),
+            "bottom-left" => Some(Corner::BottomLeft),
+            "bottom-right" => Some(Corner::BottomRight),
+            _ => None,
+        }
+    }
+
+    fn is_left(&self) -> bool {
+        matches!(self, Corner::TopLeft | Corner::BottomLeft)
+    }
+
+    fn is_bottom(&self) -> bool {
+        matches!(self, Corner::BottomLeft | Corner::BottomRight)
+    }
+
+    /*
+    Bottom left corner of a `width` by `height` block pushed into this corner of the canvas. `inset` moves the block horizontally away from the edge.
+    */
+    fn place(&self, width: f32, height: f32, inset: f32, app_state: &AppState) -> (f32, f32) {
+        let left = if self.is_left() {
+            MARGIN + inset
+        } else {
+            app_state.canvas_width - MARGIN - inset - width
+        };
+        let bottom = if self.is_bottom() {
+            MARGIN
+        } else {
+            app_state.canvas_height - MARGIN - height
+        };
+
+        (left, bottom)
+    }
+}
+
+pub struct Colorbar {
+    pub color_map: ColorMap,
+    // Values at the bottom and top of the bar
+    pub min: f32,
+    pub max: f32,
+    pub title: String,
+    pub corner: Corner,
+}
+
+pub struct LegendEntry {
+    pub name: String,
+    pub color: [f32; 3],
+}
+
+/*
+Frame stats, filled in by the caller each frame before the overlay is drawn.
+*/
+pub struct Hud {
+    pub corner: Corner,
+    pub lines: Vec<String>,
+    // Share of frames in each bucket of `FRAME_TIME_BUCKETS` and the one after it
+    pub histogram: Vec<f32>,
+}
+
+/*
+Shapes (in pixels) and text for one frame of the overlay.
+*/
+struct Layout {
+    vertices: Vec<f32>,
+    labels: Vec<Label>,
+}
+
+impl Layout {
+    fn push_quad(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, bottom_color: [f32; 3], top_color: [f32; 3]) {
+        // Counter clockwise so the quads survive culling
+        for (x, y, color) in [
+            (x0, y0, bottom_color),
+            (x1, y0, bottom_color),
+            (x1, y1, top_color),
+            (x0, y0, bottom_color),
+            (x1, y1, top_color),
+            (x0, y1, top_color),
+        ] {
+            self.vertices.extend_from_slice(&[x, y]);
+            self.vertices.extend_from_slice(&color);
+        }
+    }
+
+    fn push_label(&mut self, text: &str, x: f32, y: f32, size: f32, align: TextAlign) -> &mut Label {
+        let mut label = Label::new(text, Anchor::Screen(x, y));
+        label.size = size;
+        label.color = TEXT_COLOR;
+        label.align = align;
+        label.priority = OVERLAY_LABEL_PRIORITY;
+        self.labels.push(label);
+        self.labels.last_mut().unwrap()
+    }
+}
+
+crate::bindings! {
+    struct OverlayBindings {
+        resolution: Uniform<[f32; 2]> = "uResolution",
+        opacity: Uniform<f32> = "uOpacity",
+        vertex_position: Attribute<[f32; 2]> = "aVertexPosition",
+        vertex_color: Attribute<[f32; 3]> = "aVertexColor",
+    }
+}
+
+/*
+Screen space overlay with a colorbar for the active color map, a legend of named objects and a HUD of frame stats. All are anchored to a corner of the canvas and laid out again every frame so they follow the canvas size.
+
+Text is handed over to `Text3D` through `labels`.
+*/
+pub struct Overlay2D {
+    program: WebGlProgram,
+    bindings: OverlayBindings,
+    // Data
+    buf_vertices: GpuBuffer<f32>,
+    vertex_layout: VertexLayout,
+    // Settings
+    pub colorbar: Option<Colorbar>,
+    pub legend: Vec<LegendEntry>,
+    pub legend_corner: Corner,
+    pub hud: Option<Hud>,
+}
+
+impl Overlay2D {
+    pub fn new(gl: &Gl) -> Result<Self, MVisualError> {
+        let program = webgl::link_program(
+            gl,
+            crate::shaders::vertex::overlay_2d::SHADER,
+            crate::shaders::fragment::vary_color_from_vertex::SHADER,
+        )?;
+
+        // Refilled every frame
+        let vertex_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ARRAY_BUFFER,
+            &[],
+            GL::DYNAMIC_DRAW
+        )?;
+
+        Ok(Self {
+            // Do everything that needs &program first
+            bindings: OverlayBindings::new(gl, &program)?,
+            // Transfer program owner ship and finish
+            program,
+            buf_vertices: vertex_gpu_buffer,
+            vertex_layout: Self::vertex_layout(),
+            // Settings
+            colorbar: None,
+            legend: Vec::new(),
+            legend_corner: Corner::TopLeft,
+            hud: None,
+        })
+    }
+
+    /*
+    Vertices are interleaved, all fields are floats.
+    */
+    fn vertex_layout() -> VertexLayout {
+        VertexLayout::new()
+            .field::<f32>(2) // x, y
+            .field::<f32>(3) // r, g, b
+    }
+
+    /**
+     * Text for the current frame, positioned in pixels.
+     */
+    pub fn labels(&self, app_state: &AppState) -> Vec<Label> {
+        self.layout(app_state).labels
+    }
+
+    fn layout(&self, app_state: &AppState) -> Layout {
+        let mut layout = Layout {
+            vertices: Vec::new(),
+            labels: Vec::new(),
+        };
+
+        let mut colorbar_width = 0.;
+        if let Some(colorbar) = &self.colorbar {
+            colorbar_width = self.layout_colorbar(&mut layout, colorbar, app_state);
+        }
+
+        // Sit beside the colorbar when sharing a corner with it
+        let inset_from_colorbar = |corner: Corner| match &self.colorbar {
+            Some(colorbar) if colorbar.corner == corner => colorbar_width + MARGIN,
+            _ => 0.,
+        };
+
+        let mut legend_width = 0.;
+        if !self.legend.is_empty() {
+            legend_width = self.layout_legend(&mut layout, inset_from_colorbar(self.legend_corner), app_state);
+        }
+
+        if let Some(hud) = &self.hud {
+            let mut inset = inset_from_colorbar(hud.corner);
+            if !self.legend.is_empty() && self.legend_corner == hud.corner {
+                inset += legend_width + MARGIN;
+            }
+            self.layout_hud(&mut layout, hud, inset, app_state);
+        }
+
+        layout
+    }
+
+    /*
+    Returns the width taken up by the colorbar and its labels.
+    */
+    fn layout_colorbar(&self, layout: &mut Layout, colorbar: &Colorbar, app_state: &AppState) -> f32 {
+        let bar_height = (app_state.canvas_height * BAR_HEIGHT_FRACTION).max(MIN_BAR_HEIGHT);
+        let width = BAR_WIDTH + TICK_LENGTH + GAP + COLORBAR_LABEL_WIDTH;
+        // Half a line of text is left below the bar so the min label fits
+        let height = TEXT_SIZE / 2. + bar_height + GAP + TITLE_SIZE;
+        let corner = colorbar.corner;
+        let (left, bottom) = corner.place(width, height, 0., app_state);
+
+        // The bar hugs the edge of the canvas and the labels face inwards
+        let bar_left = if corner.is_left() { left } else { left + width - BAR_WIDTH };
+        let bar_right = bar_left + BAR_WIDTH;
+        let bar_bottom = bottom + TEXT_SIZE / 2.;
+        let bar_top = bar_bottom + bar_height;
+
+        // Gradient
+        for i in 0..BAR_SEGMENTS {
+            let t0 = i as f32 / BAR_SEGMENTS as f32;
+            let t1 = (i + 1) as f32 / BAR_SEGMENTS as f32;
+            layout.push_quad(
+                bar_left,
+                bar_bottom + t0 * bar_height,
+                bar_right,
+                bar_bottom + t1 * bar_height,
+                colorbar.color_map.sample(t0),
+                colorbar.color_map.sample(t1),
+            );
+        }
+
+        // Outline
+        layout.push_quad(bar_left - 1., bar_bottom - 1., bar_right + 1., bar_bottom, OUTLINE_COLOR, OUTLINE_COLOR);
+        layout.push_quad(bar_left - 1., bar_top, bar_right + 1., bar_top + 1., OUTLINE_COLOR, OUTLINE_COLOR);
+        layout.push_quad(bar_left - 1., bar_bottom, bar_left, bar_top, OUTLINE_COLOR, OUTLINE_COLOR);
+        layout.push_quad(bar_right, bar_bottom, bar_right + 1., bar_top, OUTLINE_COLOR, OUTLINE_COLOR);
+
+        // Ticks and their labels
+        let (tick_start, tick_end, label_x, align) = if corner.is_left() {
+            (bar_right, bar_right + TICK_LENGTH, bar_right + TICK_LENGTH + GAP, TextAlign::Left)
+        } else {
+            (bar_left - TICK_LENGTH, bar_left, bar_left - TICK_LENGTH - GAP, TextAlign::Right)
+        };
+
+        let range = colorbar.max - colorbar.min;
+        let ticks = nice_ticks(colorbar.min, colorbar.max, 5);
+        let to_y = |value: f32| {
+            if range.abs() > f32::EPSILON {
+                bar_bottom + (value - colorbar.min) / range * bar_height
+            } else {
+                bar_bottom
+            }
+        };
+
+        // Min and max are kept over any tick which lands too close to them
+        layout.push_label(&format_tick(colorbar.min, ticks.step), label_x, bar_bottom, TEXT_SIZE, align).priority += 1;
+        layout.push_label(&format_tick(colorbar.max, ticks.step), label_x, bar_top, TEXT_SIZE, align).priority += 1;
+
+        for value in ticks.major {
+            let y = to_y(value);
+            layout.push_quad(tick_start, y - 0.5, tick_end, y + 0.5, OUTLINE_COLOR, OUTLINE_COLOR);
+            layout.push_label(&format_tick(value, ticks.step), label_x, y, TEXT_SIZE, align);
+        }
+
+        // Title, lined up with the outer edge of the bar
+        let (title_x, title_align) = if corner.is_left() {
+            (bar_left, TextAlign::Left)
+        } else {
+            (bar_right, TextAlign::Right)
+        };
+        layout.push_label(&colorbar.title, title_x, bar_top + GAP + TITLE_SIZE / 2., TITLE_SIZE, title_align);
+
+        width
+    }
+
+    /*
+    Returns the width taken up by the legend.
+    */
+    fn layout_legend(&self, layout: &mut Layout, inset: f32, app_state: &AppState) -> f32 {
+        let width = SWATCH_SIZE + GAP + LEGEND_LABEL_WIDTH;
+        let height = self.legend.len() as f32 * LEGEND_ROW_HEIGHT;
+        let (left, bottom) = self.legend_corner.place(width, height, inset, app_state);
+
+        // Swatches hug the edge of the canvas like the colorbar
+        let (swatch_left, label_x, align) = if self.legend_corner.is_left() {
+            (left, left + SWATCH_SIZE + GAP, TextAlign::Left)
+        } else {
+            (left + width - SWATCH_SIZE, left + width - SWATCH_SIZE - GAP, TextAlign::Right)
+        };
+
+        // First entry at the top
+        for (row, entry) in self.legend.iter().enumerate() {
+            let middle = bottom + height - (row as f32 + 0.5) * LEGEND_ROW_HEIGHT;
+            layout.push_quad(
+                swatch_left,
+                middle - SWATCH_SIZE / 2.,
+                swatch_left + SWATCH_SIZE,
+                middle + SWATCH_SIZE / 2.,
+                entry.color,
+                entry.color,
+            );
+            layout.push_label(&entry.name, label_x, middle, TEXT_SIZE, align);
+        }
+
+        width
+    }
+
+    /*
+    Lines of text above a histogram of frame times, on a dark panel so they stay readable over the scene.
+    */
+    fn layout_hud(&self, layout: &mut Layout, hud: &Hud, inset: f32, app_state: &AppState) {
+        let text_height = hud.lines.len() as f32 * LEGEND_ROW_HEIGHT;
+        let histogram_height = if hud.histogram.is_empty() {
+            0.
+        } else {
+            GAP + HUD_HISTOGRAM_HEIGHT + GAP + HUD_BUCKET_TEXT_SIZE
+        };
+        let height = text_height + histogram_height + 2. * HUD_PADDING;
+        let (left, bottom) = hud.corner.place(HUD_WIDTH, height, inset, app_state);
+        let top = bottom + height;
+
+        layout.push_quad(left, bottom, left + HUD_WIDTH, top, HUD_BACKGROUND_COLOR, HUD_BACKGROUND_COLOR);
+
+        let text_left = left + HUD_PADDING;
+        for (row, line) in hud.lines.iter().enumerate() {
+            let middle = top - HUD_PADDING - (row as f32 + 0.5) * LEGEND_ROW_HEIGHT;
+            layout.push_label(line, text_left, middle, TEXT_SIZE, TextAlign::Left);
+        }
+
+        if hud.histogram.is_empty() {
+            return;
+        }
+
+        let inner_width = HUD_WIDTH - 2. * HUD_PADDING;
+        let slot_width = inner_width / hud.histogram.len() as f32;
+        let label_y = bottom + HUD_PADDING + HUD_BUCKET_TEXT_SIZE / 2.;
+        let bars_bottom = bottom + HUD_PADDING + HUD_BUCKET_TEXT_SIZE + GAP;
+
+        // Baseline, so empty buckets still show where they are
+        layout.push_quad(text_left, bars_bottom - 1., text_left + inner_width, bars_bottom, OUTLINE_COLOR, OUTLINE_COLOR);
+
+        for (bucket, &fraction) in hud.histogram.iter().enumerate() {
+            let slot_left = text_left + bucket as f32 * slot_width;
+            let color = match FRAME_TIME_BUCKETS.get(bucket) {
+                Some(&edge) if edge <= 17. => HUD_FAST_COLOR,
+                Some(&edge) if edge <= 34. => HUD_MEDIUM_COLOR,
+                _ => HUD_SLOW_COLOR,
+            };
+            if fraction > 0. {
+                layout.push_quad(
+                    slot_left + 1.,
+                    bars_bottom,
+                    slot_left + slot_width - 1.,
+                    bars_bottom + fraction.min(1.) * HUD_HISTOGRAM_HEIGHT,
+                    color,
+                    color,
+                );
+            }
+
+            let name = match FRAME_TIME_BUCKETS.get(bucket) {
+                Some(edge) => format!("<{}", edge),
+                None => format!("{}+", FRAME_TIME_BUCKETS[FRAME_TIME_BUCKETS.len() - 1]),
+            };
+            layout.push_label(&name, slot_left + slot_width / 2., label_y, HUD_BUCKET_TEXT_SIZE, TextAlign::Center);
+        }
+    }
+}
+
+impl Program for Overlay2D {
+    fn render(
+        &self,
+        gl: &Gl,
+        app_state: &AppState,
+        _scene: &Scene,
+    ) {
+        let layout = self.layout(app_state);
+        if layout.vertices.is_empty() {
+            return;
+        }
+
+        gl.use_program(Some(&self.program));
+
+        self.buf_vertices.set(gl, &layout.vertices);
+
+        self.vertex_layout.point(gl, 0, self.bindings.vertex_position.location());
+        self.vertex_layout.point(gl, 1, self.bindings.vertex_color.location());
+
+        self.bindings.resolution.set(gl, &[app_state.canvas_width, app_state.canvas_height]);
+        self.bindings.opacity.set(gl, &1.);
+
+        // Overlays are always on top
+        gl.disable(GL::DEPTH_TEST);
+        gl.draw_arrays(GL::TRIANGLES, 0, self.vertex_layout.vertex_count(&self.buf_vertices));
+        gl.enable(GL::DEPTH_TEST);
+
+        // The buffer shrinks and grows with the overlay, don't leave other programs pointing at it
+        gl.disable_vertex_attrib_array(self.bindings.vertex_color.location());
+    }
+
+    fn restore(&mut self, gl: &Gl, _color_map: &ColorMap) -> Result<(), MVisualError> {
+        // Everything drawn is laid out again each frame, only the settings need keeping
+        let restored = Self::new(gl)?;
+        *self = Self {
+            colorbar: self.colorbar.take(),
+            legend: std::mem::take(&mut self.legend),
+            legend_corner: self.legend_corner,
+            hud: self.hud.take(),
+            ..restored
+        };
+        Ok(())
+    }
+
+    fn release(&mut self, gl: &Gl) {
+        gl.delete_program(Some(&self.program));
+    }
+}
@@ -1,52 +1,50 @@
 use crate::util::Sphere;
+use crate::util::bounds::{BoundingSphere, Bounds};
+use crate::util::lod::{self, LodSelector};
+use crate::util::picking::{self, PickHit, Ray};
 use crate::util::constants::*;
-use crate::util::webgl;
-use crate::util::wasm::*;
+use crate::util::gpu_buffer::GpuBuffer;
 //use crate::log;
 use crate::app_state::AppState;
-use super::common::Program;
+use crate::error::MVisualError;
+use crate::scene::Scene;
+use super::common::{Coloring, LitShader, Program, Shading};
+use crate::util::material::Material;
+use crate::util::bindings::Attribute;
+use crate::util::colormap::ColorMap;
+use crate::util::gl::Gl;
+use crate::util::math;
 use web_sys::WebGlRenderingContext as GL;
 use web_sys::*;
 use nalgebra::{Perspective3, Matrix4, Vector3};
 
-pub struct Sphere3D {
-  program: WebGlProgram,
-  _sphere: Sphere,
-  model_transform: Matrix4<f32>,
-  view_transform: Matrix4<f32>,
-  // Uniform locations
-  u_amb_light_color: WebGlUniformLocation, // TODO: Do these need to be set in render? (the lookup references the program...)
-  u_diff_light_color: WebGlUniformLocation,
-  u_diff_light_pos: WebGlUniformLocation,
-  u_material_color: WebGlUniformLocation,
-  u_mv_transform: WebGlUniformLocation,
-  u_mvp_transform: WebGlUniformLocation,
-  u_opacity: WebGlUniformLocation,
-  // Attribute locations
-  a_vertex_position: u32,
-  a_vertex_normal: u32,
-  // Data
-  buf_vertex_position: WebGlBuffer,
-  buf_vertex_normal: WebGlBuffer,
-  buf_wireframe_indices: WebGlBuffer,
-  len_wireframe_indices: i32,
-  _buf_face_indices: WebGlBuffer,
-  _len_face_indices: i32,
-  // Settings
-  wireframe: bool,
+// The wireframe is drawn this much bigger than the faces so it isn't hidden by them
+const WIREFRAME_SCALE: f32 = 1.001;
+
+// Resolutions (see `Sphere::new`) of the levels of detail, coarsest first
+const LOD_RESOLUTIONS: [u16; 5] = [6, 12, 20, 30, 48];
+// Levels are switched so the segments of the sphere's outline stay under this many pixels long
+const PIXELS_PER_SEGMENT: f32 = 12.;
+
+crate::bindings! {
+  struct SphereBindings {
+    instance_offset: Attribute<[f32; 3]> = "aInstanceOffset",
+  }
 }
 
-impl Sphere3D {
-  pub fn new(gl: &WebGlRenderingContext, wireframe: bool) -> Self {
-    let program = webgl::link_program(
-      &gl,
-      &crate::shaders::vertex::sphere_3d::SHADER,
-      &crate::shaders::fragment::vary_color_from_vertex::SHADER,
-    ).unwrap();
-
-    let sphere = Sphere::new(0.5, 30);
-    let model_matrix = Matrix4::new_scaling(1.);
+/*
+Where the spheres are seen from.
+*/
+#[derive(Clone, Copy)]
+enum Placement {
+  // The demo sphere, seen through its own fixed view and spinning with the animation time
+  Demo { view: Matrix4<f32> },
+  // Placed among the axes and surfaces, seen through the orbit camera
+  World,
+}
 
+impl Placement {
+  fn demo() -> Self {
     // Build view and invert
     let mut view_matrix = Matrix4::new_translation(
       &Vector3::new(0., 0., Z_PLANE)
@@ -56,76 +54,244 @@ impl Sphere3D {
       0.,
       0.
     ));
-    view_matrix = view_matrix * view_rotation;
+    view_matrix *= view_rotation;
+    Placement::Demo { view: view_matrix }
+  }
+}
 
-    //log(&format!("Verticies: {}  Normals: {} Indices: {}", sphere.vertices.len(), sphere.normals.len(), sphere.wireframe_indices.len()));
+/*
+The mesh of one level of detail.
+*/
+struct SphereLevel {
+  buf_vertex_position: GpuBuffer<f32>,
+  buf_vertex_normal: GpuBuffer<f32>,
+  buf_wireframe_indices: GpuBuffer<u16>,
+  buf_face_indices: GpuBuffer<u16>,
+  // Attribute bindings recorded once, `None` when vertex array objects aren't supported
+  vertex_array: Option<WebGlVertexArrayObject>,
+}
 
+impl SphereLevel {
+  fn new(gl: &Gl, sphere: &Sphere) -> Result<Self, MVisualError> {
     // Buffer any data that will remain unchaged
-    let vertex_gpu_buffer = fill_new_buffer(
-      &gl,
-      GL::ARRAY_BUFFER,
-      &sphere.vertices,
-      GL::STATIC_DRAW
-    );
-    let normals_gpu_buffer = fill_new_buffer(
-      &gl,
+    Ok(Self {
+      buf_vertex_position: GpuBuffer::new(gl, GL::ARRAY_BUFFER, &sphere.vertices, GL::STATIC_DRAW)?,
+      buf_vertex_normal: GpuBuffer::new(gl, GL::ARRAY_BUFFER, &sphere.normals, GL::STATIC_DRAW)?,
+      buf_wireframe_indices: GpuBuffer::new(gl, GL::ELEMENT_ARRAY_BUFFER, &sphere.wireframe_indices, GL::STATIC_DRAW)?,
+      buf_face_indices: GpuBuffer::new(gl, GL::ELEMENT_ARRAY_BUFFER, &sphere.face_indices, GL::STATIC_DRAW)?,
+      vertex_array: None,
+    })
+  }
+}
+
+/*
+Spheres tessellated more finely the bigger they are on screen, see `LOD_RESOLUTIONS`.
+*/
+pub struct Sphere3D {
+  shader: LitShader,
+  bindings: SphereBindings,
+  // Kept to rebuild the buffers after a context loss
+  radius: f32,
+  model_transform: Matrix4<f32>,
+  placement: Placement,
+  // Data, one mesh per entry in `LOD_RESOLUTIONS`
+  levels: Vec<SphereLevel>,
+  lod: LodSelector,
+  // One model space offset per sphere drawn
+  buf_instance_offsets: GpuBuffer<f32>,
+  instance_offsets: Vec<f32>,
+  // Model space bounds of every instance together, `None` without any
+  bounds: Option<Bounds>,
+  // Settings
+  wireframe: bool,
+  material: Material,
+  // Added on top of the material while selected
+  highlight: Option<[f32; 3]>,
+  shading: Shading,
+  // Animation time of the last `animate`, in seconds
+  time: f32,
+}
+
+impl Sphere3D {
+  pub fn new(gl: &Gl, wireframe: bool) -> Result<Self, MVisualError> {
+    Self::with_shading(gl, 0.5, vec![0., 0., 0.], wireframe, Shading::default(), Placement::demo())
+  }
+
+  /**
+   * Many spheres of the same `radius` drawn in one call, `offsets` holds an (x, y, z) center for each in the same space as the axes and surfaces.
+   *
+   * They share one mesh and one material. Without instancing support the spheres are drawn one at a time instead.
+   */
+  pub fn with_instances(gl: &Gl, radius: f32, offsets: Vec<f32>, wireframe: bool) -> Result<Self, MVisualError> {
+    Self::with_shading(gl, radius, offsets, wireframe, Shading::default(), Placement::World)
+  }
+
+  fn with_shading(
+    gl: &Gl,
+    radius: f32,
+    offsets: Vec<f32>,
+    wireframe: bool,
+    shading: Shading,
+    placement: Placement,
+  ) -> Result<Self, MVisualError> {
+    let shader = Self::link(gl, shading)?;
+    let bindings = SphereBindings::new(gl, &shader.program)?;
+
+    let model_matrix = Matrix4::new_scaling(1.);
+
+    let spheres: Vec<Sphere> = LOD_RESOLUTIONS.iter().map(|&resolution| Sphere::new(radius, resolution)).collect();
+    let levels = spheres
+      .iter()
+      .map(|sphere| SphereLevel::new(gl, sphere))
+      .collect::<Result<Vec<_>, MVisualError>>()?;
+    // A level is good until its outline's segments (3 more than its resolution, see `Sphere::new`) get too long
+    let max_sizes = LOD_RESOLUTIONS[..LOD_RESOLUTIONS.len() - 1]
+      .iter()
+      .map(|&resolution| PIXELS_PER_SEGMENT * (resolution + 3) as f32 / std::f32::consts::PI)
+      .collect();
+
+    let offsets_gpu_buffer = GpuBuffer::new(
+      gl,
       GL::ARRAY_BUFFER,
-      &sphere.normals,
+      &offsets,
       GL::STATIC_DRAW
-    );
+    )?;
 
-    let widx_gpu_buffer = fill_new_buffer(
-      &gl,
-      GL::ELEMENT_ARRAY_BUFFER,
-      &sphere.wireframe_indices,
-      GL::STATIC_DRAW
-    );
-    let fidx_gpu_buffer = fill_new_buffer(
-      &gl,
-      GL::ELEMENT_ARRAY_BUFFER,
-      &sphere.face_indices,
-      GL::STATIC_DRAW
-    );
+    // The finest level is the roundest, the others only ever fit inside it
+    let bounds = Bounds::from_positions(&spheres[spheres.len() - 1].vertices).and_then(|mesh| mesh.instanced(&offsets));
 
-    return Self {
-      // Do everything that needs &program first
-      u_amb_light_color: gl.get_uniform_location(&program, "uAmbientLightColor").unwrap(),
-      u_diff_light_color: gl.get_uniform_location(&program, "uDiffuseLightColor").unwrap(),
-      u_diff_light_pos: gl.get_uniform_location(&program, "uDiffuseLightPosition").unwrap(),
-      u_material_color: gl.get_uniform_location(&program, "uMaterialColor").unwrap(),
-      u_mv_transform: gl.get_uniform_location(&program, "uModelView").unwrap(),
-      u_mvp_transform: gl.get_uniform_location(&program, "uModelViewProjection").unwrap(),
-      u_opacity: gl.get_uniform_location(&program, "uOpacity").unwrap(),
-      a_vertex_position: gl.get_attrib_location(&program, "aVertexPosition") as u32,
-      a_vertex_normal: gl.get_attrib_location(&program, "aVertexNormal") as u32,
+    let mut value = Self {
       // Transfer program owner ship and finish
-      program: program,
+      shader,
+      bindings,
       model_transform: model_matrix,
-      view_transform: view_matrix,
-      buf_vertex_position: vertex_gpu_buffer,
-      buf_vertex_normal: normals_gpu_buffer,
-      buf_wireframe_indices: widx_gpu_buffer,
-      len_wireframe_indices: sphere.wireframe_indices.len() as i32,
-      _buf_face_indices: fidx_gpu_buffer,
-      _len_face_indices: sphere.face_indices.len() as i32,
+      placement,
+      levels,
+      lod: LodSelector::new(max_sizes),
+      buf_instance_offsets: offsets_gpu_buffer,
+      instance_offsets: offsets,
+      bounds,
       // Settings
-      wireframe: wireframe,
+      wireframe,
+      material: Material::default(),
+      highlight: None,
+      shading,
+      time: 0.,
       // Data
-      _sphere: sphere,
+      radius,
+    };
+    value.record_vertex_arrays(gl);
+
+    Ok(value)
+  }
+
+  fn view_transform(&self, app_state: &AppState) -> Matrix4<f32> {
+    match self.placement {
+      Placement::Demo { view } => view,
+      Placement::World => math::get_orbit_view_matrix(
+        app_state.rotation_x_axis,
+        app_state.rotation_y_axis,
+      ),
     }
   }
+
+  /*
+  The demo sphere spins slowly with the animation time, a radian every 1.5 seconds.
+  */
+  fn solid_model_transform(&self) -> Matrix4<f32> {
+    match self.placement {
+      Placement::Demo { .. } => Matrix4::new_rotation(
+        Vector3::new(0., 0., self.time / 1.5)
+      ) * self.model_transform,
+      Placement::World => self.model_transform,
+    }
+  }
+
+  fn model_view(&self, app_state: &AppState) -> Matrix4<f32> {
+    self.view_transform(app_state) * self.solid_model_transform()
+  }
+
+  fn instance_count(&self) -> i32 {
+    (self.instance_offsets.len() / 3) as i32
+  }
+
+  /*
+  Points the shader's attributes at the buffers. The offsets advance once per instance, when instancing isn't supported they are set per draw in `draw_instances` instead.
+  */
+  fn bind_attributes(&self, gl: &Gl, level: &SphereLevel) {
+    level.buf_vertex_position.bind(gl);
+    self.shader.bindings.vertex_position.point(gl, 0, 0);
+
+    level.buf_vertex_normal.bind(gl);
+    self.shader.bindings.vertex_normal.point(gl, 0, 0);
+
+    let instance_offset = &self.bindings.instance_offset;
+    if gl.supports_instancing() {
+      self.buf_instance_offsets.bind(gl);
+      instance_offset.point(gl, 0, 0);
+      gl.vertex_attrib_divisor(instance_offset.location(), 1);
+    } else {
+      gl.disable_vertex_attrib_array(instance_offset.location());
+    }
+  }
+
+  /*
+  Captures `bind_attributes` in a vertex array object for each level, has to be redone whenever the shader (and so the attribute locations) changes.
+  */
+  fn record_vertex_arrays(&mut self, gl: &Gl) {
+    for index in 0..self.levels.len() {
+      gl.delete_vertex_array(self.levels[index].vertex_array.as_ref());
+      let vertex_array = gl.create_vertex_array();
+      if let Some(vertex_array) = &vertex_array {
+        gl.bind_vertex_array(Some(vertex_array));
+        self.bind_attributes(gl, &self.levels[index]);
+        gl.bind_vertex_array(None);
+      }
+      self.levels[index].vertex_array = vertex_array;
+    }
+  }
+
+  /*
+  Draws every sphere with the bound element buffer.
+  */
+  fn draw_instances(&self, gl: &Gl, mode: u32, count: i32) {
+    if gl.supports_instancing() {
+      gl.draw_elements_instanced(mode, count, GL::UNSIGNED_SHORT, self.instance_count());
+    } else {
+      for offset in self.instance_offsets.chunks(3) {
+        gl.vertex_attrib3f(self.bindings.instance_offset.location(), offset[0], offset[1], offset[2]);
+        gl.draw_elements_with_i32(mode, count, GL::UNSIGNED_SHORT, 0);
+      }
+    }
+  }
+
+  fn link(gl: &Gl, shading: Shading) -> Result<LitShader, MVisualError> {
+    LitShader::new(
+      gl,
+      shading,
+      Coloring::Material,
+      (
+        crate::shaders::vertex::sphere_3d::SHADER,
+        crate::shaders::fragment::vary_color_from_vertex::SHADER,
+      ),
+      (
+        crate::shaders::vertex::sphere_3d_phong::SHADER,
+        crate::shaders::fragment::blinn_phong::SHADER,
+      ),
+    )
+  }
 }
 
 impl Program for Sphere3D {
   fn render(
     &self,
-    gl: &WebGlRenderingContext,
-    app_state: &AppState
+    gl: &Gl,
+    app_state: &AppState,
+    scene: &Scene,
   ) {
     // Calculate current transformations
-    let solid_model_transform = self.model_transform * Matrix4::new_rotation(
-      Vector3::new(0., 0., app_state.time / 1500.)
-    );
+    let view_transform = self.view_transform(app_state);
+    let solid_model_transform = self.solid_model_transform();
 
     let aspect_ratio = app_state.canvas_width / app_state.canvas_height;
     let projection_matrix = Perspective3::new(
@@ -134,67 +300,187 @@ impl Program for Sphere3D {
       Z_NEAR,
       Z_FAR,
     );
-    gl.use_program(Some(&self.program));
-
-    // Load verticies & normals
-    gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.buf_vertex_position));
-    gl.enable_vertex_attrib_array(self.a_vertex_position);
-    gl.vertex_attrib_pointer_with_i32(self.a_vertex_position, 3, GL::FLOAT, false, 0, 0);
-
-    gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.buf_vertex_normal));
-    gl.enable_vertex_attrib_array(self.a_vertex_normal);
-    gl.vertex_attrib_pointer_with_i32(self.a_vertex_normal, 3, GL::FLOAT, false, 0, 0);
+    gl.use_program(Some(&self.shader.program));
 
-    // Universal settings
-    gl.uniform3f(Some(&self.u_diff_light_color), 1., 1., 1.); // White
-    gl.uniform3f(Some(&self.u_diff_light_pos), -0.5, 0.5, 0.75); // Above left shoulder
-    gl.uniform1f(Some(&self.u_opacity), 1.);
+    // Load verticies, normals & instance offsets
+    let level = &self.levels[self.lod.level()];
+    match &level.vertex_array {
+      Some(vertex_array) => gl.bind_vertex_array(Some(vertex_array)),
+      None => self.bind_attributes(gl, level),
+    }
 
     // Color settings for face drawing
-    gl.uniform3f(Some(&self.u_amb_light_color), 0.2, 0.2, 0.2); // Dim white
-    gl.uniform3f(Some(&self.u_material_color), 0.5, 0.5, 0.8); // Blue-ish
+    self.shader.set_lighting(gl, &scene.lighting, &view_transform);
+    self.shader.set_material(gl, &self.material.tinted(self.highlight));
 
     // Load transformations for faces
-    let mut mv_matrix = self.view_transform * solid_model_transform;
-    gl.uniform_matrix4fv_with_f32_array(
-      Some(&self.u_mv_transform),
-      false,
-      mv_matrix.as_slice()
-    );
-    gl.uniform_matrix4fv_with_f32_array(
-      Some(&self.u_mvp_transform),
-      false,
-      (projection_matrix.as_matrix() * mv_matrix).as_slice()
-    );
+    let mut mv_matrix = view_transform * solid_model_transform;
+    self.shader.set_transforms(gl, &mv_matrix, projection_matrix.as_matrix());
 
     // Draw faces
-    gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self._buf_face_indices));
-    gl.draw_elements_with_i32(GL::TRIANGLES, self._len_face_indices, GL::UNSIGNED_SHORT, 0);
+    level.buf_face_indices.bind(gl);
+    if self.is_transparent() {
+      /*
+      The inside of the sphere shows through, so it has to be blended in before the outside. A sphere is convex so drawing all back faces and then all front faces is enough to get them in order.
+      */
+      gl.cull_face(GL::FRONT);
+      self.draw_instances(gl, GL::TRIANGLES, level.buf_face_indices.len() as i32);
+      gl.cull_face(GL::BACK);
+    }
+    self.draw_instances(gl, GL::TRIANGLES, level.buf_face_indices.len() as i32);
 
     if self.wireframe {
       // Set color settings for wireframe
-      gl.uniform3f(Some(&self.u_amb_light_color), 1., 1., 1.); // White
-      gl.uniform3f(Some(&self.u_material_color), 0.5, 0.8, 0.5); // Green-ish
+      self.shader.set_wireframe_material(gl, &self.material);
 
       // Make wire frame a little above to precent z-fighting
-      let wire_model_transform = solid_model_transform * Matrix4::new_scaling(1.001);
+      let wire_model_transform = solid_model_transform * Matrix4::new_scaling(WIREFRAME_SCALE);
 
       // Load new MV and MVP transforms based on the scaling
-      mv_matrix = self.view_transform * wire_model_transform;
-      gl.uniform_matrix4fv_with_f32_array(
-        Some(&self.u_mv_transform),
-        false,
-        mv_matrix.as_slice()
-      );
-      gl.uniform_matrix4fv_with_f32_array(
-        Some(&self.u_mvp_transform),
-        false,
-        (projection_matrix.as_matrix() * mv_matrix).as_slice()
-      );
+      mv_matrix = view_transform * wire_model_transform;
+      self.shader.set_transforms(gl, &mv_matrix, projection_matrix.as_matrix());
 
       // Draw wireframe
-      gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self.buf_wireframe_indices));
-      gl.draw_elements_with_i32(GL::LINES, self.len_wireframe_indices, GL::UNSIGNED_SHORT, 0);
+      level.buf_wireframe_indices.bind(gl);
+      self.draw_instances(gl, GL::LINES, level.buf_wireframe_indices.len() as i32);
+    }
+
+    if level.vertex_array.is_some() {
+      // Leave the default attribute state to the programs which don't use vertex arrays
+      gl.bind_vertex_array(None);
+    } else if gl.supports_instancing() {
+      // The divisor and the short offsets buffer would otherwise stick to the location for the next program
+      gl.vertex_attrib_divisor(self.bindings.instance_offset.location(), 0);
+      gl.disable_vertex_attrib_array(self.bindings.instance_offset.location());
+    }
+  }
+
+  fn set_shading(&mut self, gl: &Gl, shading: Shading) -> Result<(), MVisualError> {
+    let shader = Self::link(gl, shading)?;
+    self.bindings = SphereBindings::new(gl, &shader.program).inspect_err(|_| shader.release(gl))?;
+    self.shader.release(gl);
+    self.shader = shader;
+    self.shading = shading;
+    self.record_vertex_arrays(gl);
+    Ok(())
+  }
+
+  fn restore(&mut self, gl: &Gl, _color_map: &ColorMap) -> Result<(), MVisualError> {
+    let restored = Self::with_shading(
+      gl,
+      self.radius,
+      self.instance_offsets.clone(),
+      self.wireframe,
+      self.shading,
+      self.placement,
+    )?;
+    *self = Self {
+      model_transform: self.model_transform,
+      material: self.material,
+      highlight: self.highlight,
+      time: self.time,
+      ..restored
+    };
+    Ok(())
+  }
+
+  fn release(&mut self, gl: &Gl) {
+    self.shader.release(gl);
+    for level in &mut self.levels {
+      gl.delete_vertex_array(level.vertex_array.take().as_ref());
     }
   }
+
+  fn animate(&mut self, _gl: &Gl, time: f32) -> Result<(), MVisualError> {
+    self.time = time;
+    Ok(())
+  }
+
+  fn material(&self) -> Option<Material> {
+    Some(self.material)
+  }
+
+  fn set_material(&mut self, material: Material) {
+    self.material = material;
+  }
+
+  fn set_highlight(&mut self, tint: Option<[f32; 3]>) {
+    self.highlight = tint;
+  }
+
+  fn set_scale(&mut self, scale: Vector3<f32>) {
+    // A sphere stretched differently along each axis is an ellipsoid
+    self.model_transform = Matrix4::new_nonuniform_scaling(&scale);
+  }
+
+  fn is_scalable(&self) -> bool {
+    true
+  }
+
+  fn is_transparent(&self) -> bool {
+    self.material.opacity < 1.
+  }
+
+  fn view_depth(&self, app_state: &AppState) -> f32 {
+    // From the middle of all the instances
+    let center = self.bounds.map_or(Vector3::zeros(), |bounds| bounds.sphere.center);
+    -(self.model_view(app_state) * center.push(1.)).z
+  }
+
+  fn view_bounds(&self, app_state: &AppState) -> Option<Bounds> {
+    // Around the wireframe too, which is drawn a little outside the faces
+    let model_view = self.model_view(app_state) * Matrix4::new_scaling(WIREFRAME_SCALE);
+    self.bounds.map(|bounds| bounds.transformed(&model_view))
+  }
+
+  fn select_detail(&mut self, app_state: &AppState) {
+    let model_view = self.model_view(app_state);
+    let group = match self.bounds {
+      Some(bounds) => bounds.sphere.transformed(&model_view),
+      None => return,
+    };
+    // One level for every instance, picked for the nearest so close ups stay smooth
+    let radius = BoundingSphere { center: Vector3::zeros(), radius: self.radius }.transformed(&model_view).radius;
+    let nearest_depth = -group.center.z - (group.radius - radius);
+    self.lod.select(lod::projected_size(radius, nearest_depth, app_state.canvas_height));
+  }
+
+  /*
+  Against the true sphere rather than any of the meshes, one test per instance.
+  */
+  fn pick(&mut self, ray: &Ray, app_state: &AppState) -> Option<PickHit> {
+    let model_view = self.model_view(app_state);
+    let ray = ray.transformed(&model_view.try_inverse()?);
+
+    let mut nearest: Option<(usize, f32, Vector3<f32>)> = None;
+    for (instance, offset) in self.instance_offsets.chunks_exact(3).enumerate() {
+      let center = Vector3::new(offset[0], offset[1], offset[2]);
+      let (entry, exit) = match ray.intersect_sphere(&BoundingSphere { center, radius: self.radius }) {
+        Some(hit) => hit,
+        None => continue,
+      };
+      // From inside a sphere its far side is what is seen
+      let t = if entry >= 0. { entry } else { exit };
+      let limit = nearest.map_or(1., |(_, nearest_t, _)| nearest_t);
+      if t >= 0. && t <= limit {
+        nearest = Some((instance, t, center));
+      }
+    }
+
+    let (instance, t, center) = nearest?;
+    let position = ray.at(t);
+    let local = position - center;
+    let longitude = local.y.atan2(local.x);
+    let latitude = (local.z / self.radius).clamp(-1., 1.).asin();
+    Some(PickHit {
+      t,
+      position,
+      normal: picking::facing(local, &ray),
+      triangle: None,
+      barycentric: None,
+      instance: Some(instance),
+      parameters: Some([longitude, latitude]),
+      value: None,
+    })
+  }
 }
\ No newline at end of file
@@ -0,0 +1,391 @@
+use crate::util::bounds::Bounds;
+use crate::util::colormap::ColorMap;
+use crate::util::surface::*;
+use crate::util::constants::*;
+use crate::util::math;
+use crate::util::webgl;
+use crate::util::gpu_buffer::GpuBuffer;
+use crate::util::lod::GridLevels;
+use crate::util::picking::{self, Bvh, PickHit, Ray};
+use crate::app_state::AppState;
+use crate::error::MVisualError;
+use crate::scene::Scene;
+use super::common::{Coloring, LitShader, Program, Shading};
+use crate::util::material::Material;
+use crate::util::bindings::{Attribute, TextureUnit, Uniform};
+use crate::util::gl::Gl;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use nalgebra::{Matrix4, Perspective3, Vector3, Vector4};
+
+crate::bindings! {
+    struct SurfaceBindings {
+        color_map: Uniform<TextureUnit> = "uColorMap",
+        // Value to color by, normalized onto [0, 1]
+        scalar: Attribute<f32> = "aScalar",
+    }
+}
+
+pub struct Surface3D {
+    shader: LitShader,
+    // Kept to rebuild the buffers after a context loss
+    surface: Surface,
+    // What the surface is sampled from when it was given as an expression
+    function: Option<SurfaceFunction>,
+    color_mode: ColorMode,
+    shading: Shading,
+    // Range of the values which were normalized onto the color map
+    value_range: (f32, f32),
+    // Middle of the surface's bounds, for depth sorting
+    center: Vector4<f32>,
+    // Model space bounds of the vertices, `None` when no sample is defined
+    bounds: Option<Bounds>,
+    // Identity unless the surface was stretched with `set_scale`
+    model_transform: Matrix4<f32>,
+    // Everything besides the bindings in `shader`
+    bindings: SurfaceBindings,
+    // The color comes from the color map, `material.color` is unused
+    material: Material,
+    // Added on top of the material while selected
+    highlight: Option<[f32; 3]>,
+    // Data
+    tex_color_map: WebGlTexture,
+    buf_vertex_position: GpuBuffer<f32>,
+    buf_vertex_normal: GpuBuffer<f32>,
+    buf_scalar: GpuBuffer<f32>,
+    // Indices at each level of detail, u32 when the context supports it, else u16
+    grid_levels: GridLevels,
+    // Built on the first pick after the surface last changed
+    bvh: Option<Bvh>,
+    // Attribute bindings recorded once, `None` when vertex array objects aren't supported
+    vertex_array: Option<WebGlVertexArrayObject>,
+}
+
+impl Surface3D {
+    pub fn new(
+        gl: &Gl,
+        surface: Surface,
+        color_mode: ColorMode,
+        color_map: &ColorMap,
+    ) -> Result<Self, MVisualError> {
+        Self::with_shading(gl, surface, None, color_mode, color_map, Shading::default())
+    }
+
+    /**
+     * A surface sampled from `function` at time `t`. When the function depends on t it is sampled again on every `animate`.
+     *
+     * Colors are normalized to the range of values at `t`, later values outside of it take the color at the nearest end of the color map.
+     */
+    pub fn from_function(
+        gl: &Gl,
+        function: SurfaceFunction,
+        t: f32,
+        color_mode: ColorMode,
+        color_map: &ColorMap,
+    ) -> Result<Self, MVisualError> {
+        let surface = function.sample(t)?;
+        Self::with_shading(gl, surface, Some(function), color_mode, color_map, Shading::default())
+    }
+
+    fn with_shading(
+        gl: &Gl,
+        surface: Surface,
+        function: Option<SurfaceFunction>,
+        color_mode: ColorMode,
+        color_map: &ColorMap,
+        shading: Shading,
+    ) -> Result<Self, MVisualError> {
+        let shader = Self::link(gl, shading)?;
+
+        let (scalars, value_range) = normalize_values(surface.color_values(color_mode));
+        let (_, (z_min, z_max)) = normalize_values(&surface.heights);
+        let center = Vector4::new(
+            (surface.x_range.0 + surface.x_range.1) / 2.,
+            (surface.y_range.0 + surface.y_range.1) / 2.,
+            (z_min + z_max) / 2.,
+            1.,
+        );
+
+        // Animated surfaces refill their vertex data every frame, the indices never change
+        let vertex_usage = match &function {
+            Some(function) if function.is_animated() => GL::DYNAMIC_DRAW,
+            _ => GL::STATIC_DRAW,
+        };
+        let vertex_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ARRAY_BUFFER,
+            &surface.vertices,
+            vertex_usage
+        )?;
+        let normals_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ARRAY_BUFFER,
+            &surface.normals,
+            vertex_usage
+        )?;
+        let scalar_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ARRAY_BUFFER,
+            &scalars,
+            vertex_usage
+        )?;
+        let grid_levels = GridLevels::new(gl, surface.resolution)?;
+
+        let color_map_texture = webgl::fill_color_map_texture(gl, None, color_map)?;
+
+        let mut value = Self {
+            // Do everything that needs &program first
+            bindings: SurfaceBindings::new(gl, &shader.program)?,
+            // Transfer program owner ship and finish
+            shader,
+            material: Material::default(),
+            highlight: None,
+            tex_color_map: color_map_texture,
+            buf_vertex_position: vertex_gpu_buffer,
+            buf_vertex_normal: normals_gpu_buffer,
+            buf_scalar: scalar_gpu_buffer,
+            grid_levels,
+            bvh: None,
+            vertex_array: None,
+            // Data
+            value_range,
+            center,
+            bounds: Bounds::from_positions(&surface.vertices),
+            model_transform: Matrix4::identity(),
+            surface,
+            function,
+            color_mode,
+            shading,
+        };
+        value.record_vertex_array(gl);
+
+        Ok(value)
+    }
+
+    /*
+    Points the shader's attributes at the buffers.
+    */
+    fn bind_attributes(&self, gl: &Gl) {
+        self.buf_vertex_position.bind(gl);
+        self.shader.bindings.vertex_position.point(gl, 0, 0);
+
+        self.buf_vertex_normal.bind(gl);
+        self.shader.bindings.vertex_normal.point(gl, 0, 0);
+
+        self.buf_scalar.bind(gl);
+        self.bindings.scalar.point(gl, 0, 0);
+    }
+
+    /*
+    Captures `bind_attributes` in a vertex array object, has to be redone whenever the shader (and so the attribute locations) changes.
+    */
+    fn record_vertex_array(&mut self, gl: &Gl) {
+        gl.delete_vertex_array(self.vertex_array.as_ref());
+        self.vertex_array = gl.create_vertex_array();
+        if let Some(vertex_array) = &self.vertex_array {
+            gl.bind_vertex_array(Some(vertex_array));
+            self.bind_attributes(gl);
+            gl.bind_vertex_array(None);
+        }
+    }
+
+    fn link(gl: &Gl, shading: Shading) -> Result<LitShader, MVisualError> {
+        LitShader::new(
+            gl,
+            shading,
+            Coloring::ColorMap,
+            (
+                crate::shaders::vertex::surface_3d::SHADER,
+                crate::shaders::fragment::color_map_lit::SHADER,
+            ),
+            (
+                crate::shaders::vertex::surface_3d_phong::SHADER,
+                crate::shaders::fragment::color_map_blinn_phong::SHADER,
+            ),
+        )
+    }
+
+    /**
+     * The (min, max) of the values the surface is colored by, for the colorbar.
+     */
+    pub fn value_range(&self) -> (f32, f32) {
+        self.value_range
+    }
+}
+
+impl Program for Surface3D {
+    fn render(
+        &self,
+        gl: &Gl,
+        app_state: &AppState,
+        scene: &Scene,
+    ) {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        let aspect_ratio = app_state.canvas_width / app_state.canvas_height;
+        let projection_matrix = Perspective3::new(
+            aspect_ratio,
+            FIELD_OF_VIEW,
+            Z_NEAR,
+            Z_FAR,
+        );
+
+        gl.use_program(Some(&self.shader.program));
+
+        // Load verticies, normals & color values
+        match &self.vertex_array {
+            Some(vertex_array) => gl.bind_vertex_array(Some(vertex_array)),
+            None => self.bind_attributes(gl),
+        }
+
+        gl.active_texture(GL::TEXTURE0);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.tex_color_map));
+        self.bindings.color_map.set(gl, &TextureUnit(0));
+
+        // Universal settings
+        self.shader.set_lighting(gl, &scene.lighting, &view_matrix);
+        self.shader.set_material(gl, &self.material.tinted(self.highlight));
+
+        let mv_matrix = view_matrix * self.model_transform;
+        self.shader.set_transforms(gl, &mv_matrix, projection_matrix.as_matrix());
+
+        // Both sides of the surface can be seen
+        gl.disable(GL::CULL_FACE);
+        // Bound here rather than in the vertex array, the level changes with the surface's size on screen
+        let indices = self.grid_levels.indices();
+        indices.bind(gl);
+        gl.draw_elements_with_i32(GL::TRIANGLES, indices.len() as i32, indices.gl_type(), 0);
+        gl.enable(GL::CULL_FACE);
+
+        // Leave the default attribute state to the programs which don't use vertex arrays
+        gl.bind_vertex_array(None);
+    }
+
+    fn set_color_map(&mut self, gl: &Gl, color_map: &ColorMap) -> Result<(), MVisualError> {
+        webgl::fill_color_map_texture(gl, Some(self.tex_color_map.clone()), color_map)?;
+        Ok(())
+    }
+
+    fn set_shading(&mut self, gl: &Gl, shading: Shading) -> Result<(), MVisualError> {
+        // Look everything up before replacing anything, so a failure leaves the old program working
+        let shader = Self::link(gl, shading)?;
+        self.bindings = SurfaceBindings::new(gl, &shader.program).inspect_err(|_| shader.release(gl))?;
+        self.shader.release(gl);
+        self.shader = shader;
+        self.shading = shading;
+        self.record_vertex_array(gl);
+        Ok(())
+    }
+
+    fn restore(&mut self, gl: &Gl, color_map: &ColorMap) -> Result<(), MVisualError> {
+        let restored = Self::with_shading(
+            gl,
+            self.surface.clone(),
+            self.function.take(),
+            self.color_mode,
+            color_map,
+            self.shading,
+        )?;
+        *self = Self {
+            model_transform: self.model_transform,
+            material: self.material,
+            highlight: self.highlight,
+            // Keep the colors where they were, rather than fitting them to the current frame
+            value_range: self.value_range,
+            ..restored
+        };
+        if self.function.is_some() {
+            let scalars = normalize_values_in(self.surface.color_values(self.color_mode), self.value_range);
+            self.buf_scalar.set(gl, &scalars);
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, gl: &Gl) {
+        self.shader.release(gl);
+        gl.delete_vertex_array(self.vertex_array.take().as_ref());
+        gl.delete_texture(Some(&self.tex_color_map));
+    }
+
+    fn animate(&mut self, gl: &Gl, time: f32) -> Result<(), MVisualError> {
+        let function = match &self.function {
+            Some(function) if function.is_animated() => function,
+            _ => return Ok(()),
+        };
+
+        self.surface.set_heights(function.heights(time))?;
+        self.bounds = Bounds::from_positions(&self.surface.vertices);
+        self.bvh = None;
+        let scalars = normalize_values_in(self.surface.color_values(self.color_mode), self.value_range);
+
+        // Same sizes every frame, so these reuse the buffers' storage
+        self.buf_vertex_position.set(gl, &self.surface.vertices);
+        self.buf_vertex_normal.set(gl, &self.surface.normals);
+        self.buf_scalar.set(gl, &scalars);
+        Ok(())
+    }
+
+    fn material(&self) -> Option<Material> {
+        Some(self.material)
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    fn legend_color(&self) -> Option<[f32; 3]> {
+        // Colored by the color map, which the colorbar explains
+        None
+    }
+
+    fn set_highlight(&mut self, tint: Option<[f32; 3]>) {
+        self.highlight = tint;
+    }
+
+    fn set_scale(&mut self, scale: Vector3<f32>) {
+        self.model_transform = Matrix4::new_nonuniform_scaling(&scale);
+    }
+
+    fn is_scalable(&self) -> bool {
+        true
+    }
+
+    fn is_transparent(&self) -> bool {
+        self.material.opacity < 1.
+    }
+
+    fn view_depth(&self, app_state: &AppState) -> f32 {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        -(view_matrix * self.model_transform * self.center).z
+    }
+
+    fn view_bounds(&self, app_state: &AppState) -> Option<Bounds> {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        self.bounds.map(|bounds| bounds.transformed(&(view_matrix * self.model_transform)))
+    }
+
+    fn select_detail(&mut self, app_state: &AppState) {
+        let bounds = self.view_bounds(app_state);
+        self.grid_levels.select(bounds, app_state.canvas_height);
+    }
+
+    fn pick(&mut self, ray: &Ray, app_state: &AppState) -> Option<PickHit> {
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        let ray = ray.transformed(&(view_matrix * self.model_transform).try_inverse()?);
+        let surface = &self.surface;
+        // Against the full grid, whatever level of detail is drawn
+        let bvh = self.bvh.get_or_insert_with(|| Bvh::new(&surface.vertices, &surface.indices));
+        picking::pick_surface(surface, bvh, &ray, self.color_mode)
+    }
+}
@@ -0,0 +1,361 @@
+use crate::util::glyph_atlas::*;
+use crate::util::constants::*;
+use crate::util::math;
+use crate::util::webgl;
+use crate::util::gpu_buffer::{GpuBuffer, VertexLayout};
+use crate::app_state::AppState;
+use crate::error::MVisualError;
+use crate::scene::Scene;
+use super::common::Program;
+use crate::util::bindings::{Attribute, TextureUnit, Uniform};
+use crate::util::colormap::ColorMap;
+use crate::util::gl::Gl;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use nalgebra::{Matrix4, Perspective3, Vector3};
+
+// Extra room (in pixels) kept between labels when checking for overlaps
+const LABEL_PADDING: f32 = 2.;
+
+pub enum Anchor {
+    // Position in world units, follows the camera
+    World(Vector3<f32>),
+    // Position in pixels from the bottom left of the canvas
+    Screen(f32, f32),
+}
+
+#[derive(Clone, Copy)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+pub struct Label {
+    pub text: String,
+    pub anchor: Anchor,
+    // Offset in pixels applied after the anchor is projected (Y is up)
+    pub offset: (f32, f32),
+    // Height of the text in pixels, stays the same no matter how far away the anchor is
+    pub size: f32,
+    pub color: [f32; 3],
+    pub align: TextAlign,
+    // When labels overlap, the one with the highest priority is shown
+    pub priority: i32,
+}
+
+impl Label {
+    pub fn new(text: &str, anchor: Anchor) -> Self {
+        Self {
+            text: String::from(text),
+            anchor,
+            offset: (0., 0.),
+            size: 14.,
+            color: [1., 1., 1.],
+            align: TextAlign::Center,
+            priority: 0,
+        }
+    }
+}
+
+crate::bindings! {
+    struct TextBindings {
+        resolution: Uniform<[f32; 2]> = "uResolution",
+        glyph_atlas: Uniform<TextureUnit> = "uGlyphAtlas",
+        opacity: Uniform<f32> = "uOpacity",
+        vertex_position: Attribute<[f32; 2]> = "aVertexPosition",
+        texture_coord: Attribute<[f32; 2]> = "aTextureCoord",
+        vertex_color: Attribute<[f32; 3]> = "aVertexColor",
+        smoothing: Attribute<f32> = "aSmoothing",
+    }
+}
+
+pub struct Text3D {
+    program: WebGlProgram,
+    atlas: GlyphAtlas,
+    labels: Vec<Label>,
+    bindings: TextBindings,
+    // Data
+    tex_glyph_atlas: WebGlTexture,
+    buf_vertices: GpuBuffer<f32>,
+    vertex_layout: VertexLayout,
+}
+
+impl Text3D {
+    pub fn new(gl: &Gl) -> Result<Self, MVisualError> {
+        let atlas = GlyphAtlas::new("sans-serif").map_err(MVisualError::context)?;
+        Self::with_atlas(gl, atlas)
+    }
+
+    /*
+    Vertices are interleaved, all fields are floats.
+    */
+    fn vertex_layout() -> VertexLayout {
+        VertexLayout::new()
+            .field::<f32>(2) // x, y
+            .field::<f32>(2) // u, v
+            .field::<f32>(3) // r, g, b
+            .field::<f32>(1) // smoothing
+    }
+
+    fn with_atlas(gl: &Gl, atlas: GlyphAtlas) -> Result<Self, MVisualError> {
+        let program = webgl::link_program(
+            gl,
+            crate::shaders::vertex::text_3d::SHADER,
+            crate::shaders::fragment::sdf_text::SHADER,
+        )?;
+
+        let texture = gl
+            .create_texture()
+            .ok_or_else(|| MVisualError::Buffer(String::from("Failed to create texture")))?;
+        gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+        // Rows of single byte pixels are not 4 byte aligned
+        gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            GL::ALPHA as i32,
+            atlas.width as i32,
+            atlas.height as i32,
+            0,
+            GL::ALPHA,
+            GL::UNSIGNED_BYTE,
+            Some(&atlas.pixels),
+        ).map_err(|error| MVisualError::Buffer(format!("Failed to fill glyph atlas texture: {:?}", error)))?;
+        gl.count_upload(atlas.pixels.len());
+        // No mipmaps, the atlas is not a power of two and the distance field scales well anyway
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+
+        // Refilled every frame
+        let vertex_gpu_buffer = GpuBuffer::new(
+            gl,
+            GL::ARRAY_BUFFER,
+            &[],
+            GL::DYNAMIC_DRAW
+        )?;
+
+        Ok(Self {
+            // Do everything that needs &program first
+            bindings: TextBindings::new(gl, &program)?,
+            // Transfer program owner ship and finish
+            program,
+            tex_glyph_atlas: texture,
+            buf_vertices: vertex_gpu_buffer,
+            vertex_layout: Self::vertex_layout(),
+            // Data
+            atlas,
+            labels: Vec::new(),
+        })
+    }
+
+    /**
+     * Adds a label and returns its index.
+     */
+    pub fn add_label(&mut self, label: Label) -> usize {
+        self.labels.push(label);
+        self.labels.len() - 1
+    }
+
+    pub fn clear_labels(&mut self) {
+        self.labels.clear();
+    }
+
+    /*
+    Where the label's anchor lands on the canvas (in pixels) or None when it is behind the camera or off screen.
+    */
+    fn project_anchor(&self, anchor: &Anchor, view_projection: &Matrix4<f32>, app_state: &AppState) -> Option<(f32, f32)> {
+        match anchor {
+            Anchor::Screen(x, y) => Some((*x, *y)),
+            Anchor::World(position) => {
+                let clip = view_projection * position.push(1.);
+                if clip.w <= 0. {
+                    return None;
+                }
+
+                let ndc = clip.xyz() / clip.w;
+                if ndc.x.abs() > 1. || ndc.y.abs() > 1. || ndc.z.abs() > 1. {
+                    return None;
+                }
+
+                Some((
+                    (ndc.x + 1.) / 2. * app_state.canvas_width,
+                    (ndc.y + 1.) / 2. * app_state.canvas_height,
+                ))
+            }
+        }
+    }
+
+    /*
+    Appends two triangles per character of `label`, starting at `left` and vertically centered on `middle`.
+    */
+    fn push_label_vertices(&self, vertices: &mut Vec<f32>, label: &Label, left: f32, middle: f32) {
+        let scale = label.size / ATLAS_FONT_SIZE;
+        let cell = self.atlas.cell_size() * scale;
+        // About half a pixel on screen, in distance field units
+        let smoothing = 0.6 / (2. * ATLAS_SPREAD * scale);
+
+        let mut pen = left;
+        for character in label.text.chars() {
+            let glyph = self.atlas.glyph(character);
+
+            // Glyphs were drawn `ATLAS_SPREAD` into their cells
+            let x0 = pen - ATLAS_SPREAD * scale;
+            let x1 = x0 + cell;
+            let y0 = middle - cell / 2.;
+            let y1 = middle + cell / 2.;
+            let [u0, v_top, u1, v_bottom] = glyph.uv;
+
+            for (x, y, u, v) in [
+                (x0, y0, u0, v_bottom),
+                (x1, y0, u1, v_bottom),
+                (x1, y1, u1, v_top),
+                (x0, y0, u0, v_bottom),
+                (x1, y1, u1, v_top),
+                (x0, y1, u0, v_top),
+            ] {
+                vertices.extend_from_slice(&[x, y, u, v]);
+                vertices.extend_from_slice(&label.color);
+                vertices.push(smoothing);
+            }
+
+            pen += glyph.advance * scale;
+        }
+    }
+}
+
+impl Program for Text3D {
+    fn render(
+        &self,
+        gl: &Gl,
+        app_state: &AppState,
+        _scene: &Scene,
+    ) {
+        self.render_with_labels(gl, app_state, &[]);
+    }
+
+    fn restore(&mut self, gl: &Gl, _color_map: &ColorMap) -> Result<(), MVisualError> {
+        // Rendering the atlas again would be slow, the pixels are kept around instead
+        let restored = Self::with_atlas(gl, self.atlas.clone())?;
+        *self = Self {
+            labels: std::mem::take(&mut self.labels),
+            ..restored
+        };
+        Ok(())
+    }
+
+    fn release(&mut self, gl: &Gl) {
+        gl.delete_program(Some(&self.program));
+        gl.delete_texture(Some(&self.tex_glyph_atlas));
+    }
+}
+
+impl Text3D {
+    /**
+     * Draws the stored labels together with `extra_labels`, which only live for this frame (overlays build theirs every frame).
+     */
+    pub fn render_with_labels(
+        &self,
+        gl: &Gl,
+        app_state: &AppState,
+        extra_labels: &[Label],
+    ) {
+        let labels: Vec<&Label> = self.labels.iter().chain(extra_labels.iter()).collect();
+        if labels.is_empty() {
+            return;
+        }
+
+        let view_matrix = math::get_orbit_view_matrix(
+            app_state.rotation_x_axis,
+            app_state.rotation_y_axis,
+        );
+        let aspect_ratio = app_state.canvas_width / app_state.canvas_height;
+        let projection_matrix = Perspective3::new(
+            aspect_ratio,
+            FIELD_OF_VIEW,
+            Z_NEAR,
+            Z_FAR,
+        );
+        let view_projection = projection_matrix.as_matrix() * view_matrix;
+
+        /*
+        Greedy placement, highest priority first. A label is skipped when its box overlaps one which has already been placed.
+
+        The sort is stable so labels of equal priority keep the order they were added in.
+        */
+        let mut order: Vec<usize> = (0..labels.len()).collect();
+        order.sort_by_key(|index| -labels[*index].priority);
+
+        let mut placed: Vec<[f32; 4]> = Vec::new();
+        let mut vertices: Vec<f32> = Vec::new();
+        for index in order {
+            let label = labels[index];
+            let (x, y) = match self.project_anchor(&label.anchor, &view_projection, app_state) {
+                Some(position) => position,
+                None => continue,
+            };
+
+            let width = self.atlas.measure(&label.text) * label.size / ATLAS_FONT_SIZE;
+            let left = x + label.offset.0 - match label.align {
+                TextAlign::Left => 0.,
+                TextAlign::Center => width / 2.,
+                TextAlign::Right => width,
+            };
+            let middle = y + label.offset.1;
+
+            let bounds = [
+                left - LABEL_PADDING,
+                middle - label.size / 2. - LABEL_PADDING,
+                left + width + LABEL_PADDING,
+                middle + label.size / 2. + LABEL_PADDING,
+            ];
+            let overlaps = placed.iter().any(|other| {
+                bounds[0] < other[2] && other[0] < bounds[2] && bounds[1] < other[3] && other[1] < bounds[3]
+            });
+            if overlaps {
+                continue;
+            }
+            placed.push(bounds);
+
+            self.push_label_vertices(&mut vertices, label, left, middle);
+        }
+
+        if vertices.is_empty() {
+            return;
+        }
+
+        gl.use_program(Some(&self.program));
+
+        self.buf_vertices.set(gl, &vertices);
+
+        let bindings = &self.bindings;
+        let layout = &self.vertex_layout;
+        layout.point(gl, 0, bindings.vertex_position.location());
+        layout.point(gl, 1, bindings.texture_coord.location());
+        layout.point(gl, 2, bindings.vertex_color.location());
+        layout.point(gl, 3, bindings.smoothing.location());
+
+        gl.active_texture(GL::TEXTURE0);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.tex_glyph_atlas));
+        bindings.glyph_atlas.set(gl, &TextureUnit(0));
+        bindings.resolution.set(gl, &[app_state.canvas_width, app_state.canvas_height]);
+        bindings.opacity.set(gl, &1.);
+
+        // Labels are drawn on top of everything and need blending for their anti-aliased edges
+        gl.disable(GL::DEPTH_TEST);
+        gl.enable(GL::BLEND);
+        gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+
+        gl.draw_arrays(GL::TRIANGLES, 0, self.vertex_layout.vertex_count(&self.buf_vertices));
+
+        // The buffer shrinks and grows with the labels, don't leave other programs pointing at it
+        gl.disable_vertex_attrib_array(bindings.texture_coord.location());
+        gl.disable_vertex_attrib_array(bindings.vertex_color.location());
+        gl.disable_vertex_attrib_array(bindings.smoothing.location());
+
+        gl.disable(GL::BLEND);
+        gl.enable(GL::DEPTH_TEST);
+    }
+}
@@ -0,0 +1,14 @@
+use crate::util::clock::Clock;
+use crate::util::lighting::Lighting;
+use crate::util::timeline::Timeline;
+
+/*
+Settings shared by every program in the scene. Unlike `AppState` this is owned by `MVisual` and only changes through its API.
+*/
+#[derive(Default)]
+pub struct Scene {
+    pub lighting: Lighting,
+    pub clock: Clock,
+    // Keyframes played by `clock`
+    pub timeline: Timeline,
+}
@@ -0,0 +1,10 @@
+/*
+Color map lookup, the map is a one row texture spanning [0, 1] (see `webgl::fill_color_map_texture`).
+*/
+pub const SHADER: &str = r#"
+    uniform sampler2D uColorMap;
+
+    vec3 colorMap(float value) {
+        return texture2D(uColorMap, vec2(value, 0.5)).rgb;
+    }
+"#;
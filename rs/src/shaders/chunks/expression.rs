@@ -0,0 +1,14 @@
+/*
+Support for expressions compiled with `Expression::to_glsl`.
+
+`expressionPow` matches `power` in util/expression.rs, GLSL's own `pow` is undefined for negative bases so "x^2" would break left of the origin.
+*/
+pub const SHADER: &str = r#"
+    float expressionPow(float base, float exponent) {
+        if (base < 0.0 && fract(exponent) == 0.0) {
+            float magnitude = pow(-base, exponent);
+            return mod(exponent, 2.0) == 0.0 ? magnitude : -magnitude;
+        }
+        return pow(base, exponent);
+    }
+"#;
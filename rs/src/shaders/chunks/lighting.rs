@@ -0,0 +1,96 @@
+/*
+Lights and material factors shared by every lit shader. Works in both vertex (Gouraud) and fragment (Blinn-Phong) shaders, everything is in view space.
+
+Lights: a w of 0 in `uLightPosition` marks a directional light (xyz points towards it), 1 a point light (xyz is where it is). `MAX_LIGHTS` is defined by `LitShader` to match `lighting::MAX_LIGHTS`.
+
+With `UNIFORM_BUFFERS` (WebGL2) the lights come from the `Lighting` block shared by every program, see `Lighting::uniform_block`. The block can't know each program's view, so world space lights are moved into view space here with `uView`. Either way the rest of the chunk only goes through the `LIGHT_*` macros.
+
+Define `TWO_SIDED` before including to light faces pointing away from a light as if their normal was flipped (for open surfaces lit per vertex, where the camera side isn't known yet).
+*/
+pub const SHADER: &str = r#"
+    #ifndef MAX_LIGHTS
+    #define MAX_LIGHTS 4
+    #endif
+
+    // Lighting settings
+    #ifdef UNIFORM_BUFFERS
+    // highp so the vertex and fragment declarations match
+    layout(std140) uniform Lighting {
+        highp vec4 uAmbientLight;
+        // x: light count, y: 1 when lights follow the camera
+        highp vec4 uLightSettings;
+        highp vec4 uLightPositions[MAX_LIGHTS];
+        highp vec4 uLightColors[MAX_LIGHTS];
+    };
+    uniform highp mat4 uView;
+
+    #define AMBIENT_LIGHT_COLOR uAmbientLight.rgb
+    #define LIGHT_COUNT int(uLightSettings.x)
+    #define LIGHT_POSITION(i) (uLightSettings.y > 0.5 ? uLightPositions[i] : uView * uLightPositions[i])
+    #define LIGHT_COLOR(i) uLightColors[i].rgb
+    #else
+    uniform vec3 uAmbientLightColor;
+    uniform int uLightCount;
+    uniform vec4 uLightPosition[MAX_LIGHTS];
+    uniform vec3 uLightColor[MAX_LIGHTS];
+
+    #define AMBIENT_LIGHT_COLOR uAmbientLightColor
+    #define LIGHT_COUNT uLightCount
+    #define LIGHT_POSITION(i) uLightPosition[i]
+    #define LIGHT_COLOR(i) uLightColor[i]
+    #endif
+
+    // Material settings, precision is explicit so fragment shaders can declare them too (uniforms shared by both stages must match)
+    uniform mediump float uAmbient;
+    uniform mediump float uDiffuse;
+    uniform mediump float uSpecular;
+    uniform mediump float uShininess;
+    uniform mediump vec3 uEmissive;
+
+    // Arrays can only be indexed by the loop counter in fragment shaders, so the light is passed in rather than its index
+    vec3 lightDirection(vec4 light, vec3 position) {
+        return normalize(light.xyz - light.w * position);
+    }
+
+    float facing(vec3 normal, vec3 direction) {
+    #ifdef TWO_SIDED
+        return abs(dot(normal, direction));
+    #else
+        return max(dot(normal, direction), 0.0);
+    #endif
+    }
+
+    vec3 ambientLighting() {
+        return uAmbient * AMBIENT_LIGHT_COLOR;
+    }
+
+    vec3 diffuseLighting(vec3 normal, vec3 position) {
+        vec3 light = vec3(0.0);
+        // Loops need a constant bound in GLSL ES 1.0
+        for (int i = 0; i < MAX_LIGHTS; i++) {
+            if (i >= LIGHT_COUNT) {
+                break;
+            }
+            light += uDiffuse * facing(normal, lightDirection(LIGHT_POSITION(i), position)) * LIGHT_COLOR(i);
+        }
+        return light;
+    }
+
+    // Blinn-Phong highlight, the camera sits at the origin so the direction to it is -position
+    vec3 specularLighting(vec3 normal, vec3 position) {
+        vec3 viewDirection = normalize(-position);
+        vec3 light = vec3(0.0);
+        for (int i = 0; i < MAX_LIGHTS; i++) {
+            if (i >= LIGHT_COUNT) {
+                break;
+            }
+            vec3 direction = lightDirection(LIGHT_POSITION(i), position);
+            // No highlight on the side facing away from the light
+            if (dot(normal, direction) > 0.0) {
+                vec3 halfway = normalize(direction + viewDirection);
+                light += uSpecular * pow(max(dot(normal, halfway), 0.0), uShininess) * LIGHT_COLOR(i);
+            }
+        }
+        return light;
+    }
+"#;
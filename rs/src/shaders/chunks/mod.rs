@@ -0,0 +1,17 @@
+pub mod color_map;
+pub mod expression;
+pub mod lighting;
+pub mod surface_function;
+
+/**
+ * Looks up a chunk by the name used in `#include "name"`.
+ */
+pub fn get(name: &str) -> Option<&'static str> {
+    match name {
+        "color_map" => Some(color_map::SHADER),
+        "expression" => Some(expression::SHADER),
+        "lighting" => Some(lighting::SHADER),
+        "surface_function" => Some(surface_function::SHADER),
+        _ => None,
+    }
+}
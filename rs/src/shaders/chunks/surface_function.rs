@@ -0,0 +1,51 @@
+/*
+A surface z = f(x, y, t) evaluated in the vertex shader. The expression is compiled to GLSL and passed in through defines:
+
+- `SURFACE_HEIGHT` is f in terms of `x`, `y` and `t`
+- with `ANALYTIC_NORMALS`, `SURFACE_DZ_DX` and `SURFACE_DZ_DY` are its partial derivatives, otherwise they are estimated from central differences `uGridStep` apart (the grid spacing, like `Surface` does on the CPU)
+*/
+pub const SHADER: &str = r#"
+    #include "expression"
+
+    float surfaceHeight(float x, float y, float t) {
+        return SURFACE_HEIGHT;
+    }
+
+    #ifdef ANALYTIC_NORMALS
+    vec2 surfaceGradient(float x, float y, float t) {
+        return vec2(SURFACE_DZ_DX, SURFACE_DZ_DY);
+    }
+    #else
+    uniform vec2 uGridStep;
+
+    vec2 surfaceGradient(float x, float y, float t) {
+        return vec2(
+            surfaceHeight(x + uGridStep.x, y, t) - surfaceHeight(x - uGridStep.x, y, t),
+            surfaceHeight(x, y + uGridStep.y, t) - surfaceHeight(x, y - uGridStep.y, t)
+        ) / (2.0 * uGridStep);
+    }
+    #endif
+
+    // Grid positions from `get_position_grid_n_by_n` run over [-1, 1] in x and z
+    uniform vec2 uXRange;
+    uniform vec2 uYRange;
+    uniform float uTime;
+    // Range of values mapped onto the color map
+    uniform vec2 uValueRange;
+
+    vec2 surfaceDomainPosition(vec3 gridPosition) {
+        vec2 fraction = (gridPosition.xz + 1.0) / 2.0;
+        return vec2(mix(uXRange.x, uXRange.y, fraction.x), mix(uYRange.x, uYRange.y, fraction.y));
+    }
+
+    // The value to color by, normalized onto [0, 1] and clamped
+    float surfaceScalar(float z, vec2 gradient) {
+        #ifdef COLOR_BY_SLOPE
+        float value = length(gradient);
+        #else
+        float value = z;
+        #endif
+        float range = uValueRange.y - uValueRange.x;
+        return range > 0.0 ? clamp((value - uValueRange.x) / range, 0.0, 1.0) : 0.5;
+    }
+"#;
@@ -0,0 +1,26 @@
+/*
+Blinn-Phong lighting evaluated per fragment. See: https://learnopengl.com/Advanced-Lighting/Advanced-Lighting
+
+The interpolated normal has to be normalized again, a blend of two unit vectors is shorter than one.
+*/
+pub const SHADER: &str = r#"
+  precision mediump float;
+
+  #include "lighting"
+
+  uniform vec3 uMaterialColor;
+  uniform float uOpacity;
+
+  varying mediump vec3 vPosition;
+  varying mediump vec3 vNormal;
+
+  void main() {
+    vec3 normal = normalize(vNormal);
+
+    vec3 color = uEmissive;
+    color += (ambientLighting() + diffuseLighting(normal, vPosition)) * uMaterialColor;
+    color += specularLighting(normal, vPosition);
+
+    gl_FragColor = vec4(color, uOpacity);
+  }
+"#;
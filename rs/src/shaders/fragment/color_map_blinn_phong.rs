@@ -0,0 +1,28 @@
+/*
+`blinn_phong` with the material color looked up in a color map, like `color_map_lit`.
+
+Surfaces are open, so a fragment on the back side flips its normal towards the camera before lighting.
+*/
+pub const SHADER: &str = r#"
+  precision mediump float;
+
+  #include "lighting"
+  #include "color_map"
+
+  uniform float uOpacity;
+
+  varying mediump vec3 vPosition;
+  varying mediump vec3 vNormal;
+  varying mediump float vScalar;
+
+  void main() {
+    vec3 normal = normalize(vNormal);
+    normal = dot(normal, -vPosition) < 0.0 ? -normal : normal;
+
+    vec3 color = uEmissive;
+    color += (ambientLighting() + diffuseLighting(normal, vPosition)) * colorMap(vScalar);
+    color += specularLighting(normal, vPosition);
+
+    gl_FragColor = vec4(color, uOpacity);
+  }
+"#;
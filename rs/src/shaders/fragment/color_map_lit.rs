@@ -0,0 +1,14 @@
+/*
+Looks the color up in a color map texture and applies the lighting computed by the vertex shader on top.
+*/
+pub const SHADER: &str = r#"
+  precision mediump float;
+  #include "color_map"
+  uniform vec3 uEmissive;
+  uniform float uOpacity;
+  varying lowp vec3 vLighting;
+  varying mediump float vScalar;
+  void main() {
+    gl_FragColor = vec4(uEmissive + colorMap(vScalar) * vLighting, uOpacity);
+  }
+"#;
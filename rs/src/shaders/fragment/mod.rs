@@ -1 +1,5 @@
+pub mod blinn_phong;
+pub mod color_map_blinn_phong;
+pub mod color_map_lit;
+pub mod sdf_text;
 pub mod vary_color_from_vertex;
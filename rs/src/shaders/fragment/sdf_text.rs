@@ -0,0 +1,16 @@
+/*
+Draws glyphs from a signed distance field atlas. A value of 0.5 is the outline of the glyph, `vSmoothing` is about half a screen pixel in distance field units which anti-aliases the edge.
+*/
+pub const SHADER: &str = r#"
+  precision mediump float;
+  uniform sampler2D uGlyphAtlas;
+  uniform float uOpacity;
+  varying highp vec2 vTextureCoord;
+  varying lowp vec4 vColor;
+  varying mediump float vSmoothing;
+  void main() {
+    float distance = texture2D(uGlyphAtlas, vTextureCoord).a;
+    float alpha = smoothstep(0.5 - vSmoothing, 0.5 + vSmoothing, distance);
+    gl_FragColor = vec4(vColor.rgb, vColor.a * alpha * uOpacity);
+  }
+"#;
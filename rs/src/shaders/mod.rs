@@ -1,2 +1,4 @@
+pub mod chunks;
 pub mod fragment;
+pub mod preprocessor;
 pub mod vertex;
@@ -0,0 +1,299 @@
+use super::chunks;
+
+/*
+A tiny preprocessor run before sources are handed to WebGL, which has no way to share code between shaders.
+
+- `#include "name"` on a line of its own is replaced by the chunk `name` (see `chunks::get`), chunks may include other chunks
+- `defines` are prepended as `#define NAME VALUE` so shaders can use them as feature flags with `#ifdef`
+
+Everything else, including the regular GLSL `#define`s and `#ifdef`s, is left to the GLSL compiler.
+*/
+pub fn preprocess(source: &str, defines: &[(&str, &str)]) -> Result<String, String> {
+    let mut output = String::new();
+    for (name, value) in defines {
+        output.push_str(&format!("#define {} {}\n", name, value));
+    }
+
+    expand_includes(source, &|name| chunks::get(name), &mut output, &mut Vec::new())?;
+
+    Ok(output)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stage {
+    Vertex,
+    Fragment,
+}
+
+/**
+ * Rewrites a preprocessed GLSL ES 1.0 shader as GLSL ES 3.0, so WebGL2 only features (uniform blocks) can be used without keeping a second copy of every shader.
+ *
+ * Only the handful of keywords which changed are renamed, whole words only:
+ *
+ * - `attribute` becomes `in`, `varying` becomes `out` in vertex shaders and `in` in fragment shaders
+ * - `texture2D` becomes `texture`
+ * - `gl_FragColor` is gone, fragment shaders write to a declared `out` instead
+ */
+pub fn to_glsl_300_es(source: &str, stage: Stage) -> String {
+    let mut renames = vec![("texture2D", "texture")];
+    match stage {
+        Stage::Vertex => {
+            renames.push(("attribute", "in"));
+            renames.push(("varying", "out"));
+        }
+        Stage::Fragment => {
+            renames.push(("varying", "in"));
+            renames.push(("gl_FragColor", "fragColor"));
+        }
+    }
+
+    // `#version` has to be the very first line
+    let mut output = String::from("#version 300 es\n");
+    if stage == Stage::Fragment {
+        output.push_str("out mediump vec4 fragColor;\n");
+    }
+    output.push_str(&rename_words(source, &renames));
+
+    output
+}
+
+/*
+Replaces identifiers found in `renames`, leaving identifiers which only contain one (`aTexture2DCoord`) alone.
+*/
+fn rename_words(source: &str, renames: &[(&str, &str)]) -> String {
+    let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut output = String::with_capacity(source.len());
+    let mut word = String::new();
+    for c in source.chars() {
+        if is_identifier(c) {
+            word.push(c);
+            continue;
+        }
+        push_renamed(&mut output, &word, renames);
+        word.clear();
+        output.push(c);
+    }
+    push_renamed(&mut output, &word, renames);
+
+    output
+}
+
+fn push_renamed(output: &mut String, word: &str, renames: &[(&str, &str)]) {
+    match renames.iter().find(|(from, _)| *from == word) {
+        Some((_, to)) => output.push_str(to),
+        None => output.push_str(word),
+    }
+}
+
+/*
+Appends `source` to `output` with its includes expanded, looking chunks up with `chunk`. `stack` holds the chunks being expanded, to catch chunks which end up including themselves.
+*/
+fn expand_includes<'a>(
+    source: &'a str,
+    chunk: &impl Fn(&str) -> Option<&'a str>,
+    output: &mut String,
+    stack: &mut Vec<&'a str>,
+) -> Result<(), String> {
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if let Some(rest) = trimmed.strip_prefix("#include") {
+            let name = rest.trim().trim_matches('"');
+            if stack.contains(&name) {
+                return Err(format!("Shader chunk \"{}\" includes itself", name));
+            }
+            let included = chunk(name).ok_or_else(|| format!("Unknown shader chunk \"{}\"", name))?;
+
+            stack.push(name);
+            expand_includes(included, chunk, output, stack)?;
+            stack.pop();
+        } else {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Formats a compile error: the info log followed by the (preprocessed) source with line numbers, lines the log complains about are marked with ">".
+ *
+ * Logs look like "ERROR: 0:12: 'foo' : undeclared identifier", the number after the source index is the line.
+ */
+pub fn annotate_errors(source: &str, log: &str) -> String {
+    let error_lines: Vec<usize> = log
+        .lines()
+        .filter_map(|line| line.split(':').nth(2))
+        .filter_map(|number| number.trim().parse().ok())
+        .collect();
+
+    let mut annotated = format!("{}\n", log.trim_end());
+    push_numbered(&mut annotated, source, |number, _| error_lines.contains(&number));
+    annotated
+}
+
+/**
+ * Formats a link error: the info log followed by both (preprocessed) sources with line numbers.
+ *
+ * Link logs name what doesn't match between the shaders rather than a line, like "Varying 'vNormal' has different types", so lines using a name the log quotes are marked with ">".
+ */
+pub fn annotate_link_errors(vert_source: &str, frag_source: &str, log: &str) -> String {
+    let names: Vec<&str> = log
+        .split(['\'', '"', '`'])
+        .skip(1)
+        .step_by(2)
+        .filter(|name| !name.is_empty())
+        .collect();
+    let mentions_name = |_: usize, line: &str| {
+        names.iter().any(|name| {
+            line.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .any(|word| word == *name)
+        })
+    };
+
+    let mut annotated = format!("{}\nVertex shader:\n", log.trim_end());
+    push_numbered(&mut annotated, vert_source, mentions_name);
+    annotated.push_str("Fragment shader:\n");
+    push_numbered(&mut annotated, frag_source, mentions_name);
+    annotated
+}
+
+/*
+Appends `source` with line numbers, lines `marked` is true for (given their number and text) start with ">".
+*/
+fn push_numbered(output: &mut String, source: &str, marked: impl Fn(usize, &str) -> bool) {
+    let width = source.lines().count().to_string().len();
+    for (i, line) in source.lines().enumerate() {
+        let number = i + 1;
+        let marker = if marked(number, line) { ">" } else { " " };
+        output.push_str(&format!("{} {:>width$} | {}\n", marker, number, line, width = width));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(name: &str) -> Option<&'static str> {
+        match name {
+            "leaf" => Some("float leaf() { return 1.0; }"),
+            "branch" => Some("#include \"leaf\"\nfloat branch() { return leaf(); }"),
+            "loop_a" => Some("#include \"loop_b\""),
+            "loop_b" => Some("  #include \"loop_a\""),
+            "self" => Some("#include \"self\""),
+            _ => None,
+        }
+    }
+
+    fn expand(source: &str) -> Result<String, String> {
+        let mut output = String::new();
+        expand_includes(source, &|name| chunk(name), &mut output, &mut Vec::new())?;
+        Ok(output)
+    }
+
+    #[test]
+    fn defines_come_first() {
+        let output = preprocess("void main() {}", &[("MAX_LIGHTS", "4"), ("UNIFORM_BUFFERS", "1")]).unwrap();
+        assert_eq!(output, "#define MAX_LIGHTS 4\n#define UNIFORM_BUFFERS 1\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn includes_expand_in_place_and_nest() {
+        let output = expand("precision mediump float;\n  #include \"branch\"\nvoid main() {}").unwrap();
+        assert_eq!(
+            output,
+            "precision mediump float;\nfloat leaf() { return 1.0; }\nfloat branch() { return leaf(); }\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn a_chunk_can_be_included_twice_side_by_side() {
+        // Only chunks including themselves are errors, not repeats
+        assert_eq!(expand("#include \"leaf\"\n#include \"leaf\"").unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        assert_eq!(expand("#include \"self\"").unwrap_err(), "Shader chunk \"self\" includes itself");
+        assert_eq!(expand("#include \"loop_a\"").unwrap_err(), "Shader chunk \"loop_a\" includes itself");
+    }
+
+    #[test]
+    fn unknown_chunks_are_errors() {
+        assert_eq!(expand("#include \"missing\"").unwrap_err(), "Unknown shader chunk \"missing\"");
+    }
+
+    #[test]
+    fn real_chunks_expand() {
+        let output = preprocess("#include \"color_map\"\n#include \"lighting\"", &[]).unwrap();
+        assert!(!output.contains("#include"));
+        assert!(output.contains(chunks::get("color_map").unwrap().lines().next().unwrap()));
+    }
+
+    #[test]
+    fn vertex_shaders_become_glsl_300_es() {
+        let source = "attribute vec3 aVertexPosition;\nvarying vec2 vUv;\nvoid main() { vUv = texture2D(uMap, aVertexPosition.xy).xy; }\n";
+        assert_eq!(
+            to_glsl_300_es(source, Stage::Vertex),
+            "#version 300 es\nin vec3 aVertexPosition;\nout vec2 vUv;\nvoid main() { vUv = texture(uMap, aVertexPosition.xy).xy; }\n"
+        );
+    }
+
+    #[test]
+    fn fragment_shaders_become_glsl_300_es() {
+        let source = "varying vec2 vUv;\nvoid main() { gl_FragColor = texture2D(uMap, vUv); }";
+        assert_eq!(
+            to_glsl_300_es(source, Stage::Fragment),
+            "#version 300 es\nout mediump vec4 fragColor;\nin vec2 vUv;\nvoid main() { fragColor = texture(uMap, vUv); }"
+        );
+    }
+
+    #[test]
+    fn only_whole_words_are_renamed() {
+        let renames = [("varying", "in"), ("texture2D", "texture")];
+        assert_eq!(
+            rename_words("aTexture2DCoord texture2DLod varyingColor my_varying varying", &renames),
+            "aTexture2DCoord texture2DLod varyingColor my_varying in"
+        );
+        // At the very start and end, and next to punctuation
+        assert_eq!(rename_words("texture2D(a);texture2D", &renames), "texture(a);texture");
+        assert_eq!(rename_words("", &renames), "");
+    }
+
+    #[test]
+    fn compile_errors_mark_their_lines() {
+        let source = (1..=10).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n");
+        let log = "ERROR: 0:3: 'foo' : undeclared identifier\nERROR: 0:10: '' : syntax error\n";
+        let annotated = annotate_errors(&source, log);
+        let lines: Vec<&str> = annotated.lines().collect();
+
+        assert_eq!(&lines[..2], &["ERROR: 0:3: 'foo' : undeclared identifier", "ERROR: 0:10: '' : syntax error"]);
+        assert_eq!(lines[2], "   1 | line 1");
+        assert_eq!(lines[4], ">  3 | line 3");
+        assert_eq!(lines[11], "> 10 | line 10");
+        assert_eq!(lines.iter().filter(|line| line.starts_with('>')).count(), 2);
+    }
+
+    #[test]
+    fn link_errors_show_both_shaders_and_mark_quoted_names() {
+        let vert = "varying vec3 vNormal;\nvoid main() {}";
+        let frag = "varying vec4 vNormal;\nvarying vec4 vNormalScaled;\nvoid main() {}";
+        let annotated = annotate_link_errors(vert, frag, "Varying 'vNormal' has different types\n");
+        let lines: Vec<&str> = annotated.lines().collect();
+
+        assert_eq!(
+            lines,
+            [
+                "Varying 'vNormal' has different types",
+                "Vertex shader:",
+                "> 1 | varying vec3 vNormal;",
+                "  2 | void main() {}",
+                "Fragment shader:",
+                "> 1 | varying vec4 vNormal;",
+                "  2 | varying vec4 vNormalScaled;",
+                "  3 | void main() {}",
+            ]
+        );
+    }
+}
@@ -0,0 +1,18 @@
+/*
+Unlit shader where every vertex carries its own color. Used for lines (axes, ticks, grids) which have no meaningful normal to light with.
+*/
+pub const SHADER: &str = r#"
+    // Transformations
+    uniform mat4 uModelViewProjection;
+
+    // Vertex data
+    attribute vec3 aVertexPosition;
+    attribute vec3 aVertexColor;
+
+    varying lowp vec4 vColor;
+
+    void main() {
+        gl_Position = uModelViewProjection * vec4(aVertexPosition, 1.0);
+        vColor = vec4(aVertexColor, 1.0);
+    }
+"#;
@@ -1,2 +1,10 @@
+pub mod axes_3d;
 pub mod graph_3d;
+pub mod overlay_2d;
 pub mod sphere_3d;
+pub mod sphere_3d_phong;
+pub mod surface_3d;
+pub mod surface_3d_gpu;
+pub mod surface_3d_gpu_phong;
+pub mod surface_3d_phong;
+pub mod text_3d;
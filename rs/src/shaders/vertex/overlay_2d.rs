@@ -0,0 +1,18 @@
+/*
+Flat colored shapes positioned in pixels, used for overlays drawn on top of the scene (colorbar, legend).
+*/
+pub const SHADER: &str = r#"
+    // Canvas size in pixels
+    uniform vec2 uResolution;
+
+    // Vertex data
+    attribute vec2 aVertexPosition;
+    attribute vec3 aVertexColor;
+
+    varying lowp vec4 vColor;
+
+    void main() {
+        gl_Position = vec4(aVertexPosition / uResolution * 2.0 - 1.0, 0.0, 1.0);
+        vColor = vec4(aVertexColor, 1.0);
+    }
+"#;
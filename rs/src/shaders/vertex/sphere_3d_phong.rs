@@ -0,0 +1,26 @@
+/*
+Per fragment version of `sphere_3d`. The lighting moves to the fragment shader (see `blinn_phong`) so only the view space position and normal are passed along, to be interpolated across each triangle.
+*/
+pub const SHADER: &str = r#"
+    // Transformations
+    uniform mat4 uModelView;
+    uniform mat3 uNormalMatrix;
+    uniform mat4 uModelViewProjection;
+
+    // Vertex data
+    attribute vec3 aVertexPosition;
+    attribute vec3 aVertexNormal;
+    // Where this copy of the sphere sits in model space, one value per instance
+    attribute vec3 aInstanceOffset;
+
+    varying mediump vec3 vPosition;
+    varying mediump vec3 vNormal;
+
+    void main() {
+        vec4 vertexPosition = vec4(aVertexPosition + aInstanceOffset, 1.0);
+        gl_Position = uModelViewProjection * vertexPosition;
+
+        vPosition = vec3(uModelView * vertexPosition);
+        vNormal = uNormalMatrix * aVertexNormal;
+    }
+"#;
@@ -0,0 +1,34 @@
+/*
+Same lighting as `sphere_3d` but the material color comes from a color map in the fragment shader, so only the light reaching the vertex is passed along.
+
+Surfaces are open, so both sides can face the camera. `TWO_SIDED` lights the back side as if its normal was flipped.
+*/
+pub const SHADER: &str = r#"
+    #define TWO_SIDED
+    #include "lighting"
+
+    // Transformations
+    uniform mat4 uModelView;
+    // Inverse transpose of the model view, see `math::get_normal_matrix`
+    uniform mat3 uNormalMatrix;
+    uniform mat4 uModelViewProjection;
+
+    // Vertex data
+    attribute vec3 aVertexPosition;
+    attribute vec3 aVertexNormal;
+    // Value to color by, normalized onto [0, 1]
+    attribute float aScalar;
+
+    varying lowp vec3 vLighting;
+    varying mediump float vScalar;
+
+    void main() {
+        gl_Position = uModelViewProjection * vec4(aVertexPosition, 1.0);
+
+        vec3 transformedNormal = normalize(uNormalMatrix * aVertexNormal);
+        vec3 position = vec3(uModelView * vec4(aVertexPosition, 1.0));
+
+        vLighting = ambientLighting() + diffuseLighting(transformedNormal, position);
+        vScalar = aScalar;
+    }
+"#;
@@ -0,0 +1,35 @@
+/*
+`surface_3d` with the surface computed here instead of on the CPU. `aVertexPosition` is a point on the flat grid, which is moved onto the surface (see the "surface_function" chunk).
+*/
+pub const SHADER: &str = r#"
+    #define TWO_SIDED
+    #include "lighting"
+    #include "surface_function"
+
+    // Transformations
+    uniform mat4 uModelView;
+    uniform mat3 uNormalMatrix;
+    uniform mat4 uModelViewProjection;
+
+    // Vertex data
+    attribute vec3 aVertexPosition;
+
+    varying lowp vec3 vLighting;
+    varying mediump float vScalar;
+
+    void main() {
+        vec2 xy = surfaceDomainPosition(aVertexPosition);
+        float z = surfaceHeight(xy.x, xy.y, uTime);
+        vec2 gradient = surfaceGradient(xy.x, xy.y, uTime);
+        vec4 surfacePosition = vec4(xy, z, 1.0);
+
+        gl_Position = uModelViewProjection * surfacePosition;
+
+        // The normal of z = f(x, y) is (-df/dx, -df/dy, 1)
+        vec3 transformedNormal = normalize(uNormalMatrix * vec3(-gradient, 1.0));
+        vec3 position = vec3(uModelView * surfacePosition);
+
+        vLighting = ambientLighting() + diffuseLighting(transformedNormal, position);
+        vScalar = surfaceScalar(z, gradient);
+    }
+"#;
@@ -0,0 +1,31 @@
+/*
+Per fragment version of `surface_3d_gpu`, pairs with `color_map_blinn_phong`.
+*/
+pub const SHADER: &str = r#"
+    #include "surface_function"
+
+    // Transformations
+    uniform mat4 uModelView;
+    uniform mat3 uNormalMatrix;
+    uniform mat4 uModelViewProjection;
+
+    // Vertex data
+    attribute vec3 aVertexPosition;
+
+    varying mediump vec3 vPosition;
+    varying mediump vec3 vNormal;
+    varying mediump float vScalar;
+
+    void main() {
+        vec2 xy = surfaceDomainPosition(aVertexPosition);
+        float z = surfaceHeight(xy.x, xy.y, uTime);
+        vec2 gradient = surfaceGradient(xy.x, xy.y, uTime);
+        vec4 surfacePosition = vec4(xy, z, 1.0);
+
+        gl_Position = uModelViewProjection * surfacePosition;
+
+        vPosition = vec3(uModelView * surfacePosition);
+        vNormal = uNormalMatrix * vec3(-gradient, 1.0);
+        vScalar = surfaceScalar(z, gradient);
+    }
+"#;
@@ -0,0 +1,27 @@
+/*
+Per fragment version of `surface_3d`, pairs with `color_map_blinn_phong`.
+*/
+pub const SHADER: &str = r#"
+    // Transformations
+    uniform mat4 uModelView;
+    uniform mat3 uNormalMatrix;
+    uniform mat4 uModelViewProjection;
+
+    // Vertex data
+    attribute vec3 aVertexPosition;
+    attribute vec3 aVertexNormal;
+    // Value to color by, normalized onto [0, 1]
+    attribute float aScalar;
+
+    varying mediump vec3 vPosition;
+    varying mediump vec3 vNormal;
+    varying mediump float vScalar;
+
+    void main() {
+        gl_Position = uModelViewProjection * vec4(aVertexPosition, 1.0);
+
+        vPosition = vec3(uModelView * vec4(aVertexPosition, 1.0));
+        vNormal = uNormalMatrix * aVertexNormal;
+        vScalar = aScalar;
+    }
+"#;
@@ -0,0 +1,25 @@
+/*
+Labels are laid out on the CPU in pixels (after projecting their anchors), so this only needs to convert pixels into clip space.
+*/
+pub const SHADER: &str = r#"
+    // Canvas size in pixels
+    uniform vec2 uResolution;
+
+    // Vertex data
+    attribute vec2 aVertexPosition;
+    attribute vec2 aTextureCoord;
+    attribute vec3 aVertexColor;
+    attribute float aSmoothing;
+
+    varying highp vec2 vTextureCoord;
+    varying lowp vec4 vColor;
+    varying mediump float vSmoothing;
+
+    void main() {
+        gl_Position = vec4(aVertexPosition / uResolution * 2.0 - 1.0, 0.0, 1.0);
+
+        vTextureCoord = aTextureCoord;
+        vColor = vec4(aVertexColor, 1.0);
+        vSmoothing = aSmoothing;
+    }
+"#;
@@ -0,0 +1,238 @@
+use super::ticks::{nice_ticks, Ticks};
+use nalgebra::Vector3;
+
+// Roughly how many major ticks to place along each axis
+const TARGET_TICK_COUNT: usize = 6;
+
+// Sizes relative to the largest extent of the data range
+const MAJOR_TICK_SIZE: f32 = 0.03;
+const MINOR_TICK_SIZE: f32 = 0.015;
+const ARROW_LENGTH: f32 = 0.06;
+const ARROW_RADIUS: f32 = 0.015;
+const ARROW_SEGMENTS: u16 = 12;
+
+const AXIS_COLORS: [[f32; 3]; 3] = [
+    [0.9, 0.3, 0.3], // X: Red-ish
+    [0.3, 0.9, 0.3], // Y: Green-ish
+    [0.3, 0.5, 0.9], // Z: Blue-ish
+];
+const GRID_COLOR: [f32; 3] = [0.3, 0.3, 0.3];
+
+pub const AXIS_NAMES: [&str; 3] = ["x", "y", "z"];
+
+#[derive(Clone)]
+pub struct Axes {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+    // Where the three axes cross
+    pub origin: Vector3<f32>,
+    pub ticks: [Ticks; 3],
+    pub vertices: Vec<f32>,
+    pub colors: Vec<f32>,
+    pub line_indices: Vec<u16>,
+    pub arrow_indices: Vec<u16>,
+    pub grid_indices: Vec<u16>,
+    /*
+    (offset, count) into `grid_indices` for the grid plane perpendicular to each axis.
+
+    Index 0 is the plane on the min side of the axis and index 1 is the plane on the max side.
+    */
+    pub grid_ranges: [[(usize, usize); 2]; 3],
+}
+
+impl Axes {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        // Keep min / max in order so the rest of the math can assume it
+        let (min, max) = (min.inf(&max), min.sup(&max));
+
+        let ticks = [
+            nice_ticks(min.x, max.x, TARGET_TICK_COUNT),
+            nice_ticks(min.y, max.y, TARGET_TICK_COUNT),
+            nice_ticks(min.z, max.z, TARGET_TICK_COUNT),
+        ];
+
+        // Axes pass through zero when it is in range, otherwise they hug the closest side
+        let origin = Vector3::zeros().sup(&min).inf(&max);
+
+        let mut value = Self {
+            min,
+            max,
+            origin,
+            ticks,
+            vertices: Vec::new(),
+            colors: Vec::new(),
+            line_indices: Vec::new(),
+            arrow_indices: Vec::new(),
+            grid_indices: Vec::new(),
+            grid_ranges: [[(0, 0); 2]; 3],
+        };
+
+        value.recalculate();
+
+        value
+    }
+
+    /**
+     * Which side the grid plane perpendicular to `axis` should be drawn on so it sits behind the data from the camera's point of view.
+     *
+     * Returns the index into `grid_ranges[axis]`.
+     */
+    pub fn back_side(&self, axis: usize, camera_position: &Vector3<f32>) -> usize {
+        let center = (self.min[axis] + self.max[axis]) / 2.;
+        if camera_position[axis] > center {
+            0
+        } else {
+            1
+        }
+    }
+
+    /**
+     * Just past the tip of the arrow on the end of `axis`, where the axis name goes.
+     */
+    pub fn name_position(&self, axis: usize) -> Vector3<f32> {
+        let mut position = self.origin;
+        position[axis] = self.max[axis] + 2. * ARROW_LENGTH * self.extent();
+        position
+    }
+
+    /**
+     * Beside the major tick at `value` on `axis`, opposite to the side the tick marks stick out of.
+     */
+    pub fn tick_label_position(&self, axis: usize, value: f32) -> Vector3<f32> {
+        let mut position = self.origin;
+        position[axis] = value;
+        position[(axis + 1) % 3] -= 3. * MAJOR_TICK_SIZE * self.extent();
+        position
+    }
+
+    pub fn axis_color(&self, axis: usize) -> [f32; 3] {
+        AXIS_COLORS[axis]
+    }
+
+    fn extent(&self) -> f32 {
+        (self.max - self.min).max().max(f32::EPSILON)
+    }
+
+    fn push_vertex(&mut self, position: Vector3<f32>, color: [f32; 3]) -> u16 {
+        let index = (self.vertices.len() / 3) as u16;
+        self.vertices.extend_from_slice(position.as_slice());
+        self.colors.extend_from_slice(&color);
+        index
+    }
+
+    fn push_line(&mut self, start: Vector3<f32>, end: Vector3<f32>, color: [f32; 3]) {
+        let start = self.push_vertex(start, color);
+        let end = self.push_vertex(end, color);
+        self.line_indices.push(start);
+        self.line_indices.push(end);
+    }
+
+    fn recalculate(&mut self) {
+        let extent = self.extent();
+
+        for axis in 0..3 {
+            let color = AXIS_COLORS[axis];
+            let direction = Vector3::ith(axis, 1.);
+            // The tick marks stick out along the "next" axis
+            let tick_direction = Vector3::ith((axis + 1) % 3, 1.);
+
+            // Main axis line, running from the min side to the max side
+            let mut start = self.origin;
+            start[axis] = self.min[axis];
+            let mut end = self.origin;
+            end[axis] = self.max[axis];
+            self.push_line(start, end, color);
+
+            // Major and minor ticks
+            for (values, size) in [
+                (self.ticks[axis].major.clone(), MAJOR_TICK_SIZE),
+                (self.ticks[axis].minor.clone(), MINOR_TICK_SIZE),
+            ] {
+                for value in values {
+                    let mut center = self.origin;
+                    center[axis] = value;
+                    let offset = tick_direction * size * extent;
+                    self.push_line(center - offset, center + offset, color);
+                }
+            }
+
+            self.push_arrow(end, direction, extent, color);
+        }
+
+        for axis in 0..3 {
+            for side in 0..2 {
+                let offset = self.grid_indices.len();
+                self.push_grid(axis, side);
+                self.grid_ranges[axis][side] = (offset, self.grid_indices.len() - offset);
+            }
+        }
+    }
+
+    /*
+    Cone at the end of an axis.
+
+    The base circle is built from two vectors `u` and `v` where u x v = direction. Walking the circle from u towards v keeps the side triangles counter clockwise when seen from outside the cone (required for culling).
+    */
+    fn push_arrow(&mut self, base: Vector3<f32>, direction: Vector3<f32>, extent: f32, color: [f32; 3]) {
+        let u = Vector3::ith(
+            (direction.iamax() + 1) % 3,
+            1.,
+        );
+        let v = direction.cross(&u);
+
+        let radius = ARROW_RADIUS * extent;
+        let tip = self.push_vertex(base + direction * ARROW_LENGTH * extent, color);
+        let center = self.push_vertex(base, color);
+
+        let first = (self.vertices.len() / 3) as u16;
+        for i in 0..ARROW_SEGMENTS {
+            let angle = 2. * std::f32::consts::PI * i as f32 / ARROW_SEGMENTS as f32;
+            self.push_vertex(base + (u * angle.cos() + v * angle.sin()) * radius, color);
+        }
+
+        for i in 0..ARROW_SEGMENTS {
+            let current = first + i;
+            let next = first + (i + 1) % ARROW_SEGMENTS;
+
+            // Side
+            self.arrow_indices.push(current);
+            self.arrow_indices.push(next);
+            self.arrow_indices.push(tip);
+
+            // Base cap (faces backwards along the axis)
+            self.arrow_indices.push(next);
+            self.arrow_indices.push(current);
+            self.arrow_indices.push(center);
+        }
+    }
+
+    /*
+    Lines on the plane perpendicular to `axis` at the major ticks of the two other axes.
+    */
+    fn push_grid(&mut self, axis: usize, side: usize) {
+        let plane_value = if side == 0 {
+            self.min[axis]
+        } else {
+            self.max[axis]
+        };
+
+        for i in 1..3 {
+            let line_axis = (axis + i) % 3;
+            let across_axis = (axis + 3 - i) % 3;
+
+            for value in self.ticks[across_axis].major.clone() {
+                let mut start = Vector3::zeros();
+                start[axis] = plane_value;
+                start[across_axis] = value;
+                let mut end = start;
+                start[line_axis] = self.min[line_axis];
+                end[line_axis] = self.max[line_axis];
+
+                let start = self.push_vertex(start, GRID_COLOR);
+                let end = self.push_vertex(end, GRID_COLOR);
+                self.grid_indices.push(start);
+                self.grid_indices.push(end);
+            }
+        }
+    }
+}
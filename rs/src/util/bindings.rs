@@ -0,0 +1,444 @@
+use std::marker::PhantomData;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use nalgebra::{Matrix3, Matrix4};
+
+/*
+Typed uniform and attribute bindings, checked against what the linked program actually uses.
+
+A program declares the variables it expects with `bindings!`:
+
+    bindings! {
+        struct AxesBindings {
+            mvp_transform: Uniform<Matrix4<f32>> = "uModelViewProjection",
+            opacity: Uniform<f32> = "uOpacity",
+            vertex_position: Attribute<[f32; 3]> = "aVertexPosition",
+        }
+    }
+
+`AxesBindings::new(gl, &program)` reflects the program's active uniforms and attributes and fails with a `MVisualError::Shader` naming the field when one is missing or has a different GLSL type than declared. Uploads then go through `Uniform::set`, which only accepts the declared Rust type.
+
+Compilers drop variables which don't affect the output, so a uniform some shader variants don't use is declared as an `OptionalUniform`, which is only checked when present. `OptionalAttribute` does the same for attributes.
+*/
+#[macro_export]
+macro_rules! bindings {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident: $type:ty = $glsl_name:literal,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: $type,
+            )*
+        }
+
+        impl $name {
+            pub fn new(
+                gl: &web_sys::WebGlRenderingContext,
+                program: &web_sys::WebGlProgram,
+            ) -> Result<Self, $crate::error::MVisualError> {
+                let reflection = $crate::util::bindings::Reflection::new(gl, program);
+                Ok(Self {
+                    $(
+                        $field: <$type as $crate::util::bindings::Binding>::bind(&reflection, $glsl_name).map_err(
+                            |error| $crate::error::MVisualError::Shader(format!(
+                                "{}::{}: {}",
+                                stringify!($name),
+                                stringify!($field),
+                                error,
+                            ))
+                        )?,
+                    )*
+                })
+            }
+        }
+    };
+}
+
+/*
+An active uniform or attribute as reported by the program, arrays are reported as "name[0]" and stored as "name".
+*/
+struct Variable {
+    name: String,
+    gl_type: u32,
+    is_array: bool,
+    uniform_location: Option<WebGlUniformLocation>,
+    attribute_location: i32,
+}
+
+/**
+ * Everything a linked program actually uses, looked up once for all of a bindings struct's fields.
+ */
+pub struct Reflection {
+    uniforms: Vec<Variable>,
+    attributes: Vec<Variable>,
+}
+
+impl Reflection {
+    pub fn new(gl: &WebGlRenderingContext, program: &WebGlProgram) -> Self {
+        let count = |parameter| gl.get_program_parameter(program, parameter).as_f64().unwrap_or(0.) as u32;
+
+        let uniforms = (0..count(GL::ACTIVE_UNIFORMS))
+            .filter_map(|index| gl.get_active_uniform(program, index))
+            .map(|info| {
+                let (name, is_array) = base_name(&info.name());
+                Variable {
+                    uniform_location: gl.get_uniform_location(program, &name),
+                    attribute_location: -1,
+                    name,
+                    gl_type: info.type_(),
+                    is_array,
+                }
+            })
+            .collect();
+
+        let attributes = (0..count(GL::ACTIVE_ATTRIBUTES))
+            .filter_map(|index| gl.get_active_attrib(program, index))
+            .map(|info| {
+                let (name, is_array) = base_name(&info.name());
+                Variable {
+                    uniform_location: None,
+                    attribute_location: gl.get_attrib_location(program, &name),
+                    name,
+                    gl_type: info.type_(),
+                    is_array,
+                }
+            })
+            .collect();
+
+        Self { uniforms, attributes }
+    }
+
+    fn uniform(&self, name: &str) -> Option<&Variable> {
+        self.uniforms.iter().find(|uniform| uniform.name == name)
+    }
+
+    fn attribute(&self, name: &str) -> Option<&Variable> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+}
+
+fn base_name(name: &str) -> (String, bool) {
+    match name.strip_suffix("[0]") {
+        Some(base) => (String::from(base), true),
+        None => (String::from(name), false),
+    }
+}
+
+fn check_type(kind: &str, variable: &Variable, expected: u32, is_array: bool) -> Result<(), String> {
+    if variable.gl_type != expected || variable.is_array != is_array {
+        let describe = |gl_type: u32, array: bool| {
+            format!("{}{}", type_name(gl_type), if array { "[]" } else { "" })
+        };
+        return Err(format!(
+            "{} {} is a {} in the shader but bound as a {}",
+            kind,
+            variable.name,
+            describe(variable.gl_type, variable.is_array),
+            describe(expected, is_array),
+        ));
+    }
+
+    Ok(())
+}
+
+fn type_name(gl_type: u32) -> &'static str {
+    match gl_type {
+        GL::FLOAT => "float",
+        GL::FLOAT_VEC2 => "vec2",
+        GL::FLOAT_VEC3 => "vec3",
+        GL::FLOAT_VEC4 => "vec4",
+        GL::INT => "int",
+        GL::BOOL => "bool",
+        GL::FLOAT_MAT3 => "mat3",
+        GL::FLOAT_MAT4 => "mat4",
+        GL::SAMPLER_2D => "sampler2D",
+        _ => "unsupported type",
+    }
+}
+
+/**
+ * A field of a `bindings!` struct, looked up by its GLSL name.
+ */
+pub trait Binding: Sized {
+    fn bind(reflection: &Reflection, name: &str) -> Result<Self, String>;
+}
+
+/**
+ * Rust types which can be uploaded to a uniform, with the GLSL type they match.
+ */
+pub trait UniformValue {
+    const GL_TYPE: u32;
+    const IS_ARRAY: bool = false;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>);
+}
+
+impl UniformValue for f32 {
+    const GL_TYPE: u32 = GL::FLOAT;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        gl.uniform1f(location, *self);
+    }
+}
+
+impl UniformValue for i32 {
+    const GL_TYPE: u32 = GL::INT;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        gl.uniform1i(location, *self);
+    }
+}
+
+impl UniformValue for [f32; 2] {
+    const GL_TYPE: u32 = GL::FLOAT_VEC2;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        gl.uniform2f(location, self[0], self[1]);
+    }
+}
+
+impl UniformValue for [f32; 3] {
+    const GL_TYPE: u32 = GL::FLOAT_VEC3;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        gl.uniform3f(location, self[0], self[1], self[2]);
+    }
+}
+
+impl UniformValue for [[f32; 3]] {
+    const GL_TYPE: u32 = GL::FLOAT_VEC3;
+    const IS_ARRAY: bool = true;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        // Uploading an empty array is an error
+        if !self.is_empty() {
+            gl.uniform3fv_with_f32_array(location, &self.concat());
+        }
+    }
+}
+
+impl UniformValue for [[f32; 4]] {
+    const GL_TYPE: u32 = GL::FLOAT_VEC4;
+    const IS_ARRAY: bool = true;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        if !self.is_empty() {
+            gl.uniform4fv_with_f32_array(location, &self.concat());
+        }
+    }
+}
+
+impl UniformValue for Matrix3<f32> {
+    const GL_TYPE: u32 = GL::FLOAT_MAT3;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        gl.uniform_matrix3fv_with_f32_array(location, false, self.as_slice());
+    }
+}
+
+impl UniformValue for Matrix4<f32> {
+    const GL_TYPE: u32 = GL::FLOAT_MAT4;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        gl.uniform_matrix4fv_with_f32_array(location, false, self.as_slice());
+    }
+}
+
+/**
+ * The texture unit a `sampler2D` reads from.
+ */
+pub struct TextureUnit(pub i32);
+
+impl UniformValue for TextureUnit {
+    const GL_TYPE: u32 = GL::SAMPLER_2D;
+
+    fn upload(&self, gl: &WebGlRenderingContext, location: Option<&WebGlUniformLocation>) {
+        gl.uniform1i(location, self.0);
+    }
+}
+
+pub struct Uniform<T: UniformValue + ?Sized> {
+    location: Option<WebGlUniformLocation>,
+    value_type: PhantomData<T>,
+}
+
+impl<T: UniformValue + ?Sized> Uniform<T> {
+    /**
+     * Uploads `value`, call after `use_program`.
+     */
+    pub fn set(&self, gl: &WebGlRenderingContext, value: &T) {
+        value.upload(gl, self.location.as_ref());
+    }
+}
+
+impl<T: UniformValue + ?Sized> Binding for Uniform<T> {
+    fn bind(reflection: &Reflection, name: &str) -> Result<Self, String> {
+        let uniform = reflection
+            .uniform(name)
+            .ok_or_else(|| format!("uniform {} is not used by the shader", name))?;
+        check_type("uniform", uniform, T::GL_TYPE, T::IS_ARRAY)?;
+
+        Ok(Self {
+            location: uniform.uniform_location.clone(),
+            value_type: PhantomData,
+        })
+    }
+}
+
+/**
+ * A uniform only some variants of a shader use, setting it does nothing when it isn't there.
+ */
+pub struct OptionalUniform<T: UniformValue + ?Sized>(Option<Uniform<T>>);
+
+impl<T: UniformValue + ?Sized> OptionalUniform<T> {
+    pub fn set(&self, gl: &WebGlRenderingContext, value: &T) {
+        if let Some(uniform) = &self.0 {
+            uniform.set(gl, value);
+        }
+    }
+}
+
+impl<T: UniformValue + ?Sized> Binding for OptionalUniform<T> {
+    fn bind(reflection: &Reflection, name: &str) -> Result<Self, String> {
+        if reflection.uniform(name).is_none() {
+            return Ok(Self(None));
+        }
+        Ok(Self(Some(Uniform::bind(reflection, name)?)))
+    }
+}
+
+/**
+ * Rust types matching a vertex attribute's GLSL type, `COMPONENTS` floats per vertex.
+ */
+pub trait AttributeValue {
+    const GL_TYPE: u32;
+    const COMPONENTS: i32;
+}
+
+impl AttributeValue for f32 {
+    const GL_TYPE: u32 = GL::FLOAT;
+    const COMPONENTS: i32 = 1;
+}
+
+impl AttributeValue for [f32; 2] {
+    const GL_TYPE: u32 = GL::FLOAT_VEC2;
+    const COMPONENTS: i32 = 2;
+}
+
+impl AttributeValue for [f32; 3] {
+    const GL_TYPE: u32 = GL::FLOAT_VEC3;
+    const COMPONENTS: i32 = 3;
+}
+
+impl AttributeValue for [f32; 4] {
+    const GL_TYPE: u32 = GL::FLOAT_VEC4;
+    const COMPONENTS: i32 = 4;
+}
+
+pub struct Attribute<T: AttributeValue> {
+    location: u32,
+    value_type: PhantomData<T>,
+}
+
+impl<T: AttributeValue> Attribute<T> {
+    pub fn location(&self) -> u32 {
+        self.location
+    }
+
+    /**
+     * Reads the attribute from the bound `ARRAY_BUFFER`, `stride` and `offset` are in bytes (0 stride for tightly packed data).
+     */
+    pub fn point(&self, gl: &WebGlRenderingContext, stride: i32, offset: i32) {
+        gl.enable_vertex_attrib_array(self.location);
+        gl.vertex_attrib_pointer_with_i32(self.location, T::COMPONENTS, GL::FLOAT, false, stride, offset);
+    }
+}
+
+impl<T: AttributeValue> Binding for Attribute<T> {
+    fn bind(reflection: &Reflection, name: &str) -> Result<Self, String> {
+        let attribute = reflection
+            .attribute(name)
+            .ok_or_else(|| format!("attribute {} is not used by the shader", name))?;
+        check_type("attribute", attribute, T::GL_TYPE, false)?;
+
+        Ok(Self {
+            location: attribute.attribute_location as u32,
+            value_type: PhantomData,
+        })
+    }
+}
+
+/**
+ * An attribute only some variants of a shader read (e.g. normals, which GPU evaluated surfaces compute themselves), pointing it does nothing when it isn't there.
+ */
+pub struct OptionalAttribute<T: AttributeValue>(Option<Attribute<T>>);
+
+impl<T: AttributeValue> OptionalAttribute<T> {
+    pub fn point(&self, gl: &WebGlRenderingContext, stride: i32, offset: i32) {
+        if let Some(attribute) = &self.0 {
+            attribute.point(gl, stride, offset);
+        }
+    }
+}
+
+impl<T: AttributeValue> Binding for OptionalAttribute<T> {
+    fn bind(reflection: &Reflection, name: &str) -> Result<Self, String> {
+        if reflection.attribute(name).is_none() {
+            return Ok(Self(None));
+        }
+        Ok(Self(Some(Attribute::bind(reflection, name)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str, gl_type: u32, is_array: bool) -> Variable {
+        Variable {
+            name: String::from(name),
+            gl_type,
+            is_array,
+            uniform_location: None,
+            attribute_location: -1,
+        }
+    }
+
+    #[test]
+    fn arrays_are_stored_without_their_index() {
+        assert_eq!(base_name("uLightPosition[0]"), (String::from("uLightPosition"), true));
+        assert_eq!(base_name("uOpacity"), (String::from("uOpacity"), false));
+    }
+
+    #[test]
+    fn only_a_trailing_first_index_marks_an_array() {
+        assert_eq!(base_name("uLights[0].color"), (String::from("uLights[0].color"), false));
+        assert_eq!(base_name("uLightPosition[1]"), (String::from("uLightPosition[1]"), false));
+    }
+
+    #[test]
+    fn matching_types_pass() {
+        assert!(check_type("uniform", &variable("uOpacity", GL::FLOAT, false), GL::FLOAT, false).is_ok());
+        assert!(check_type("uniform", &variable("uLightColor", GL::FLOAT_VEC3, true), GL::FLOAT_VEC3, true).is_ok());
+    }
+
+    #[test]
+    fn a_different_type_names_both() {
+        let error = check_type("uniform", &variable("uEmissive", GL::FLOAT_VEC3, false), GL::FLOAT_VEC4, false).unwrap_err();
+        assert_eq!(error, "uniform uEmissive is a vec3 in the shader but bound as a vec4");
+    }
+
+    #[test]
+    fn an_array_bound_as_a_single_value_fails() {
+        let error = check_type("uniform", &variable("uLightColor", GL::FLOAT_VEC3, true), GL::FLOAT_VEC3, false).unwrap_err();
+        assert_eq!(error, "uniform uLightColor is a vec3[] in the shader but bound as a vec3");
+    }
+}
@@ -0,0 +1,338 @@
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+
+/*
+Axis aligned bounding box.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.
+    }
+
+    /*
+    The 8 corners, in no particular order.
+    */
+    fn corners(&self) -> [Vector3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vector3::new(min.x, min.y, min.z),
+            Vector3::new(max.x, min.y, min.z),
+            Vector3::new(min.x, max.y, min.z),
+            Vector3::new(max.x, max.y, min.z),
+            Vector3::new(min.x, min.y, max.z),
+            Vector3::new(max.x, min.y, max.z),
+            Vector3::new(min.x, max.y, max.z),
+            Vector3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /**
+     * The box around this one after `transform`, which has to be affine. Rotating a box makes the box around it bigger, so this only ever grows.
+     */
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        let corners = self.corners();
+        let mut corners = corners
+            .iter()
+            .map(|&corner| transform.transform_point(&Point3::from(corner)).coords);
+        // Always 8 corners
+        let first = corners.next().unwrap();
+        corners.fold(Aabb { min: first, max: first }, |aabb, corner| Aabb {
+            min: aabb.min.inf(&corner),
+            max: aabb.max.sup(&corner),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /**
+     * The sphere around this one after `transform`, which has to be affine. Scaled by the transform's largest stretch so it holds for non uniform scales too.
+     */
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        let stretch = (0..3)
+            .map(|column| transform.fixed_slice::<3, 1>(0, column).norm())
+            .fold(0., f32::max);
+        BoundingSphere {
+            center: transform.transform_point(&Point3::from(self.center)).coords,
+            radius: self.radius * stretch,
+        }
+    }
+}
+
+/*
+A box and a sphere around the same points. The sphere is cheaper to test and rotates without growing, the box fits long flat objects (like surfaces) much tighter, so culling checks the sphere first and the box only when that wasn't enough.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    /**
+     * Bounds of (x, y, z) triples, `None` without any. NaNs (from expressions undefined at some samples) are skipped, they aren't drawn either.
+     */
+    pub fn from_positions(positions: &[f32]) -> Option<Self> {
+        let points = || {
+            positions
+                .chunks_exact(3)
+                .map(|point| Vector3::new(point[0], point[1], point[2]))
+                .filter(|point| point.iter().all(|value| value.is_finite()))
+        };
+
+        let first = points().next()?;
+        let aabb = points().fold(Aabb { min: first, max: first }, |aabb, point| Aabb {
+            min: aabb.min.inf(&point),
+            max: aabb.max.sup(&point),
+        });
+
+        // Centered on the box, not the tightest sphere but close enough and found in one pass
+        let center = aabb.center();
+        let radius = points().map(|point| (point - center).norm()).fold(0., f32::max);
+
+        Some(Bounds {
+            aabb,
+            sphere: BoundingSphere { center, radius },
+        })
+    }
+
+    /**
+     * Bounds of copies of this object moved by each (x, y, z) in `offsets`, like instances drawn from one mesh. `None` without any offsets.
+     */
+    pub fn instanced(&self, offsets: &[f32]) -> Option<Self> {
+        let offsets = Bounds::from_positions(offsets)?;
+        Some(Bounds {
+            aabb: Aabb {
+                min: self.aabb.min + offsets.aabb.min,
+                max: self.aabb.max + offsets.aabb.max,
+            },
+            sphere: BoundingSphere {
+                center: self.sphere.center + offsets.sphere.center,
+                radius: self.sphere.radius + offsets.sphere.radius,
+            },
+        })
+    }
+
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        Bounds {
+            aabb: self.aabb.transformed(transform),
+            sphere: self.sphere.transformed(transform),
+        }
+    }
+}
+
+/*
+The six planes around what a projection can see, pointing inwards. Built from the projection matrix itself (Gribb & Hartmann) so it always agrees with what is drawn, see: https://www.gribb.com/assets/Fast-Extraction-of-Viewing-Frustum-Planes-from-the-WorldView-Projection-Matrix.pdf
+
+Planes are (a, b, c, d) with a x + b y + c z + d >= 0 inside, normalized so that is the distance to the plane.
+*/
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /**
+     * The frustum of `matrix` in the space it takes points from: a projection gives it in view space, a model view projection in model space.
+     */
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Self {
+        let row = |index: usize| -> Vector4<f32> { matrix.row(index).transpose() };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let planes = [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| {
+            let length = plane.xyz().norm();
+            if length > 0. {
+                plane / length
+            } else {
+                plane
+            }
+        });
+        Frustum { planes }
+    }
+
+    /**
+     * Whether anything inside `bounds` could be seen. Conservative, bounds near a corner of the frustum can pass without being in view.
+     */
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.xyz().dot(&sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner farthest along the plane's normal, if that is outside so is the whole box
+            let farthest = Vector3::new(
+                if plane.x >= 0. { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0. { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0. { aabb.max.z } else { aabb.min.z },
+            );
+            plane.xyz().dot(&farthest) + plane.w >= 0.
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Perspective3;
+    use std::f32::consts::FRAC_PI_2;
+
+    // A 90 degree view of square, looking down -z from the origin, from 1 to 100 away
+    fn frustum() -> Frustum {
+        Frustum::from_matrix(&Perspective3::new(1., FRAC_PI_2, 1., 100.).to_homogeneous())
+    }
+
+    fn bounds_at(center: Vector3<f32>, half_size: f32) -> Bounds {
+        let offset = Vector3::repeat(half_size);
+        Bounds {
+            aabb: Aabb {
+                min: center - offset,
+                max: center + offset,
+            },
+            sphere: BoundingSphere {
+                center,
+                radius: half_size * 3f32.sqrt(),
+            },
+        }
+    }
+
+    fn close(a: &Vector3<f32>, b: &Vector3<f32>) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    #[test]
+    fn sees_what_is_in_front_of_the_camera() {
+        assert!(frustum().intersects(&bounds_at(Vector3::new(0., 0., -5.), 0.5)));
+        // 5 away the view is 10 wide, so this is near its right edge
+        assert!(frustum().intersects(&bounds_at(Vector3::new(4.5, -4.5, -5.), 0.5)));
+    }
+
+    #[test]
+    fn culls_what_is_behind_the_camera() {
+        assert!(!frustum().intersects(&bounds_at(Vector3::new(0., 0., 5.), 0.5)));
+        // Between the camera and the near plane
+        assert!(!frustum().intersects(&bounds_at(Vector3::new(0., 0., -0.4), 0.1)));
+    }
+
+    #[test]
+    fn culls_what_is_beside_or_beyond_the_view() {
+        assert!(!frustum().intersects(&bounds_at(Vector3::new(8., 0., -5.), 0.5)));
+        assert!(!frustum().intersects(&bounds_at(Vector3::new(0., -8., -5.), 0.5)));
+        assert!(!frustum().intersects(&bounds_at(Vector3::new(0., 0., -150.), 0.5)));
+    }
+
+    #[test]
+    fn keeps_what_straddles_an_edge() {
+        assert!(frustum().intersects(&bounds_at(Vector3::new(5.3, 0., -5.), 0.5)));
+        assert!(frustum().intersects(&bounds_at(Vector3::new(0., 0., -100.3), 0.5)));
+    }
+
+    #[test]
+    fn a_model_view_projection_gives_the_frustum_in_model_space() {
+        let projection = Perspective3::new(1., FRAC_PI_2, 1., 100.).to_homogeneous();
+        let model_view = Matrix4::new_translation(&Vector3::new(0., 0., -5.));
+        let frustum = Frustum::from_matrix(&(projection * model_view));
+        assert!(frustum.intersects(&bounds_at(Vector3::zeros(), 0.5)));
+        assert!(!frustum.intersects(&bounds_at(Vector3::new(0., 0., 10.), 0.5)));
+    }
+
+    #[test]
+    fn the_box_catches_long_objects_the_sphere_lets_through() {
+        // A long thin rod just outside the right edge, its sphere reaches into the view
+        let rod = Aabb {
+            min: Vector3::new(5.5, -4., -5.1),
+            max: Vector3::new(5.7, 4., -4.9),
+        };
+        let sphere = BoundingSphere {
+            center: rod.center(),
+            radius: (rod.max - rod.center()).norm(),
+        };
+        assert!(frustum().intersects_sphere(&sphere));
+        assert!(!frustum().intersects_aabb(&rod));
+        assert!(!frustum().intersects(&Bounds { aabb: rod, sphere }));
+    }
+
+    #[test]
+    fn rotated_boxes_grow_to_hold_their_corners() {
+        let cube = Aabb {
+            min: Vector3::repeat(-1.),
+            max: Vector3::repeat(1.),
+        };
+        let rotated = cube.transformed(&Matrix4::new_rotation(Vector3::new(0., 0., std::f32::consts::FRAC_PI_4)));
+        let diagonal = 2f32.sqrt();
+        assert!(close(&rotated.min, &Vector3::new(-diagonal, -diagonal, -1.)));
+        assert!(close(&rotated.max, &Vector3::new(diagonal, diagonal, 1.)));
+
+        // A quarter turn fits exactly again, with the sides swapped
+        let flat = Aabb {
+            min: Vector3::new(-2., -1., 0.),
+            max: Vector3::new(2., 1., 0.5),
+        };
+        let turned = flat.transformed(&Matrix4::new_rotation(Vector3::new(0., 0., FRAC_PI_2)));
+        assert!(close(&turned.min, &Vector3::new(-1., -2., 0.)));
+        assert!(close(&turned.max, &Vector3::new(1., 2., 0.5)));
+    }
+
+    #[test]
+    fn boxes_follow_translation_and_scale() {
+        let cube = Aabb {
+            min: Vector3::zeros(),
+            max: Vector3::repeat(1.),
+        };
+        let transform = Matrix4::new_translation(&Vector3::new(1., 2., 3.))
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(2., -1., 1.));
+        let moved = cube.transformed(&transform);
+        assert!(close(&moved.min, &Vector3::new(1., 1., 3.)));
+        assert!(close(&moved.max, &Vector3::new(3., 2., 4.)));
+    }
+
+    #[test]
+    fn spheres_grow_by_the_largest_stretch() {
+        let sphere = BoundingSphere {
+            center: Vector3::new(1., 0., 0.),
+            radius: 1.,
+        };
+        let stretched = sphere.transformed(&Matrix4::new_nonuniform_scaling(&Vector3::new(1., 3., 0.5)));
+        assert!(close(&stretched.center, &Vector3::new(1., 0., 0.)));
+        assert!((stretched.radius - 3.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn positions_which_are_not_numbers_are_skipped() {
+        let bounds = Bounds::from_positions(&[0., 0., 0., f32::NAN, 5., 5., 2., 2., 2.]).unwrap();
+        assert_eq!(bounds.aabb.max, Vector3::new(2., 2., 2.));
+        assert!(Bounds::from_positions(&[f32::NAN, 0., 0.]).is_none());
+    }
+
+    #[test]
+    fn instances_are_all_inside_their_bounds() {
+        let mesh = Bounds::from_positions(&[-1., -1., -1., 1., 1., 1.]).unwrap();
+        let offsets = [0., 0., 0., 4., 0., 0., 0., -3., 2.];
+        let all = mesh.instanced(&offsets).unwrap();
+
+        assert_eq!(all.aabb.min, Vector3::new(-1., -4., -1.));
+        assert_eq!(all.aabb.max, Vector3::new(5., 1., 3.));
+        for offset in offsets.chunks_exact(3) {
+            let offset = Vector3::new(offset[0], offset[1], offset[2]);
+            for corner in mesh.aabb.corners().iter() {
+                let point = corner + offset;
+                assert!((point - all.sphere.center).norm() <= all.sphere.radius + 1e-5, "{:?} is outside the sphere", point);
+            }
+        }
+
+        assert!(mesh.instanced(&[]).is_none());
+    }
+}
@@ -0,0 +1,165 @@
+/*
+Animation time, in seconds, separate from the wall clock time the host page passes to `MVisual::update`.
+
+Playing, it advances by the wall clock time since the last update scaled by `speed`. Pausing stops it without losing its place, and seeking jumps straight to a time (scrubbing is seeking every time the slider moves).
+*/
+pub struct Clock {
+    time: f32,
+    speed: f32,
+    playing: bool,
+    // Wall clock time (ms) of the last `advance`, `None` until the first one
+    last_wall_time: Option<f32>,
+    // Time reported by the last `advance`, to tell whether anything needs recomputing
+    last_time: Option<f32>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            time: 0.,
+            speed: 1.,
+            playing: true,
+            last_wall_time: None,
+            last_time: None,
+        }
+    }
+}
+
+impl Clock {
+    /**
+     * Moves the clock on to `wall_time` (in ms, as given to `MVisual::update`). Returns whether the animation time changed since the last call, from playing or from a seek.
+     */
+    pub fn advance(&mut self, wall_time: f32) -> bool {
+        if let (true, Some(last)) = (self.playing, self.last_wall_time) {
+            // The wall clock can jump back when the host page restarts its timer, don't run backwards with it
+            self.time += (wall_time - last).max(0.) / 1000. * self.speed;
+        }
+        self.last_wall_time = Some(wall_time);
+
+        let changed = self.last_time != Some(self.time);
+        self.last_time = Some(self.time);
+        changed
+    }
+
+    /**
+     * Forgets the last wall clock time and animation time, so the wall clock time until the next `advance` isn't played and that `advance` reports a change. For after something else has moved the animation (an export) and put it back.
+     */
+    pub fn resync(&mut self) {
+        self.last_wall_time = None;
+        self.last_time = None;
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /**
+     * Negative speeds play backwards.
+     */
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_first_advance_only_sets_the_wall_clock() {
+        let mut clock = Clock::default();
+        assert!(clock.advance(5000.));
+        assert_eq!(clock.time(), 0.);
+        clock.advance(5500.);
+        assert_eq!(clock.time(), 0.5);
+    }
+
+    #[test]
+    fn only_reports_changes() {
+        let mut clock = Clock::default();
+        clock.advance(0.);
+        assert!(!clock.advance(0.));
+        assert!(clock.advance(16.));
+    }
+
+    #[test]
+    fn pausing_keeps_the_place() {
+        let mut clock = Clock::default();
+        clock.advance(0.);
+        clock.advance(1000.);
+        clock.pause();
+        assert!(!clock.advance(3000.));
+        assert_eq!(clock.time(), 1.);
+
+        // The paused wall clock time isn't made up for on playing again
+        clock.play();
+        clock.advance(3500.);
+        assert_eq!(clock.time(), 1.5);
+    }
+
+    #[test]
+    fn seeking_is_a_change_even_while_paused() {
+        let mut clock = Clock::default();
+        clock.pause();
+        clock.advance(0.);
+        clock.seek(4.);
+        assert!(clock.advance(100.));
+        assert_eq!(clock.time(), 4.);
+        assert!(!clock.advance(200.));
+    }
+
+    #[test]
+    fn speed_scales_the_wall_clock() {
+        let mut clock = Clock::default();
+        clock.set_speed(2.);
+        clock.advance(0.);
+        clock.advance(250.);
+        assert_eq!(clock.time(), 0.5);
+
+        clock.set_speed(-1.);
+        clock.advance(500.);
+        assert_eq!(clock.time(), 0.25);
+    }
+
+    #[test]
+    fn a_wall_clock_going_backwards_holds_the_time() {
+        let mut clock = Clock::default();
+        clock.advance(10_000.);
+        clock.advance(11_000.);
+        assert!(!clock.advance(20.));
+        assert_eq!(clock.time(), 1.);
+        // And time carries on from the new wall clock
+        clock.advance(520.);
+        assert_eq!(clock.time(), 1.5);
+    }
+
+    #[test]
+    fn resync_skips_the_gap_and_reports_a_change() {
+        let mut clock = Clock::default();
+        clock.advance(0.);
+        clock.advance(1000.);
+        clock.resync();
+        assert!(clock.advance(60_000.));
+        assert_eq!(clock.time(), 1.);
+    }
+}
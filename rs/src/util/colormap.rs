@@ -0,0 +1,196 @@
+/*
+A color map turns a scalar in [0, 1] into a color. It is stored as a list of stops and colors are linearly interpolated between neighbouring stops.
+
+The built in maps are perceptually uniform (equal steps in value look like equal steps in color) so they don't invent features in the data. See: https://bids.github.io/colormap/
+*/
+
+// Stops used when sampling the polynomial fits below
+const POLYNOMIAL_STOPS: usize = 33;
+
+// Number of texels in the lookup textures built by `to_rgb_bytes`
+pub const COLOR_MAP_TEXTURE_SIZE: usize = 256;
+
+/*
+Degree 6 polynomial fits of matplotlib's viridis, magma and plasma (coefficients c0 to c6 per channel) by Matt Zucker. See: https://www.shadertoy.com/view/WlfXRN
+*/
+const VIRIDIS_COEFFICIENTS: [[f32; 3]; 7] = [
+    [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+    [0.105_093_04, 1.404_613_5, 1.384_590_2],
+    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+    [-4.634_230_6, -5.799_101, -19.332_441],
+    [6.228_27, 14.179_933, 56.690_55],
+    [4.776_385, -13.745_146, -65.353_03],
+    [-5.435_456, 4.645_852_6, 26.312_435],
+];
+const MAGMA_COEFFICIENTS: [[f32; 3]; 7] = [
+    [-0.002_136_485, -0.000_749_655_05, -0.005_386_128],
+    [0.251_660_54, 0.677_523_2, 2.494_026_6],
+    [8.353_717, -3.577_719_5, 0.314_467_9],
+    [-27.668_733, 14.264_731, -13.649_213],
+    [52.176_14, -27.943_607, 12.944_169],
+    [-50.768_524, 29.046_583, 4.234_153],
+    [18.655_705, -11.489_774, -5.601_961_5],
+];
+const PLASMA_COEFFICIENTS: [[f32; 3]; 7] = [
+    [0.058_732_344, 0.023_336_709, 0.543_340_2],
+    [2.176_514_6, 0.238_383_42, 0.753_960_4],
+    [-2.689_460_5, -7.455_851, 3.110_8],
+    [6.130_348, 42.346_188, -28.518_854],
+    [-11.107_436, -82.666_31, 60.139_847],
+    [10.023_066, 71.413_62, -54.072_186],
+    [-3.658_713_8, -22.931_534, 18.191_908],
+];
+
+// viridisLite's cividis(10)
+const CIVIDIS_COLORS: [[u8; 3]; 10] = [
+    [0x00, 0x20, 0x4D],
+    [0x00, 0x33, 0x6F],
+    [0x39, 0x48, 0x6B],
+    [0x57, 0x5C, 0x6D],
+    [0x70, 0x71, 0x73],
+    [0x8A, 0x87, 0x79],
+    [0xA6, 0x9D, 0x75],
+    [0xC4, 0xB5, 0x6C],
+    [0xE4, 0xCF, 0x5B],
+    [0xFF, 0xEA, 0x46],
+];
+
+// Kenneth Moreland's diverging "cool to warm" map. See: https://www.kennethmoreland.com/color-maps/
+const COOLWARM_COLORS: [[u8; 3]; 9] = [
+    [59, 76, 192],
+    [98, 130, 234],
+    [141, 176, 254],
+    [184, 208, 249],
+    [221, 221, 221],
+    [245, 196, 173],
+    [244, 154, 123],
+    [222, 96, 77],
+    [180, 4, 38],
+];
+
+pub const COLOR_MAP_NAMES: [&str; 5] = ["viridis", "magma", "plasma", "cividis", "coolwarm"];
+
+#[derive(Clone)]
+pub struct ColorMap {
+    // (position in [0, 1], RGB) sorted by position
+    pub stops: Vec<(f32, [f32; 3])>,
+}
+
+impl ColorMap {
+    /**
+     * Builds a color map from stops. Stops are sorted and clamped into [0, 1], a map without stops is solid white.
+     */
+    pub fn new(mut stops: Vec<(f32, [f32; 3])>) -> Self {
+        for stop in stops.iter_mut() {
+            stop.0 = stop.0.clamp(0., 1.);
+        }
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        if stops.is_empty() {
+            stops.push((0., [1., 1., 1.]));
+        }
+
+        Self { stops }
+    }
+
+    /**
+     * Evenly spaced stops from a list of colors.
+     */
+    pub fn from_colors(colors: &[[f32; 3]]) -> Self {
+        let last = (colors.len().max(2) - 1) as f32;
+        Self::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, color)| (i as f32 / last, *color))
+                .collect(),
+        )
+    }
+
+    /**
+     * Looks up one of the built in maps by name, see `COLOR_MAP_NAMES`.
+     */
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "viridis" => Some(Self::from_polynomial(&VIRIDIS_COEFFICIENTS)),
+            "magma" => Some(Self::from_polynomial(&MAGMA_COEFFICIENTS)),
+            "plasma" => Some(Self::from_polynomial(&PLASMA_COEFFICIENTS)),
+            "cividis" => Some(Self::from_bytes(&CIVIDIS_COLORS)),
+            "coolwarm" => Some(Self::from_bytes(&COOLWARM_COLORS)),
+            _ => None,
+        }
+    }
+
+    fn from_polynomial(coefficients: &[[f32; 3]; 7]) -> Self {
+        let colors: Vec<[f32; 3]> = (0..POLYNOMIAL_STOPS)
+            .map(|i| {
+                let t = i as f32 / (POLYNOMIAL_STOPS - 1) as f32;
+                let mut color = [0.; 3];
+                // Horner's method, highest power first
+                for coefficient in coefficients.iter().rev() {
+                    for channel in 0..3 {
+                        color[channel] = color[channel] * t + coefficient[channel];
+                    }
+                }
+                [color[0].clamp(0., 1.), color[1].clamp(0., 1.), color[2].clamp(0., 1.)]
+            })
+            .collect();
+
+        Self::from_colors(&colors)
+    }
+
+    fn from_bytes(colors: &[[u8; 3]]) -> Self {
+        let colors: Vec<[f32; 3]> = colors
+            .iter()
+            .map(|color| [color[0] as f32 / 255., color[1] as f32 / 255., color[2] as f32 / 255.])
+            .collect();
+
+        Self::from_colors(&colors)
+    }
+
+    /**
+     * Samples the map into `COLOR_MAP_TEXTURE_SIZE` RGB texels for a lookup texture.
+     */
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        (0..COLOR_MAP_TEXTURE_SIZE)
+            .flat_map(|i| {
+                let color = self.sample(i as f32 / (COLOR_MAP_TEXTURE_SIZE - 1) as f32);
+                [
+                    (color[0] * 255.).round() as u8,
+                    (color[1] * 255.).round() as u8,
+                    (color[2] * 255.).round() as u8,
+                ]
+            })
+            .collect()
+    }
+
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+
+        for pair in self.stops.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            if t <= end.0 {
+                let span = end.0 - start.0;
+                let amount = if span > 0. { (t - start.0) / span } else { 1. };
+                return [
+                    start.1[0] + (end.1[0] - start.1[0]) * amount,
+                    start.1[1] + (end.1[1] - start.1[1]) * amount,
+                    start.1[2] + (end.1[2] - start.1[2]) * amount,
+                ];
+            }
+        }
+
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+impl Default for ColorMap {
+    fn default() -> Self {
+        Self::from_polynomial(&VIRIDIS_COEFFICIENTS)
+    }
+}
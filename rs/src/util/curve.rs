@@ -0,0 +1,58 @@
+use super::expression::Expression;
+use crate::error::MVisualError;
+
+// Enough for a smooth line without the vertex data getting large
+pub const MAX_CURVE_SAMPLES: usize = 65536;
+
+/*
+A parametric curve (x(s, t), y(s, t), z(s, t)) for s running over `range`, sampled again whenever t changes.
+*/
+#[derive(Clone)]
+pub struct CurveFunction {
+    pub x: Expression,
+    pub y: Expression,
+    pub z: Expression,
+    pub range: (f32, f32),
+    pub samples: usize,
+}
+
+impl CurveFunction {
+    // Order of the values `Expression::evaluate` takes
+    pub const VARIABLES: [&'static str; 2] = ["s", "t"];
+
+    pub fn parse(x: &str, y: &str, z: &str, range: (f32, f32), samples: usize) -> Result<Self, MVisualError> {
+        if !(2..=MAX_CURVE_SAMPLES).contains(&samples) {
+            return Err(MVisualError::Input(format!("Curves need between 2 and {} samples, got {}", MAX_CURVE_SAMPLES, samples)));
+        }
+
+        Ok(Self {
+            x: Expression::parse(x, &Self::VARIABLES)?,
+            y: Expression::parse(y, &Self::VARIABLES)?,
+            z: Expression::parse(z, &Self::VARIABLES)?,
+            range,
+            samples,
+        })
+    }
+
+    /**
+     * Whether the curve changes with t.
+     */
+    pub fn is_animated(&self) -> bool {
+        [&self.x, &self.y, &self.z].iter().any(|expression| expression.uses_variable(1))
+    }
+
+    /**
+     * (x, y, z) of every sample at time `t`, evenly spaced in s. Values which aren't finite are flattened to 0.
+     */
+    pub fn positions(&self, t: f32) -> Vec<f32> {
+        let mut positions = Vec::with_capacity(3 * self.samples);
+        for i in 0..self.samples {
+            let s = self.range.0 + (self.range.1 - self.range.0) * i as f32 / (self.samples - 1) as f32;
+            for expression in [&self.x, &self.y, &self.z].iter() {
+                let value = expression.evaluate(&[s, t]);
+                positions.push(if value.is_finite() { value } else { 0. });
+            }
+        }
+        positions
+    }
+}
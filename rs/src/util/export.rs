@@ -0,0 +1,211 @@
+use crate::error::MVisualError;
+
+// Largest export side, a frame's RGBA pixels are held in memory several times over while it is encoded
+pub const MAX_EXPORT_SIZE: u32 = 4096;
+
+// GIF frame delays are in hundredths of a second and browsers slow anything under 2 down to 10
+const MAX_GIF_FPS: f32 = 50.;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gif" => Some(AnimationFormat::Gif),
+            "apng" => Some(AnimationFormat::Apng),
+            _ => None,
+        }
+    }
+}
+
+/*
+What to export: `duration` seconds of animation time from t = 0, stepped by a fixed 1 / `fps` regardless of how long each frame takes to render, so the same scene always exports the same frames.
+*/
+#[derive(Clone, Copy)]
+pub struct ExportSettings {
+    pub fps: f32,
+    pub duration: f32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ExportSettings {
+    pub fn new(fps: f32, duration: f32, width: u32, height: u32) -> Result<Self, MVisualError> {
+        if !(fps > 0. && fps.is_finite()) {
+            return Err(MVisualError::Input(format!("Export frame rate must be positive, got {}", fps)));
+        }
+        if !(duration > 0. && duration.is_finite()) {
+            return Err(MVisualError::Input(format!("Export duration must be positive, got {}", duration)));
+        }
+        if !(1..=MAX_EXPORT_SIZE).contains(&width) || !(1..=MAX_EXPORT_SIZE).contains(&height) {
+            return Err(MVisualError::Input(format!(
+                "Export size must be between 1 and {} on each side, got {}x{}",
+                MAX_EXPORT_SIZE, width, height
+            )));
+        }
+        Ok(Self { fps, duration, width, height })
+    }
+
+    /**
+     * Frames in the export, at least one.
+     */
+    pub fn frame_count(&self) -> usize {
+        ((self.duration * self.fps).round() as usize).max(1)
+    }
+
+    /**
+     * Animation time of frame `index`. Computed from the index rather than by adding up steps so rounding doesn't build up over long exports.
+     */
+    pub fn frame_time(&self, index: usize) -> f32 {
+        index as f32 / self.fps
+    }
+}
+
+/*
+Encodes frames into an animated GIF or APNG as they are rendered, so only one frame's pixels are held at a time.
+*/
+pub enum AnimationWriter<'a> {
+    Gif(gif::Encoder<&'a mut Vec<u8>>),
+    Apng(png::Writer<&'a mut Vec<u8>>),
+}
+
+impl<'a> AnimationWriter<'a> {
+    /**
+     * Writes the file's header into `out`, `settings` has to be valid (see `ExportSettings::new`).
+     */
+    pub fn new(
+        format: AnimationFormat,
+        settings: &ExportSettings,
+        out: &'a mut Vec<u8>,
+    ) -> Result<Self, MVisualError> {
+        match format {
+            AnimationFormat::Gif => {
+                if settings.fps > MAX_GIF_FPS {
+                    return Err(MVisualError::Input(format!(
+                        "GIFs can't play faster than {} fps, got {}, use APNG instead",
+                        MAX_GIF_FPS, settings.fps
+                    )));
+                }
+                let mut encoder = gif::Encoder::new(out, settings.width as u16, settings.height as u16, &[])
+                    .map_err(export_error)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(export_error)?;
+                Ok(AnimationWriter::Gif(encoder))
+            }
+            AnimationFormat::Apng => {
+                let mut encoder = png_encoder(out, settings.width, settings.height);
+                encoder.set_animated(settings.frame_count() as u32, 0).map_err(export_error)?;
+                let (numerator, denominator) = apng_frame_delay(settings.fps);
+                encoder.set_frame_delay(numerator, denominator).map_err(export_error)?;
+                Ok(AnimationWriter::Apng(encoder.write_header().map_err(export_error)?))
+            }
+        }
+    }
+
+    /**
+     * Adds the next frame, RGBA with the top row first. GIF frames are reduced to a 256 color palette in place.
+     */
+    pub fn add_frame(&mut self, settings: &ExportSettings, pixels: &mut [u8]) -> Result<(), MVisualError> {
+        match self {
+            AnimationWriter::Gif(encoder) => {
+                // Speed 10 is the quantizer's default, lower is slower with slightly better colors
+                let mut frame = gif::Frame::from_rgba_speed(settings.width as u16, settings.height as u16, pixels, 10);
+                // Rounded to whole hundredths, frame rates which don't divide 100 (like 30) play slightly off
+                frame.delay = (100. / settings.fps).round() as u16;
+                encoder.write_frame(&frame).map_err(export_error)
+            }
+            AnimationWriter::Apng(writer) => writer.write_image_data(pixels).map_err(export_error),
+        }
+    }
+
+    /**
+     * Writes the end of the file. Every frame `ExportSettings::frame_count` promised has to have been added.
+     */
+    pub fn finish(self) -> Result<(), MVisualError> {
+        match self {
+            AnimationWriter::Gif(encoder) => encoder.into_inner().map(|_| ()).map_err(export_error),
+            AnimationWriter::Apng(writer) => writer.finish().map_err(export_error),
+        }
+    }
+}
+
+/**
+ * One RGBA frame, top row first, as a PNG file.
+ */
+pub fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Result<Vec<u8>, MVisualError> {
+    let mut out = Vec::new();
+    let mut writer = png_encoder(&mut out, width, height).write_header().map_err(export_error)?;
+    writer.write_image_data(pixels).map_err(export_error)?;
+    writer.finish().map_err(export_error)?;
+    Ok(out)
+}
+
+fn png_encoder(out: &mut Vec<u8>, width: u32, height: u32) -> png::Encoder<'static, &mut Vec<u8>> {
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+}
+
+/*
+APNG frame delays are a fraction of a second with 16 bit parts. Whole frame rates are exact, others are rounded to the millisecond.
+*/
+fn apng_frame_delay(fps: f32) -> (u16, u16) {
+    if fps.fract() == 0. && fps <= u16::MAX as f32 {
+        (1, fps as u16)
+    } else {
+        ((1000. / fps).round().clamp(1., u16::MAX as f32) as u16, 1000)
+    }
+}
+
+fn export_error(error: impl std::fmt::Display) -> MVisualError {
+    MVisualError::Export(format!("Failed to encode: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(fps: f32, duration: f32) -> ExportSettings {
+        ExportSettings::new(fps, duration, 64, 64).unwrap()
+    }
+
+    #[test]
+    fn frame_count_rounds_to_the_nearest_frame() {
+        assert_eq!(settings(30., 2.).frame_count(), 60);
+        assert_eq!(settings(24., 1.01).frame_count(), 24);
+        assert_eq!(settings(10., 0.26).frame_count(), 3);
+    }
+
+    #[test]
+    fn frame_count_is_at_least_one() {
+        assert_eq!(settings(10., 0.01).frame_count(), 1);
+    }
+
+    #[test]
+    fn frame_time_steps_by_a_fixed_interval() {
+        let settings = settings(25., 4.);
+        assert_eq!(settings.frame_time(0), 0.);
+        assert_eq!(settings.frame_time(1), 0.04);
+        assert_eq!(settings.frame_time(75), 3.);
+    }
+
+    #[test]
+    fn new_rejects_frame_rates_and_durations_which_are_not_positive() {
+        for value in [0., -1., f32::NAN, f32::INFINITY] {
+            assert!(ExportSettings::new(value, 1., 64, 64).is_err());
+            assert!(ExportSettings::new(30., value, 64, 64).is_err());
+        }
+    }
+
+    #[test]
+    fn new_rejects_sizes_outside_the_limit() {
+        assert!(ExportSettings::new(30., 1., MAX_EXPORT_SIZE, MAX_EXPORT_SIZE).is_ok());
+        assert!(ExportSettings::new(30., 1., MAX_EXPORT_SIZE + 1, 64).is_err());
+        assert!(ExportSettings::new(30., 1., 64, MAX_EXPORT_SIZE + 1).is_err());
+        assert!(ExportSettings::new(30., 1., 0, 64).is_err());
+    }
+}
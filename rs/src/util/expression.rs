@@ -0,0 +1,1041 @@
+use crate::error::MVisualError;
+
+/*
+A small expression language for plotting, e.g. "sin(x - t) * exp(-(x^2 + y^2) / 4)".
+
+    expression = term (("+" | "-") term)*
+    term       = unary (("*" | "/") unary)*
+    unary      = "-" unary | power
+    power      = primary ("^" unary)?
+    primary    = number | name | name "(" expression ("," expression)* ")" | "(" expression ")"
+
+`^` binds tighter than unary minus and groups to the right, so "-x^2" is -(x^2) and "2^3^2" is 2^(3^2). The names which are allowed as variables are given when parsing, "pi" and "e" are constants.
+
+Everything is evaluated in f32 so the values match what a shader computes. `to_glsl` turns an expression into the same computation in GLSL, for evaluating on the GPU.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Number(f32),
+    // Index into the variable names given to `parse`
+    Variable(usize),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    Call(Function, Vec<Expression>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    // Two argument arctangent, atan2(y, x)
+    Atan2,
+    Exp,
+    // Natural logarithm
+    Log,
+    Sqrt,
+    Abs,
+    Sign,
+    Floor,
+    Ceil,
+    Fract,
+    // Always has the sign of the divisor, like GLSL's mod
+    Mod,
+    Min,
+    Max,
+    Pow,
+}
+
+impl Function {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sin" => Some(Function::Sin),
+            "cos" => Some(Function::Cos),
+            "tan" => Some(Function::Tan),
+            "asin" => Some(Function::Asin),
+            "acos" => Some(Function::Acos),
+            "atan" => Some(Function::Atan),
+            "atan2" => Some(Function::Atan2),
+            "exp" => Some(Function::Exp),
+            "log" | "ln" => Some(Function::Log),
+            "sqrt" => Some(Function::Sqrt),
+            "abs" => Some(Function::Abs),
+            "sign" => Some(Function::Sign),
+            "floor" => Some(Function::Floor),
+            "ceil" => Some(Function::Ceil),
+            "fract" => Some(Function::Fract),
+            "mod" => Some(Function::Mod),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "pow" => Some(Function::Pow),
+            _ => None,
+        }
+    }
+
+    /*
+    The GLSL function computing the same thing. `pow` is replaced by `expressionPow` (see the "expression" shader chunk) as GLSL's is undefined for negative bases.
+    */
+    fn glsl_name(&self) -> &'static str {
+        match self {
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Asin => "asin",
+            Function::Acos => "acos",
+            Function::Atan | Function::Atan2 => "atan",
+            Function::Exp => "exp",
+            Function::Log => "log",
+            Function::Sqrt => "sqrt",
+            Function::Abs => "abs",
+            Function::Sign => "sign",
+            Function::Floor => "floor",
+            Function::Ceil => "ceil",
+            Function::Fract => "fract",
+            Function::Mod => "mod",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Pow => "expressionPow",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Function::Atan2 | Function::Mod | Function::Min | Function::Max | Function::Pow => 2,
+            _ => 1,
+        }
+    }
+
+    fn apply(&self, arguments: &[f32]) -> f32 {
+        let a = arguments[0];
+        match self {
+            Function::Sin => a.sin(),
+            Function::Cos => a.cos(),
+            Function::Tan => a.tan(),
+            Function::Asin => a.asin(),
+            Function::Acos => a.acos(),
+            Function::Atan => a.atan(),
+            Function::Atan2 => a.atan2(arguments[1]),
+            Function::Exp => a.exp(),
+            Function::Log => a.ln(),
+            Function::Sqrt => a.sqrt(),
+            Function::Abs => a.abs(),
+            // GLSL's sign is 0 at 0, Rust's signum is 1
+            Function::Sign => {
+                if a > 0. {
+                    1.
+                } else if a < 0. {
+                    -1.
+                } else {
+                    0.
+                }
+            }
+            Function::Floor => a.floor(),
+            Function::Ceil => a.ceil(),
+            Function::Fract => a - a.floor(),
+            Function::Mod => a - arguments[1] * (a / arguments[1]).floor(),
+            Function::Min => a.min(arguments[1]),
+            Function::Max => a.max(arguments[1]),
+            Function::Pow => power(a, arguments[1]),
+        }
+    }
+}
+
+/*
+`powf` with negative bases allowed for whole exponents, so "x^2" works on both sides of 0.
+*/
+fn power(base: f32, exponent: f32) -> f32 {
+    if base < 0. && exponent.fract() == 0. {
+        let magnitude = (-base).powf(exponent);
+        if exponent % 2. == 0. {
+            magnitude
+        } else {
+            -magnitude
+        }
+    } else {
+        base.powf(exponent)
+    }
+}
+
+impl Expression {
+    /**
+     * Parses `source`, where the names in `variables` may be used as variables. `evaluate` takes their values in the same order.
+     */
+    pub fn parse(source: &str, variables: &[&str]) -> Result<Self, MVisualError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            source,
+            tokens,
+            position: 0,
+            variables,
+        };
+
+        let expression = parser.expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(parser.error(token, "Expected an operator"));
+        }
+
+        Ok(expression)
+    }
+
+    pub fn evaluate(&self, variables: &[f32]) -> f32 {
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Variable(index) => variables[*index],
+            Expression::Negate(operand) => -operand.evaluate(variables),
+            Expression::Binary(operator, left, right) => {
+                let a = left.evaluate(variables);
+                let b = right.evaluate(variables);
+                match operator {
+                    Operator::Add => a + b,
+                    Operator::Subtract => a - b,
+                    Operator::Multiply => a * b,
+                    Operator::Divide => a / b,
+                    Operator::Power => power(a, b),
+                }
+            }
+            Expression::Call(function, arguments) => {
+                let values: Vec<f32> = arguments.iter().map(|argument| argument.evaluate(variables)).collect();
+                function.apply(&values)
+            }
+        }
+    }
+
+    /**
+     * The expression as GLSL, with variables written as the matching entries of `variables`. Every operation is parenthesized so GLSL's precedence never comes into it.
+     *
+     * Needs the "expression" shader chunk for `expressionPow`.
+     */
+    pub fn to_glsl(&self, variables: &[&str]) -> String {
+        match self {
+            // Debug always keeps a decimal point or an exponent ("1.0", "1e-7"), both valid GLSL floats
+            Expression::Number(value) => format!("{:?}", value),
+            Expression::Variable(index) => String::from(variables[*index]),
+            Expression::Negate(operand) => format!("(-{})", operand.to_glsl(variables)),
+            Expression::Binary(Operator::Power, base, exponent) => format!(
+                "expressionPow({}, {})",
+                base.to_glsl(variables),
+                exponent.to_glsl(variables)
+            ),
+            Expression::Binary(operator, left, right) => {
+                let symbol = match operator {
+                    Operator::Add => "+",
+                    Operator::Subtract => "-",
+                    Operator::Multiply => "*",
+                    Operator::Divide => "/",
+                    Operator::Power => unreachable!(),
+                };
+                format!("({} {} {})", left.to_glsl(variables), symbol, right.to_glsl(variables))
+            }
+            Expression::Call(function, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_glsl(variables)).collect();
+                format!("{}({})", function.glsl_name(), arguments.join(", "))
+            }
+        }
+    }
+
+    /**
+     * The partial derivative with respect to the variable at `index`, as another expression.
+     *
+     * Functions with steps (floor, sign...) are taken to be flat between them, the steps themselves are ignored. min and max follow whichever argument they pick.
+     */
+    pub fn derivative(&self, index: usize) -> Expression {
+        use Expression::Number;
+
+        match self {
+            Expression::Number(_) => Number(0.),
+            Expression::Variable(variable) => Number(if *variable == index { 1. } else { 0. }),
+            Expression::Negate(operand) => negate(operand.derivative(index)),
+            Expression::Binary(operator, left, right) => {
+                let (a, b) = (left.as_ref().clone(), right.as_ref().clone());
+                let (da, db) = (left.derivative(index), right.derivative(index));
+                match operator {
+                    Operator::Add => add(da, db),
+                    Operator::Subtract => subtract(da, db),
+                    // a'b + ab'
+                    Operator::Multiply => add(multiply(da, b), multiply(a, db)),
+                    // (a'b - ab') / b^2
+                    Operator::Divide => divide(
+                        subtract(multiply(da, b.clone()), multiply(a, db)),
+                        multiply(b.clone(), b),
+                    ),
+                    Operator::Power => power_derivative(a, b, da, db, index),
+                }
+            }
+            Expression::Call(function, arguments) => {
+                let a = arguments[0].clone();
+                let da = arguments[0].derivative(index);
+                let call = |function: Function, arguments: Vec<Expression>| Expression::Call(function, arguments);
+                match function {
+                    Function::Sin => multiply(call(Function::Cos, vec![a]), da),
+                    Function::Cos => negate(multiply(call(Function::Sin, vec![a]), da)),
+                    // a' / cos(a)^2
+                    Function::Tan => {
+                        let cos = call(Function::Cos, vec![a]);
+                        divide(da, multiply(cos.clone(), cos))
+                    }
+                    // ±a' / sqrt(1 - a^2)
+                    Function::Asin | Function::Acos => {
+                        let root = call(Function::Sqrt, vec![subtract(Number(1.), multiply(a.clone(), a))]);
+                        let value = divide(da, root);
+                        if *function == Function::Asin { value } else { negate(value) }
+                    }
+                    // a' / (1 + a^2)
+                    Function::Atan => divide(da, add(Number(1.), multiply(a.clone(), a))),
+                    // atan2(a, b)' = (b a' - a b') / (a^2 + b^2)
+                    Function::Atan2 => {
+                        let b = arguments[1].clone();
+                        let db = arguments[1].derivative(index);
+                        divide(
+                            subtract(multiply(b.clone(), da), multiply(a.clone(), db)),
+                            add(multiply(a.clone(), a), multiply(b.clone(), b)),
+                        )
+                    }
+                    Function::Exp => multiply(self.clone(), da),
+                    Function::Log => divide(da, a),
+                    Function::Sqrt => divide(da, multiply(Number(2.), self.clone())),
+                    Function::Abs => multiply(call(Function::Sign, vec![a]), da),
+                    Function::Sign | Function::Floor | Function::Ceil => Number(0.),
+                    Function::Fract => da,
+                    // mod(a, b) = a - b floor(a / b)
+                    Function::Mod => {
+                        let b = arguments[1].clone();
+                        let db = arguments[1].derivative(index);
+                        subtract(da, multiply(db, call(Function::Floor, vec![divide(a, b)])))
+                    }
+                    /*
+                    (a' + b') / 2 ± sign(a - b) (a' - b') / 2, which is a' on one side and b' on the other without needing a branch.
+                    */
+                    Function::Min | Function::Max => {
+                        let b = arguments[1].clone();
+                        let db = arguments[1].derivative(index);
+                        let mean = divide(add(da.clone(), db.clone()), Number(2.));
+                        let half_difference = divide(
+                            multiply(call(Function::Sign, vec![subtract(a, b)]), subtract(da, db)),
+                            Number(2.),
+                        );
+                        if *function == Function::Max {
+                            add(mean, half_difference)
+                        } else {
+                            subtract(mean, half_difference)
+                        }
+                    }
+                    Function::Pow => {
+                        let b = arguments[1].clone();
+                        let db = arguments[1].derivative(index);
+                        power_derivative(a, b, da, db, index)
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * Whether the variable at `index` appears anywhere, so e.g. surfaces which don't depend on t aren't recomputed every frame.
+     */
+    pub fn uses_variable(&self, index: usize) -> bool {
+        match self {
+            Expression::Number(_) => false,
+            Expression::Variable(variable) => *variable == index,
+            Expression::Negate(operand) => operand.uses_variable(index),
+            Expression::Binary(_, left, right) => left.uses_variable(index) || right.uses_variable(index),
+            Expression::Call(_, arguments) => arguments.iter().any(|argument| argument.uses_variable(index)),
+        }
+    }
+}
+
+/*
+Constructors for derivatives which fold constants and drop the terms multiplying by 0 (most of them, as a derivative with respect to x zeroes everything in y and t) so the GLSL stays short.
+*/
+fn is_number(expression: &Expression, value: f32) -> bool {
+    *expression == Expression::Number(value)
+}
+
+fn negate(a: Expression) -> Expression {
+    if is_number(&a, 0.) {
+        return a;
+    }
+    Expression::Negate(Box::new(a))
+}
+
+fn add(a: Expression, b: Expression) -> Expression {
+    if let (Expression::Number(a), Expression::Number(b)) = (&a, &b) {
+        return Expression::Number(a + b);
+    }
+    if is_number(&a, 0.) {
+        return b;
+    }
+    if is_number(&b, 0.) {
+        return a;
+    }
+    Expression::Binary(Operator::Add, Box::new(a), Box::new(b))
+}
+
+fn subtract(a: Expression, b: Expression) -> Expression {
+    if let (Expression::Number(a), Expression::Number(b)) = (&a, &b) {
+        return Expression::Number(a - b);
+    }
+    if is_number(&b, 0.) {
+        return a;
+    }
+    if is_number(&a, 0.) {
+        return negate(b);
+    }
+    Expression::Binary(Operator::Subtract, Box::new(a), Box::new(b))
+}
+
+fn multiply(a: Expression, b: Expression) -> Expression {
+    if let (Expression::Number(a), Expression::Number(b)) = (&a, &b) {
+        return Expression::Number(a * b);
+    }
+    if is_number(&a, 0.) || is_number(&b, 0.) {
+        return Expression::Number(0.);
+    }
+    if is_number(&a, 1.) {
+        return b;
+    }
+    if is_number(&b, 1.) {
+        return a;
+    }
+    Expression::Binary(Operator::Multiply, Box::new(a), Box::new(b))
+}
+
+fn divide(a: Expression, b: Expression) -> Expression {
+    if let (Expression::Number(a), Expression::Number(b)) = (&a, &b) {
+        return Expression::Number(a / b);
+    }
+    if is_number(&a, 0.) {
+        return a;
+    }
+    Expression::Binary(Operator::Divide, Box::new(a), Box::new(b))
+}
+
+/*
+(a^b)' = b a^(b - 1) a' when b doesn't depend on the variable (which also holds for negative a with whole b), otherwise a^b (b' log(a) + b a' / a).
+*/
+fn power_derivative(a: Expression, b: Expression, da: Expression, db: Expression, index: usize) -> Expression {
+    let pow = |base: Expression, exponent: Expression| Expression::Binary(Operator::Power, Box::new(base), Box::new(exponent));
+
+    if !b.uses_variable(index) {
+        let lowered = pow(a, subtract(b.clone(), Expression::Number(1.)));
+        return multiply(multiply(b, lowered), da);
+    }
+
+    let log = Expression::Call(Function::Log, vec![a.clone()]);
+    multiply(
+        pow(a.clone(), b.clone()),
+        add(multiply(db, log), divide(multiply(b, da), a)),
+    )
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Number(f32),
+    Name(String),
+    Symbol(char),
+}
+
+#[derive(Clone)]
+struct Token {
+    kind: TokenKind,
+    // Byte offset into the source, for errors
+    start: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, MVisualError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            let mut previous = c;
+            while let Some(&(index, c)) = chars.peek() {
+                // Exponents may carry a sign, "1e-3"
+                let exponent_sign = (c == '-' || c == '+') && (previous == 'e' || previous == 'E');
+                if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign) {
+                    break;
+                }
+                end = index + c.len_utf8();
+                previous = c;
+                chars.next();
+            }
+            let text = &source[start..end];
+            // Too big for f32 parses as infinity, which has no GLSL literal
+            let value = text.parse::<f32>().ok().filter(|value| value.is_finite()).ok_or_else(|| {
+                MVisualError::Input(format!("Invalid number \"{}\" at column {} of \"{}\"", text, start + 1, source))
+            })?;
+            tokens.push(Token {
+                kind: TokenKind::Number(value),
+                start,
+            });
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(index, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = index + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token {
+                kind: TokenKind::Name(String::from(&source[start..end])),
+                start,
+            });
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token {
+                kind: TokenKind::Symbol(c),
+                start,
+            });
+            chars.next();
+        } else {
+            return Err(MVisualError::Input(format!(
+                "Unexpected \"{}\" at column {} of \"{}\"",
+                c,
+                start + 1,
+                source
+            )));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    variables: &'a [&'a str],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_symbol_is(&self, symbol: char) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Symbol(c), .. }) if *c == symbol)
+    }
+
+    fn error(&self, token: &Token, message: &str) -> MVisualError {
+        MVisualError::Input(format!("{} at column {} of \"{}\"", message, token.start + 1, self.source))
+    }
+
+    fn end_error(&self, message: &str) -> MVisualError {
+        MVisualError::Input(format!("{} at the end of \"{}\"", message, self.source))
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), MVisualError> {
+        if self.next_symbol_is(symbol) {
+            self.position += 1;
+            return Ok(());
+        }
+        let message = format!("Expected \"{}\"", symbol);
+        Err(match self.peek() {
+            Some(token) => self.error(token, &message),
+            None => self.end_error(&message),
+        })
+    }
+
+    fn expression(&mut self) -> Result<Expression, MVisualError> {
+        let mut left = self.term()?;
+        loop {
+            let operator = if self.next_symbol_is('+') {
+                Operator::Add
+            } else if self.next_symbol_is('-') {
+                Operator::Subtract
+            } else {
+                return Ok(left);
+            };
+            self.position += 1;
+            left = Expression::Binary(operator, Box::new(left), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expression, MVisualError> {
+        let mut left = self.unary()?;
+        loop {
+            let operator = if self.next_symbol_is('*') {
+                Operator::Multiply
+            } else if self.next_symbol_is('/') {
+                Operator::Divide
+            } else {
+                return Ok(left);
+            };
+            self.position += 1;
+            left = Expression::Binary(operator, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, MVisualError> {
+        if self.next_symbol_is('-') {
+            self.position += 1;
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expression, MVisualError> {
+        let base = self.primary()?;
+        if self.next_symbol_is('^') {
+            self.position += 1;
+            // The exponent may itself be negative or another power, "2^-x", "2^3^2"
+            let exponent = self.unary()?;
+            return Ok(Expression::Binary(Operator::Power, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expression, MVisualError> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.end_error("Expected a value")),
+        };
+        self.position += 1;
+
+        match token.kind.clone() {
+            TokenKind::Number(value) => Ok(Expression::Number(value)),
+            TokenKind::Symbol('(') => {
+                let inner = self.expression()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            TokenKind::Symbol(_) => Err(self.error(&token, "Expected a value")),
+            TokenKind::Name(name) => {
+                if self.next_symbol_is('(') {
+                    return self.call(&token, &name);
+                }
+                if let Some(index) = self.variables.iter().position(|variable| *variable == name) {
+                    return Ok(Expression::Variable(index));
+                }
+                match name.as_str() {
+                    "pi" => Ok(Expression::Number(std::f32::consts::PI)),
+                    "e" => Ok(Expression::Number(std::f32::consts::E)),
+                    _ => Err(self.error(&token, &format!(
+                        "Unknown name \"{}\", the variables are {}",
+                        name,
+                        self.variables.join(", ")
+                    ))),
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, token: &Token, name: &str) -> Result<Expression, MVisualError> {
+        let function = Function::from_name(name)
+            .ok_or_else(|| self.error(token, &format!("Unknown function \"{}\"", name)))?;
+
+        self.expect('(')?;
+        let mut arguments = vec![self.expression()?];
+        while self.next_symbol_is(',') {
+            self.position += 1;
+            arguments.push(self.expression()?);
+        }
+        self.expect(')')?;
+
+        // atan(y, x) is also accepted, as in GLSL
+        let function = if function == Function::Atan && arguments.len() == 2 {
+            Function::Atan2
+        } else {
+            function
+        };
+
+        if arguments.len() != function.arity() {
+            return Err(self.error(token, &format!(
+                "{} takes {} argument{}, got {}",
+                name,
+                function.arity(),
+                if function.arity() == 1 { "" } else { "s" },
+                arguments.len()
+            )));
+        }
+
+        Ok(Expression::Call(function, arguments))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARIABLES: [&str; 3] = ["x", "y", "t"];
+
+    // Covers every operator and function, with the precedence cases that are easy to get wrong
+    const EXPRESSIONS: [&str; 16] = [
+        "sin(3 * x - t) * cos(y)",
+        "-x^2 + y^2 / 2",
+        "2^-x^2",
+        "1 - 2 - 3 * x / 4 / y",
+        "exp(-(x^2 + y^2) / 4) * sqrt(abs(x * y) + 1)",
+        "log(x^2 + 1) + ln(y^2 + 2)",
+        "tan(x / 3) + atan(y) + atan(y, x) + atan2(x, y + 0.5)",
+        "asin(x / 4) - acos(y / 4)",
+        "mod(x * 3, 1.5) + fract(y) - floor(t) + ceil(x)",
+        "min(x, y) * max(x, t) + sign(x - y)",
+        "pow(x, 3) - pow(abs(y) + 0.1, 1.5)",
+        "x^3 - (-y)^3 + x^-2",
+        "0.1 * x + 1e-3 * y + 2.5e2 * t",
+        "pi * e * x",
+        "sin(x) * sin(x) + cos(x)^2",
+        "--x - -(-y)",
+    ];
+
+    fn points() -> Vec<[f32; 3]> {
+        let mut points = Vec::new();
+        for i in 0..7 {
+            for j in 0..7 {
+                let x = -3. + i as f32;
+                let y = -2.7 + 0.9 * j as f32;
+                points.push([x, y, 0.37 * (i + j) as f32]);
+            }
+        }
+        points
+    }
+
+    fn same(a: f32, b: f32) -> bool {
+        a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan())
+    }
+
+    /*
+    The GLSL only uses syntax the expression language also has, so reading it back in (with `expressionPow` for `pow`, which it stands in for) and evaluating it on the CPU has to give exactly the original values. That catches precedence, grouping and literals coming out differently in the shader, whether GLSL's functions compute what the CPU's do is checked against the `glsl` module below.
+    */
+    #[test]
+    fn glsl_keeps_the_grouping_and_literals() {
+        for source in EXPRESSIONS.iter() {
+            let expression = Expression::parse(source, &VARIABLES).unwrap();
+            let glsl = expression.to_glsl(&VARIABLES);
+            let compiled = Expression::parse(&glsl.replace("expressionPow", "pow"), &VARIABLES)
+                .unwrap_or_else(|error| panic!("{} compiled to unreadable GLSL {}: {}", source, glsl, error));
+
+            for point in points() {
+                let expected = expression.evaluate(&point);
+                let actual = compiled.evaluate(&point);
+                assert!(same(expected, actual), "{} at {:?}: CPU {} but GLSL {} gives {}", source, point, expected, glsl, actual);
+            }
+        }
+    }
+
+    /*
+    The GLSL ES 1.0 built-ins compiled expressions use, as the spec defines them. `pow` is exp2(y * log2(x)) as drivers compute it, the spec leaves negative bases undefined and this gives NaN for them.
+    */
+    mod glsl {
+        pub fn pow(x: f32, y: f32) -> f32 {
+            (y * x.log2()).exp2()
+        }
+
+        pub fn modulo(x: f32, y: f32) -> f32 {
+            x - y * (x / y).floor()
+        }
+
+        pub fn fract(x: f32) -> f32 {
+            x - x.floor()
+        }
+
+        pub fn sign(x: f32) -> f32 {
+            if x > 0. {
+                1.
+            } else if x < 0. {
+                -1.
+            } else {
+                0.
+            }
+        }
+
+        // `expressionPow` from the "expression" chunk, line for line
+        pub fn expression_pow(base: f32, exponent: f32) -> f32 {
+            if base < 0. && fract(exponent) == 0. {
+                let magnitude = pow(-base, exponent);
+                return if modulo(exponent, 2.) == 0. { magnitude } else { -magnitude };
+            }
+            pow(base, exponent)
+        }
+
+        // What `expression_pow` was written from, so it can't drift from the chunk unnoticed
+        pub const EXPRESSION_POW: [&str; 5] = [
+            "if (base < 0.0 && fract(exponent) == 0.0) {",
+            "float magnitude = pow(-base, exponent);",
+            "return mod(exponent, 2.0) == 0.0 ? magnitude : -magnitude;",
+            "}",
+            "return pow(base, exponent);",
+        ];
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a.is_nan() && b.is_nan()) || (a - b).abs() <= 1e-5 * a.abs().max(1.)
+    }
+
+    #[test]
+    fn expression_pow_is_the_chunk() {
+        let chunk = crate::shaders::chunks::expression::SHADER;
+        let lines: Vec<&str> = chunk.lines().map(str::trim).collect();
+        for line in glsl::EXPRESSION_POW.iter() {
+            assert!(lines.contains(line), "The expression chunk no longer has \"{}\"", line);
+        }
+    }
+
+    #[test]
+    fn expression_pow_matches_power() {
+        let bases = [-3., -2., -1.5, -1., -0.5, 0., 0.5, 1., 2.5];
+        // Odd, even and fractional, either side of 0
+        let exponents = [-3., -2., -1., 0., 1., 2., 3., 4., 0.5, -0.5, 1.5, -2.25];
+        for &base in bases.iter() {
+            for &exponent in exponents.iter() {
+                // Undefined in GLSL
+                if base == 0. && exponent <= 0. {
+                    continue;
+                }
+                let gpu = glsl::expression_pow(base, exponent);
+                for cpu in [power(base, exponent), Function::Pow.apply(&[base, exponent])].iter() {
+                    assert!(close(*cpu, gpu), "{}^{}: CPU {} but GPU {}", base, exponent, cpu, gpu);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn negative_bases_keep_the_sign_of_odd_exponents() {
+        assert_eq!(glsl::expression_pow(-2., 3.), -8.);
+        assert_eq!(glsl::expression_pow(-2., -3.), -0.125);
+        assert_eq!(glsl::expression_pow(-2., 2.), 4.);
+        assert!(glsl::expression_pow(-2., 0.5).is_nan());
+        // GLSL's own pow has nothing to give for any of them
+        assert!(glsl::pow(-2., 2.).is_nan());
+    }
+
+    #[test]
+    fn glsl_builtins_match_apply() {
+        let values = [-7.5, -3., -1.25, -1., -0.3, 0., 0.3, 1., 2.75, 6.];
+        for &a in values.iter() {
+            assert!(same(Function::Fract.apply(&[a]), glsl::fract(a)), "fract({})", a);
+            assert!(same(Function::Sign.apply(&[a]), glsl::sign(a)), "sign({})", a);
+            for &b in [-2.5, -1., 0.7, 3.].iter() {
+                assert!(same(Function::Mod.apply(&[a, b]), glsl::modulo(a, b)), "mod({}, {})", a, b);
+            }
+        }
+    }
+
+    /*
+    GLSL's atan(y, x) takes y first like `atan2`, and `to_glsl` has to keep the arguments in that order.
+    */
+    #[test]
+    fn two_argument_atan_takes_y_first() {
+        use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+        let quadrants = [
+            ((1., 1.), FRAC_PI_4),
+            ((1., -1.), 3. * FRAC_PI_4),
+            ((-1., -1.), -3. * FRAC_PI_4),
+            ((-1., 1.), -FRAC_PI_4),
+            ((1., 0.), FRAC_PI_2),
+            ((0., -1.), PI),
+        ];
+        for ((y, x), angle) in quadrants.iter() {
+            assert!(close(Function::Atan2.apply(&[*y, *x]), *angle), "atan({}, {})", y, x);
+        }
+        assert_eq!(parse("atan(y, x)").to_glsl(&VARIABLES), "atan(y, x)");
+        assert_eq!(parse("atan2(y, x)").to_glsl(&VARIABLES), "atan(y, x)");
+    }
+
+    #[test]
+    fn glsl_floats_are_valid_literals() {
+        for value in [1., 0.1, 250., 1e-7, 3.4e38, 0.].iter() {
+            let glsl = Expression::Number(*value).to_glsl(&VARIABLES);
+            assert!(glsl.contains('.') || glsl.contains('e'), "{} is an int in GLSL", glsl);
+        }
+    }
+
+    /*
+    Analytic derivatives against central differences of the CPU evaluator, in both x and y. Points are kept away from the steps of floor, sign and friends.
+    */
+    #[test]
+    fn derivatives_match_finite_differences() {
+        let smooth = [
+            "sin(3 * x - t) * cos(y)",
+            "-x^2 + y^2 / 2",
+            "2^-x^2",
+            "exp(-(x^2 + y^2) / 4) * sqrt(x * x + y * y + 1)",
+            "log(x^2 + 1) / (y^2 + 2)",
+            "tan(x / 3) + atan(y) + atan(y, x + 5)",
+            "asin(x / 4) - acos(y / 4)",
+            "mod(x * 3, 1.7) + fract(y) + floor(t)",
+            "min(x, y) * max(x, 2 * t) + abs(x - 0.3)",
+            "pow(x + 4, y / 3) + x^3 * y^-2",
+        ];
+        let h = 1e-3;
+
+        for source in smooth.iter() {
+            let expression = Expression::parse(source, &VARIABLES).unwrap();
+            for point in [[0.7, 1.3, 0.4], [-1.9, -0.6, 1.1], [2.3, 2.2, 2.9]].iter() {
+                for variable in 0..2 {
+                    let mut above = *point;
+                    let mut below = *point;
+                    above[variable] += h;
+                    below[variable] -= h;
+                    let estimate = (expression.evaluate(&above) - expression.evaluate(&below)) / (2. * h);
+                    let exact = expression.derivative(variable).evaluate(point);
+
+                    let tolerance = 1e-2 * exact.abs().max(1.);
+                    assert!(
+                        (estimate - exact).abs() < tolerance,
+                        "d/d{} of {} at {:?}: analytic {} but finite difference {}",
+                        VARIABLES[variable], source, point, exact, estimate
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn derivatives_drop_other_variables() {
+        let expression = Expression::parse("x^2 + sin(y) * t", &VARIABLES).unwrap();
+        let derivative = expression.derivative(0);
+        assert!(!derivative.uses_variable(1));
+        assert!(!derivative.uses_variable(2));
+        assert_eq!(derivative.evaluate(&[3., 100., 100.]), 6.);
+    }
+
+    #[test]
+    fn power_allows_negative_bases_with_whole_exponents() {
+        let evaluate = |source| Expression::parse(source, &VARIABLES).unwrap().evaluate(&[-2., 0., 0.]);
+        assert_eq!(evaluate("x^2"), 4.);
+        assert_eq!(evaluate("x^3"), -8.);
+        assert_eq!(evaluate("x^-1"), -0.5);
+        assert!(evaluate("x^0.5").is_nan());
+        // Unary minus applies after the power
+        assert_eq!(evaluate("-x^2"), -4.);
+    }
+
+    fn parse(source: &str) -> Expression {
+        Expression::parse(source, &VARIABLES).unwrap_or_else(|error| panic!("{} didn't parse: {}", source, error))
+    }
+
+    fn number(value: f32) -> Box<Expression> {
+        Box::new(Expression::Number(value))
+    }
+
+    fn variable(index: usize) -> Box<Expression> {
+        Box::new(Expression::Variable(index))
+    }
+
+    fn binary(operator: Operator, a: Box<Expression>, b: Box<Expression>) -> Box<Expression> {
+        Box::new(Expression::Binary(operator, a, b))
+    }
+
+    #[test]
+    fn products_bind_tighter_than_sums() {
+        assert_eq!(
+            parse("1 + 2 * x"),
+            *binary(Operator::Add, number(1.), binary(Operator::Multiply, number(2.), variable(0)))
+        );
+        assert_eq!(
+            parse("(1 + 2) * x"),
+            *binary(Operator::Multiply, binary(Operator::Add, number(1.), number(2.)), variable(0))
+        );
+    }
+
+    #[test]
+    fn sums_and_products_group_to_the_left() {
+        assert_eq!(
+            parse("x - y - t"),
+            *binary(Operator::Subtract, binary(Operator::Subtract, variable(0), variable(1)), variable(2))
+        );
+        assert_eq!(
+            parse("x / y * t"),
+            *binary(Operator::Multiply, binary(Operator::Divide, variable(0), variable(1)), variable(2))
+        );
+    }
+
+    #[test]
+    fn powers_group_to_the_right_and_bind_tighter_than_minus() {
+        assert_eq!(
+            parse("x^y^t"),
+            *binary(Operator::Power, variable(0), binary(Operator::Power, variable(1), variable(2)))
+        );
+        assert_eq!(
+            parse("-x^2"),
+            Expression::Negate(binary(Operator::Power, variable(0), number(2.)))
+        );
+        assert_eq!(
+            parse("2^-x"),
+            *binary(Operator::Power, number(2.), Box::new(Expression::Negate(variable(0))))
+        );
+    }
+
+    #[test]
+    fn names_are_variables_constants_or_functions() {
+        assert_eq!(parse("t"), Expression::Variable(2));
+        assert_eq!(parse("pi"), Expression::Number(std::f32::consts::PI));
+        assert_eq!(parse("e"), Expression::Number(std::f32::consts::E));
+        assert_eq!(parse("ln(x)"), Expression::Call(Function::Log, vec![Expression::Variable(0)]));
+        assert_eq!(
+            parse("atan(y, x)"),
+            Expression::Call(Function::Atan2, vec![Expression::Variable(1), Expression::Variable(0)])
+        );
+        // Variables are whatever the caller names, they can shadow the constants
+        assert_eq!(Expression::parse("e", &["e"]).unwrap(), Expression::Variable(0));
+    }
+
+    #[test]
+    fn numbers_and_whitespace() {
+        assert_eq!(parse(" .5 "), Expression::Number(0.5));
+        assert_eq!(parse("2.5e-1"), Expression::Number(0.25));
+        assert_eq!(parse("1E+2"), Expression::Number(100.));
+        assert_eq!(parse("x*y"), parse("  x  *\ty "));
+    }
+
+    #[test]
+    fn evaluates_with_the_precedence_it_parsed_with() {
+        let evaluate = |source| parse(source).evaluate(&[3., 2., 0.5]);
+        assert_eq!(evaluate("1 - 2 - 3"), -4.);
+        assert_eq!(evaluate("8 / 4 / 2"), 1.);
+        assert_eq!(evaluate("2^3^2"), 512.);
+        assert_eq!(evaluate("x + y * t"), 4.);
+        assert_eq!(evaluate("(x + y) * t"), 2.5);
+        assert_eq!(evaluate("-y^2"), -4.);
+        assert_eq!(evaluate("(-y)^2"), 4.);
+        assert_eq!(evaluate("y^-1"), 0.5);
+        assert_eq!(evaluate("--x"), 3.);
+    }
+
+    #[test]
+    fn functions_follow_glsl() {
+        let evaluate = |source| parse(source).evaluate(&[0., 0., 0.]);
+        // mod takes the sign of the divisor
+        assert_eq!(evaluate("mod(-1, 3)"), 2.);
+        assert_eq!(evaluate("mod(1, -3)"), -2.);
+        assert_eq!(evaluate("fract(-0.25)"), 0.75);
+        assert_eq!(evaluate("sign(0)"), 0.);
+        assert_eq!(evaluate("sign(-0.1)"), -1.);
+        assert_eq!(evaluate("atan(1, -1)"), 3. * std::f32::consts::FRAC_PI_4);
+        assert_eq!(evaluate("min(1, 2) + max(1, 2)"), 3.);
+        assert_eq!(evaluate("pow(2, 10)"), 1024.);
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let message = |source| Expression::parse(source, &VARIABLES).unwrap_err().to_string();
+        assert!(message("x + ").contains("at the end"));
+        assert!(message("x + z").contains("column 5"));
+        assert!(message("foo(x)").contains("Unknown function"));
+        assert!(message("min(x)").contains("takes 2 arguments"));
+        assert!(message("x y").contains("column 3"));
+        assert!(message("1e99").contains("Invalid number"));
+        assert!(message("").contains("Expected a value at the end"));
+        assert!(message("(x + 1").contains("Expected \")\" at the end"));
+        assert!(message("x )").contains("Expected an operator at column 3"));
+        assert!(message("x $ 1").contains("Unexpected \"$\" at column 3"));
+        assert!(message("* x").contains("Expected a value at column 1"));
+    }
+}
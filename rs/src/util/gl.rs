@@ -0,0 +1,346 @@
+use std::cell::Cell;
+use std::ops::Deref;
+use wasm_bindgen::JsCast;
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use crate::error::MVisualError;
+use super::lighting::Lighting;
+use super::perf::FrameCounters;
+
+/*
+Binding point every lit program's `Lighting` uniform block is attached to.
+*/
+pub const LIGHTING_BLOCK_BINDING: u32 = 0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backend {
+    WebGl1,
+    WebGl2,
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "webgl1" => Some(Backend::WebGl1),
+            "webgl2" => Some(Backend::WebGl2),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::WebGl1 => "webgl1",
+            Backend::WebGl2 => "webgl2",
+        }
+    }
+}
+
+/*
+The rendering context handed to every program.
+
+WebGL2 is a superset of WebGL1 and web-sys calls methods by name, so a WebGL2 context can be used through the `WebGlRenderingContext` API. `Gl` derefs to that API, which is all most programs need, and adds the features which differ between the two:
+
+- Vertex array objects, from WebGL2 or the OES_vertex_array_object extension
+- Instanced drawing, from WebGL2 or the ANGLE_instanced_arrays extension
+- 32 bit indices, from WebGL2 or the OES_element_index_uint extension
+- Uniform buffers (WebGL2 only), used to share the scene's lights between programs
+
+Each of these is optional, callers check for it and fall back to the WebGL1 way.
+
+`Gl` also counts the work it is given for the frame stats. Draws and state changes are counted by methods sharing their names with the `WebGlRenderingContext` methods they wrap, so calls made through a `Gl` are counted without the caller doing anything, uploads are reported with `count_upload` by whatever knows their size.
+*/
+pub struct Gl {
+    context: WebGlRenderingContext,
+    webgl2: Option<WebGl2RenderingContext>,
+    vertex_array_extension: Option<OesVertexArrayObject>,
+    instancing_extension: Option<AngleInstancedArrays>,
+    uint_indices: bool,
+    lighting_buffer: Option<WebGlBuffer>,
+    // Since the last `take_counters`
+    counters: Cell<FrameCounters>,
+}
+
+impl Gl {
+    /**
+     * Creates a context on `canvas`. `Backend::WebGl2` falls back to WebGL1 when WebGL2 isn't available, `Backend::WebGl1` never tries WebGL2.
+     */
+    pub fn new(canvas: &HtmlCanvasElement, preferred: Backend) -> Result<Self, MVisualError> {
+        if preferred == Backend::WebGl2 {
+            if let Some(context) = canvas.get_context("webgl2").map_err(MVisualError::context)? {
+                let webgl2: WebGl2RenderingContext = context
+                    .dyn_into()
+                    .map_err(|_| MVisualError::Context(String::from("Context is not a WebGl2RenderingContext")))?;
+                let lighting_buffer = webgl2
+                    .create_buffer()
+                    .ok_or_else(|| MVisualError::Buffer(String::from("Failed to create lighting buffer")))?;
+
+                return Ok(Self {
+                    context: webgl2.clone().unchecked_into(),
+                    webgl2: Some(webgl2),
+                    vertex_array_extension: None,
+                    instancing_extension: None,
+                    uint_indices: true,
+                    lighting_buffer: Some(lighting_buffer),
+                    counters: Cell::default(),
+                });
+            }
+        }
+
+        // `get_context` gives `None` when the browser (or GPU) doesn't support WebGL
+        let context: WebGlRenderingContext = canvas
+            .get_context("webgl")
+            .map_err(MVisualError::context)?
+            .ok_or_else(|| MVisualError::Context(String::from("WebGL is not supported")))?
+            .dyn_into()
+            .map_err(|_| MVisualError::Context(String::from("Context is not a WebGlRenderingContext")))?;
+
+        let mut gl = Self {
+            context,
+            webgl2: None,
+            vertex_array_extension: None,
+            instancing_extension: None,
+            uint_indices: false,
+            lighting_buffer: None,
+            counters: Cell::default(),
+        };
+        gl.load_extensions();
+
+        Ok(gl)
+    }
+
+    /*
+    Looks up the WebGL1 extensions standing in for WebGL2 features, WebGL2 has them built in.
+    */
+    fn load_extensions(&mut self) {
+        if self.webgl2.is_some() {
+            return;
+        }
+
+        let context = &self.context;
+        let extension = |name: &str| context.get_extension(name).ok().flatten();
+        self.vertex_array_extension = extension("OES_vertex_array_object").map(|object| object.unchecked_into());
+        self.instancing_extension = extension("ANGLE_instanced_arrays").map(|object| object.unchecked_into());
+        self.uint_indices = extension("OES_element_index_uint").is_some();
+    }
+
+    /**
+     * Recreates the context's own resources after it was lost and restored. Extension objects and the lighting buffer belong to the lost context, the context object itself survives.
+     */
+    pub fn restore(&mut self) -> Result<(), MVisualError> {
+        self.load_extensions();
+        if let Some(webgl2) = &self.webgl2 {
+            self.lighting_buffer = Some(
+                webgl2
+                    .create_buffer()
+                    .ok_or_else(|| MVisualError::Buffer(String::from("Failed to create lighting buffer")))?,
+            );
+        }
+
+        Ok(())
+    }
+
+    /**
+     * The canvas the context draws to.
+     */
+    pub fn canvas(&self) -> Option<HtmlCanvasElement> {
+        self.context.canvas()?.dyn_into().ok()
+    }
+
+    pub fn backend(&self) -> Backend {
+        if self.webgl2.is_some() {
+            Backend::WebGl2
+        } else {
+            Backend::WebGl1
+        }
+    }
+
+    /**
+     * Whether `ELEMENT_ARRAY_BUFFER`s can hold u32 indices.
+     */
+    pub fn supports_uint_indices(&self) -> bool {
+        self.uint_indices
+    }
+
+    pub fn supports_uniform_buffers(&self) -> bool {
+        self.webgl2.is_some()
+    }
+
+    /**
+     * Creates a vertex array object, `None` when they aren't supported.
+     */
+    pub fn create_vertex_array(&self) -> Option<WebGlVertexArrayObject> {
+        if let Some(webgl2) = &self.webgl2 {
+            webgl2.create_vertex_array()
+        } else {
+            self.vertex_array_extension
+                .as_ref()
+                .and_then(|extension| extension.create_vertex_array_oes())
+        }
+    }
+
+    /**
+     * Binds a vertex array object, `None` goes back to the default attribute state every other program uses.
+     */
+    pub fn bind_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+        self.count_state_change();
+        if let Some(webgl2) = &self.webgl2 {
+            webgl2.bind_vertex_array(vertex_array);
+        } else if let Some(extension) = &self.vertex_array_extension {
+            extension.bind_vertex_array_oes(vertex_array);
+        }
+    }
+
+    pub fn delete_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+        if let Some(webgl2) = &self.webgl2 {
+            webgl2.delete_vertex_array(vertex_array);
+        } else if let Some(extension) = &self.vertex_array_extension {
+            extension.delete_vertex_array_oes(vertex_array);
+        }
+    }
+
+    pub fn supports_instancing(&self) -> bool {
+        self.webgl2.is_some() || self.instancing_extension.is_some()
+    }
+
+    /**
+     * How many instances pass before the attribute moves to its next value, 0 means every vertex.
+     */
+    pub fn vertex_attrib_divisor(&self, index: u32, divisor: u32) {
+        if let Some(webgl2) = &self.webgl2 {
+            webgl2.vertex_attrib_divisor(index, divisor);
+        } else if let Some(extension) = &self.instancing_extension {
+            extension.vertex_attrib_divisor_angle(index, divisor);
+        }
+    }
+
+    /**
+     * Draws `instances` copies of the indexed geometry. Only call when `supports_instancing` is true.
+     */
+    pub fn draw_elements_instanced(&self, mode: u32, count: i32, index_type: u32, instances: i32) {
+        self.count(|counters| counters.add_draw(mode, count, instances));
+        if let Some(webgl2) = &self.webgl2 {
+            webgl2.draw_elements_instanced_with_i32(mode, count, index_type, 0, instances);
+        } else if let Some(extension) = &self.instancing_extension {
+            extension.draw_elements_instanced_angle_with_i32(mode, count, index_type, 0, instances);
+        }
+    }
+
+    /**
+     * Attaches a program's `Lighting` uniform block (if it has one) to `LIGHTING_BLOCK_BINDING`.
+     */
+    pub fn bind_lighting_block(&self, program: &WebGlProgram) {
+        if let Some(webgl2) = &self.webgl2 {
+            let index = webgl2.get_uniform_block_index(program, "Lighting");
+            if index != WebGl2RenderingContext::INVALID_INDEX {
+                webgl2.uniform_block_binding(program, index, LIGHTING_BLOCK_BINDING);
+            }
+        }
+    }
+
+    /**
+     * Uploads the scene's lights into the shared uniform buffer, once per frame instead of once per program. Does nothing without uniform buffers.
+     *
+     * Only the lights are shared, the camera isn't. Programs don't all look through the same view (the demo sphere keeps a fixed camera of its own) and each one multiplies its model matrix into the view and projection on the CPU anyway, so `uView` stays a per program uniform.
+     */
+    pub fn upload_lighting(&self, lighting: &Lighting) {
+        if let (Some(webgl2), Some(buffer)) = (&self.webgl2, &self.lighting_buffer) {
+            let data = lighting.uniform_block();
+            self.count_upload(4 * data.len());
+            let array = js_sys::Float32Array::from(data.as_slice());
+            webgl2.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(buffer));
+            webgl2.buffer_data_with_array_buffer_view(
+                WebGl2RenderingContext::UNIFORM_BUFFER,
+                &array,
+                GL::DYNAMIC_DRAW,
+            );
+            webgl2.bind_buffer_base(WebGl2RenderingContext::UNIFORM_BUFFER, LIGHTING_BLOCK_BINDING, Some(buffer));
+        }
+    }
+}
+
+/*
+Counted versions of `WebGlRenderingContext` methods, see `Gl`.
+*/
+impl Gl {
+    fn count(&self, update: impl FnOnce(&mut FrameCounters)) {
+        let mut counters = self.counters.get();
+        update(&mut counters);
+        self.counters.set(counters);
+    }
+
+    fn count_state_change(&self) {
+        self.count(|counters| counters.state_changes += 1);
+    }
+
+    /**
+     * Records `bytes` of data sent to the GPU.
+     */
+    pub fn count_upload(&self, bytes: usize) {
+        self.count(|counters| counters.bytes_uploaded += bytes as u64);
+    }
+
+    /**
+     * The counters since the last call, which starts them again from 0.
+     */
+    pub fn take_counters(&self) -> FrameCounters {
+        self.counters.take()
+    }
+
+    pub fn draw_arrays(&self, mode: u32, first: i32, count: i32) {
+        self.count(|counters| counters.add_draw(mode, count, 1));
+        self.context.draw_arrays(mode, first, count);
+    }
+
+    pub fn draw_elements_with_i32(&self, mode: u32, count: i32, index_type: u32, offset: i32) {
+        self.count(|counters| counters.add_draw(mode, count, 1));
+        self.context.draw_elements_with_i32(mode, count, index_type, offset);
+    }
+
+    pub fn use_program(&self, program: Option<&WebGlProgram>) {
+        self.count_state_change();
+        self.context.use_program(program);
+    }
+
+    pub fn bind_buffer(&self, target: u32, buffer: Option<&WebGlBuffer>) {
+        self.count_state_change();
+        self.context.bind_buffer(target, buffer);
+    }
+
+    pub fn bind_texture(&self, target: u32, texture: Option<&WebGlTexture>) {
+        self.count_state_change();
+        self.context.bind_texture(target, texture);
+    }
+
+    pub fn bind_framebuffer(&self, target: u32, framebuffer: Option<&WebGlFramebuffer>) {
+        self.count_state_change();
+        self.context.bind_framebuffer(target, framebuffer);
+    }
+
+    pub fn enable(&self, capability: u32) {
+        self.count_state_change();
+        self.context.enable(capability);
+    }
+
+    pub fn disable(&self, capability: u32) {
+        self.count_state_change();
+        self.context.disable(capability);
+    }
+
+    pub fn blend_func(&self, source: u32, destination: u32) {
+        self.count_state_change();
+        self.context.blend_func(source, destination);
+    }
+
+    pub fn depth_mask(&self, flag: bool) {
+        self.count_state_change();
+        self.context.depth_mask(flag);
+    }
+}
+
+impl Deref for Gl {
+    type Target = WebGlRenderingContext;
+
+    fn deref(&self) -> &WebGlRenderingContext {
+        &self.context
+    }
+}
@@ -0,0 +1,218 @@
+use wasm_bindgen::JsCast;
+use wasm_bindgen::JsValue;
+use web_sys::*;
+
+/*
+Glyphs are rasterized with the browser's 2D canvas (so we get the system fonts for free) and then turned into a signed distance field (SDF).
+
+Storing distances instead of coverage lets the fragment shader draw crisp edges at any scale from one small texture. See: https://steamcdn-a.akamaihd.net/apps/valve/2007/SIGGRAPH2007_AlphaTestedMagnification.pdf
+*/
+
+// Printable ASCII
+const FIRST_CHAR: u32 = 32;
+const LAST_CHAR: u32 = 126;
+
+// Size the glyphs are rasterized at, labels are scaled relative to this
+pub const ATLAS_FONT_SIZE: f32 = 40.;
+// How many pixels (at `ATLAS_FONT_SIZE`) the distance field reaches from the edge of a glyph
+pub const ATLAS_SPREAD: f32 = 8.;
+
+const CELL_SIZE: u32 = 64;
+const COLUMNS: u32 = 16;
+
+// Used in place of infinity by the distance transform (infinity - infinity = NaN)
+const FAR: f32 = 1e20;
+
+#[derive(Clone)]
+pub struct Glyph {
+    // Texture coordinates of the cell, (left, top, right, bottom)
+    pub uv: [f32; 4],
+    // Horizontal distance to the next glyph (at `ATLAS_FONT_SIZE`)
+    pub advance: f32,
+}
+
+#[derive(Clone)]
+pub struct GlyphAtlas {
+    pub width: u32,
+    pub height: u32,
+    // One distance value per pixel, 0.5 (127) is the edge of a glyph
+    pub pixels: Vec<u8>,
+    glyphs: Vec<Glyph>,
+}
+
+impl GlyphAtlas {
+    pub fn new(font_family: &str) -> Result<Self, JsValue> {
+        let glyph_count = LAST_CHAR - FIRST_CHAR + 1;
+        let rows = glyph_count.div_ceil(COLUMNS);
+        let width = COLUMNS * CELL_SIZE;
+        let height = rows * CELL_SIZE;
+
+        let document = window()
+            .ok_or("No window to build the glyph atlas in")?
+            .document()
+            .ok_or("No document to build the glyph atlas in")?;
+        let canvas: HtmlCanvasElement = document.create_element("canvas")?.dyn_into()?;
+        canvas.set_width(width);
+        canvas.set_height(height);
+        let context: CanvasRenderingContext2d = canvas
+            .get_context("2d")?
+            .ok_or("Unable to create 2D context for the glyph atlas")?
+            .dyn_into()?;
+
+        context.set_font(&format!("{}px {}", ATLAS_FONT_SIZE, font_family));
+        context.set_text_baseline("middle");
+        context.set_fill_style_str("white");
+
+        let mut glyphs = Vec::new();
+        for (i, code) in (FIRST_CHAR..(LAST_CHAR + 1)).enumerate() {
+            let character = std::char::from_u32(code).unwrap().to_string();
+            let column = i as u32 % COLUMNS;
+            let row = i as u32 / COLUMNS;
+            let x = (column * CELL_SIZE) as f64;
+            let y = (row * CELL_SIZE) as f64;
+
+            // Leave `ATLAS_SPREAD` on the left so the field has room to fall off
+            context.fill_text(&character, x + ATLAS_SPREAD as f64, y + CELL_SIZE as f64 / 2.)?;
+
+            glyphs.push(Glyph {
+                uv: [
+                    x as f32 / width as f32,
+                    y as f32 / height as f32,
+                    (x as f32 + CELL_SIZE as f32) / width as f32,
+                    (y as f32 + CELL_SIZE as f32) / height as f32,
+                ],
+                advance: context.measure_text(&character)?.width() as f32,
+            });
+        }
+
+        // Only the alpha channel matters, the text is drawn in solid white
+        let image = context.get_image_data(0., 0., width as f64, height as f64)?;
+        let coverage: Vec<u8> = image.data().iter().skip(3).step_by(4).copied().collect();
+
+        Ok(Self {
+            width,
+            height,
+            pixels: signed_distance_field(&coverage, width as usize, height as usize, ATLAS_SPREAD),
+            glyphs,
+        })
+    }
+
+    /**
+     * Looks up the glyph for a character, unsupported characters are shown as '?'.
+     */
+    pub fn glyph(&self, character: char) -> &Glyph {
+        let code = character as u32;
+        let code = if (FIRST_CHAR..(LAST_CHAR + 1)).contains(&code) {
+            code
+        } else {
+            '?' as u32
+        };
+
+        &self.glyphs[(code - FIRST_CHAR) as usize]
+    }
+
+    /**
+     * Width of a line of text (at `ATLAS_FONT_SIZE`).
+     */
+    pub fn measure(&self, text: &str) -> f32 {
+        text.chars().map(|character| self.glyph(character).advance).sum()
+    }
+
+    /**
+     * Size of a cell in the atlas (at `ATLAS_FONT_SIZE`). The glyph quads are drawn at this size.
+     */
+    pub fn cell_size(&self) -> f32 {
+        CELL_SIZE as f32
+    }
+}
+
+/*
+Converts glyph coverage (0-255) into a distance field mapped onto 0-255, where 127 is the outline, larger values are inside the glyph and `spread` pixels away from the edge saturates.
+*/
+fn signed_distance_field(coverage: &[u8], width: usize, height: usize, spread: f32) -> Vec<u8> {
+    let inside: Vec<bool> = coverage.iter().map(|value| *value > 127).collect();
+
+    // Squared distance to the closest pixel inside / outside of the glyph
+    let to_inside = squared_distance_transform(&inside, width, height);
+    let outside: Vec<bool> = inside.iter().map(|value| !value).collect();
+    let to_outside = squared_distance_transform(&outside, width, height);
+
+    to_inside
+        .iter()
+        .zip(to_outside.iter())
+        .map(|(to_inside, to_outside)| {
+            let signed_distance = to_inside.sqrt() - to_outside.sqrt();
+            let value = 0.5 - signed_distance / (2. * spread);
+            (value.clamp(0., 1.) * 255.) as u8
+        })
+        .collect()
+}
+
+/*
+Exact euclidean distance transform from Felzenszwalb and Huttenlocher, "Distance Transforms of Sampled Functions". See: https://cs.brown.edu/people/pfelzens/papers/dt-final.pdf
+
+The 2D transform is done by running the 1D transform over every column and then over every row.
+*/
+fn squared_distance_transform(seeds: &[bool], width: usize, height: usize) -> Vec<f32> {
+    let mut grid: Vec<f32> = seeds.iter().map(|seed| if *seed { 0. } else { FAR }).collect();
+
+    let longest = width.max(height);
+    let mut line = vec![0.; longest];
+    let mut result = vec![0.; longest];
+    let mut parabolas = vec![0; longest];
+    let mut boundaries = vec![0.; longest + 1];
+
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&line[..height], &mut result, &mut parabolas, &mut boundaries);
+        for y in 0..height {
+            grid[y * width + x] = result[y];
+        }
+    }
+
+    for y in 0..height {
+        line[..width].copy_from_slice(&grid[(y * width)..((y + 1) * width)]);
+        distance_transform_1d(&line[..width], &mut result, &mut parabolas, &mut boundaries);
+        grid[(y * width)..((y + 1) * width)].copy_from_slice(&result[..width]);
+    }
+
+    grid
+}
+
+/*
+Lower envelope of the parabolas rooted at each sample, `parabolas` and `boundaries` are scratch space (named v and z in the paper).
+*/
+fn distance_transform_1d(f: &[f32], result: &mut [f32], parabolas: &mut [usize], boundaries: &mut [f32]) {
+    let n = f.len();
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2. * q as f32 - 2. * p as f32)
+    };
+
+    let mut k = 0;
+    parabolas[0] = 0;
+    boundaries[0] = -FAR;
+    boundaries[1] = FAR;
+
+    for q in 1..n {
+        let mut s = intersection(q, parabolas[k]);
+        while s <= boundaries[k] {
+            k -= 1;
+            s = intersection(q, parabolas[k]);
+        }
+        k += 1;
+        parabolas[k] = q;
+        boundaries[k] = s;
+        boundaries[k + 1] = FAR;
+    }
+
+    k = 0;
+    for (q, value) in result.iter_mut().enumerate().take(n) {
+        while boundaries[k + 1] < q as f32 {
+            k += 1;
+        }
+        let offset = q as f32 - parabolas[k] as f32;
+        *value = offset * offset + f[parabolas[k]];
+    }
+}
@@ -0,0 +1,283 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use js_sys::{Object, WebAssembly};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use crate::error::MVisualError;
+use super::gl::Gl;
+
+/**
+ * Element types a `GpuBuffer` can hold, with the matching WebGL type and JS typed array.
+ */
+pub trait BufferElement: Copy + 'static {
+    // Type enum for `vertexAttribPointer` and `drawElements`
+    const GL_TYPE: u32;
+    const BYTES: usize;
+
+    /*
+    A typed array over `length` elements of wasm memory starting at element `start`.
+    */
+    fn js_array(memory: &JsValue, start: u32, length: u32) -> Object;
+}
+
+impl BufferElement for u8 {
+    const GL_TYPE: u32 = GL::UNSIGNED_BYTE;
+    const BYTES: usize = 1;
+
+    fn js_array(memory: &JsValue, start: u32, length: u32) -> Object {
+        js_sys::Uint8Array::new(memory).subarray(start, start + length).into()
+    }
+}
+
+impl BufferElement for u16 {
+    const GL_TYPE: u32 = GL::UNSIGNED_SHORT;
+    const BYTES: usize = 2;
+
+    fn js_array(memory: &JsValue, start: u32, length: u32) -> Object {
+        js_sys::Uint16Array::new(memory).subarray(start, start + length).into()
+    }
+}
+
+impl BufferElement for u32 {
+    const GL_TYPE: u32 = GL::UNSIGNED_INT;
+    const BYTES: usize = 4;
+
+    fn js_array(memory: &JsValue, start: u32, length: u32) -> Object {
+        js_sys::Uint32Array::new(memory).subarray(start, start + length).into()
+    }
+}
+
+impl BufferElement for i16 {
+    const GL_TYPE: u32 = GL::SHORT;
+    const BYTES: usize = 2;
+
+    fn js_array(memory: &JsValue, start: u32, length: u32) -> Object {
+        js_sys::Int16Array::new(memory).subarray(start, start + length).into()
+    }
+}
+
+impl BufferElement for f32 {
+    const GL_TYPE: u32 = GL::FLOAT;
+    const BYTES: usize = 4;
+
+    fn js_array(memory: &JsValue, start: u32, length: u32) -> Object {
+        js_sys::Float32Array::new(memory).subarray(start, start + length).into()
+    }
+}
+
+/*
+A JS view of `data` in place, nothing is copied until WebGL reads it.
+
+Only valid until wasm memory grows (any allocation can do it), so it has to be used right away.
+*/
+fn js_view<T: BufferElement>(data: &[T]) -> Object {
+    let memory = wasm_bindgen::memory()
+        .dyn_into::<WebAssembly::Memory>()
+        .unwrap()
+        .buffer();
+    // Slices are aligned to their element size, so the byte address divides evenly
+    let start = data.as_ptr() as usize / T::BYTES;
+    T::js_array(&memory, start as u32, data.len() as u32)
+}
+
+/*
+A WebGL buffer holding elements of type `T`.
+
+The buffer keeps track of how many elements were uploaded (`len`) and how many fit in the allocation (`capacity`). `set` reuses the allocation with `bufferSubData` while the data fits and only reallocates (with room to grow) when it doesn't, so data that changes every frame doesn't reallocate every frame.
+
+`len` and `capacity` are `Cell`s so buffers can be refilled from `Program::render`, which only has `&self`.
+
+The WebGL buffer is deleted when the `GpuBuffer` is dropped, so programs free their buffers just by replacing or dropping them.
+*/
+pub struct GpuBuffer<T: BufferElement> {
+    // Kept to delete the buffer on drop
+    context: WebGlRenderingContext,
+    buffer: WebGlBuffer,
+    target: u32,
+    usage: u32,
+    len: Cell<usize>,
+    capacity: Cell<usize>,
+    element_type: PhantomData<T>,
+}
+
+impl<T: BufferElement> GpuBuffer<T> {
+    /**
+     * Creates a buffer holding exactly `data`. `target` is `ARRAY_BUFFER` or `ELEMENT_ARRAY_BUFFER`, `usage` a hint like `STATIC_DRAW` or `DYNAMIC_DRAW`.
+     */
+    pub fn new(gl: &Gl, target: u32, data: &[T], usage: u32) -> Result<Self, MVisualError> {
+        let buffer = gl
+            .create_buffer()
+            .ok_or_else(|| MVisualError::Buffer(String::from("Failed to create buffer")))?;
+
+        gl.bind_buffer(target, Some(&buffer));
+        gl.buffer_data_with_array_buffer_view(target, &js_view(data), usage);
+        gl.count_upload(data.len() * T::BYTES);
+
+        Ok(Self {
+            context: WebGlRenderingContext::clone(gl),
+            buffer,
+            target,
+            usage,
+            len: Cell::new(data.len()),
+            capacity: Cell::new(data.len()),
+            element_type: PhantomData,
+        })
+    }
+
+    pub fn bind(&self, gl: &Gl) {
+        gl.bind_buffer(self.target, Some(&self.buffer));
+    }
+
+    /**
+     * Number of elements uploaded.
+     */
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.get()
+    }
+
+    /**
+     * Replaces the contents with `data`, growing the allocation when it doesn't fit. Leaves the buffer bound.
+     */
+    pub fn set(&self, gl: &Gl, data: &[T]) {
+        if data.len() > self.capacity() {
+            // Headroom so data which grows a little at a time doesn't reallocate every time
+            self.resize(gl, data.len().next_power_of_two());
+        }
+
+        self.bind(gl);
+        if !data.is_empty() {
+            gl.buffer_sub_data_with_i32_and_array_buffer_view(self.target, 0, &js_view(data));
+            gl.count_upload(data.len() * T::BYTES);
+        }
+        self.len.set(data.len());
+    }
+
+    /**
+     * Reallocates room for `capacity` elements. WebGL1 can't copy between buffers, so the contents are discarded and `len` goes back to 0.
+     */
+    pub fn resize(&self, gl: &Gl, capacity: usize) {
+        self.bind(gl);
+        gl.buffer_data_with_i32(self.target, (capacity * T::BYTES) as i32, self.usage);
+        self.capacity.set(capacity);
+        self.len.set(0);
+    }
+}
+
+impl<T: BufferElement> Drop for GpuBuffer<T> {
+    fn drop(&mut self) {
+        self.context.delete_buffer(Some(&self.buffer));
+    }
+}
+
+struct Field {
+    components: i32,
+    gl_type: u32,
+    // Bytes from the start of the vertex
+    offset: i32,
+}
+
+/*
+Describes interleaved vertices, where each vertex is a run of fields (position, color...) one after the other in a single buffer.
+
+    // x, y then a palette index
+    let layout = VertexLayout::new()
+        .field::<f32>(2)
+        .field::<u8>(1);
+
+Fields are padded to 4 bytes, as WebGL requires, so the `u8` field above takes 4 bytes. Data of mixed types is uploaded as bytes (`GpuBuffer<u8>`), layouts of a single type can use a buffer of that type directly.
+*/
+pub struct VertexLayout {
+    fields: Vec<Field>,
+    stride: i32,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        Self {
+            fields: Vec::new(),
+            stride: 0,
+        }
+    }
+
+    /**
+     * Appends a field of `components` values of type `T`, read by the shader as is.
+     */
+    pub fn field<T: BufferElement>(mut self, components: i32) -> Self {
+        self.fields.push(Field {
+            components,
+            gl_type: T::GL_TYPE,
+            offset: self.stride,
+        });
+        let bytes = components * T::BYTES as i32;
+        self.stride += (bytes + 3) / 4 * 4;
+        self
+    }
+
+    /**
+     * Number of whole vertices in `buffer`.
+     */
+    pub fn vertex_count<T: BufferElement>(&self, buffer: &GpuBuffer<T>) -> i32 {
+        (buffer.len() * T::BYTES) as i32 / self.stride
+    }
+
+    /**
+     * Points the attribute at `location` at field `index` of the bound `ARRAY_BUFFER`.
+     */
+    pub fn point(&self, gl: &Gl, index: usize, location: u32) {
+        let field = &self.fields[index];
+        gl.enable_vertex_attrib_array(location);
+        gl.vertex_attrib_pointer_with_i32(
+            location,
+            field.components,
+            field.gl_type,
+            false,
+            self.stride,
+            field.offset,
+        );
+    }
+}
+
+impl Default for VertexLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * Triangle or line indices in whichever width the context can draw, for meshes which only sometimes fit in u16.
+ */
+pub enum IndexBuffer {
+    U16(GpuBuffer<u16>),
+    U32(GpuBuffer<u32>),
+}
+
+impl IndexBuffer {
+    pub fn bind(&self, gl: &Gl) {
+        match self {
+            Self::U16(buffer) => buffer.bind(gl),
+            Self::U32(buffer) => buffer.bind(gl),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U16(buffer) => buffer.len(),
+            Self::U32(buffer) => buffer.len(),
+        }
+    }
+
+    /**
+     * `UNSIGNED_SHORT` or `UNSIGNED_INT`, for `drawElements`.
+     */
+    pub fn gl_type(&self) -> u32 {
+        match self {
+            Self::U16(_) => u16::GL_TYPE,
+            Self::U32(_) => u32::GL_TYPE,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+// Size of the light arrays in the lit shaders, passed to them as the `MAX_LIGHTS` define
+pub const MAX_LIGHTS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LightKind {
+    // Light arriving from a direction, like the sun. `vector` points towards the light
+    Directional,
+    // Light spreading out from `vector` in every direction (no falloff)
+    Point,
+}
+
+#[derive(Clone, Copy)]
+pub struct Light {
+    pub kind: LightKind,
+    pub vector: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn new(kind: LightKind, vector: Vector3<f32>) -> Self {
+        Self {
+            kind,
+            vector,
+            color: [1., 1., 1.],
+            intensity: 1.,
+        }
+    }
+}
+
+/*
+The lights of a scene. Every lit program reads the same `Lighting` when it renders.
+
+When `follow_camera` is set light vectors are in view space (the light moves with the camera, like a lamp on its shoulder), otherwise they are in world space and get transformed by each program's view matrix.
+*/
+pub struct Lighting {
+    pub ambient: [f32; 3],
+    pub lights: Vec<Light>,
+    pub follow_camera: bool,
+}
+
+impl Lighting {
+    /**
+     * Adds a light, returning its index or `None` when there are already `MAX_LIGHTS`.
+     */
+    pub fn add_light(&mut self, light: Light) -> Option<usize> {
+        if self.lights.len() >= MAX_LIGHTS {
+            return None;
+        }
+        self.lights.push(light);
+        Some(self.lights.len() - 1)
+    }
+
+    /**
+     * Light positions in view space packed for `uLightPosition`, w is 0 for directional and 1 for point lights.
+     */
+    pub fn view_positions(&self, view_matrix: &Matrix4<f32>) -> Vec<[f32; 4]> {
+        self.lights
+            .iter()
+            .map(|light| {
+                let w = match light.kind {
+                    LightKind::Directional => 0.,
+                    LightKind::Point => 1.,
+                };
+                let position = Vector4::new(light.vector.x, light.vector.y, light.vector.z, w);
+                let position = if self.follow_camera { position } else { view_matrix * position };
+                [position.x, position.y, position.z, w]
+            })
+            .collect()
+    }
+
+    /**
+     * Everything in the `Lighting` uniform block of the lighting chunk, laid out as std140 (every member is a vec4, so no padding).
+     *
+     * Positions stay in whichever space `follow_camera` says, the shaders move world space lights by their own `uView`.
+     */
+    pub fn uniform_block(&self) -> Vec<f32> {
+        let mut block = vec![0.; 4 * (2 + 2 * MAX_LIGHTS)];
+        block[..3].copy_from_slice(&self.ambient);
+        block[4] = self.lights.len() as f32;
+        block[5] = if self.follow_camera { 1. } else { 0. };
+
+        let positions = 8;
+        let colors = positions + 4 * MAX_LIGHTS;
+        for (i, light) in self.lights.iter().enumerate() {
+            let w = match light.kind {
+                LightKind::Directional => 0.,
+                LightKind::Point => 1.,
+            };
+            block[positions + 4 * i..positions + 4 * i + 4]
+                .copy_from_slice(&[light.vector.x, light.vector.y, light.vector.z, w]);
+            for channel in 0..3 {
+                block[colors + 4 * i + channel] = light.color[channel] * light.intensity;
+            }
+        }
+
+        block
+    }
+
+    /**
+     * Light colors scaled by intensity packed for `uLightColor`.
+     */
+    pub fn colors(&self) -> Vec<[f32; 3]> {
+        self.lights
+            .iter()
+            .map(|light| {
+                [
+                    light.color[0] * light.intensity,
+                    light.color[1] * light.intensity,
+                    light.color[2] * light.intensity,
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Default for Lighting {
+    // A dim white ambient and one white light above the left shoulder of the camera
+    fn default() -> Self {
+        Self {
+            ambient: [0.2, 0.2, 0.2],
+            lights: vec![Light::new(LightKind::Directional, Vector3::new(-0.5, 0.5, 0.75))],
+            follow_camera: true,
+        }
+    }
+}
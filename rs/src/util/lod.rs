@@ -0,0 +1,135 @@
+use super::bounds::Bounds;
+use super::constants::*;
+use super::gl::Gl;
+use super::gpu_buffer::{GpuBuffer, IndexBuffer};
+use super::math;
+use super::surface::MAX_U16_SURFACE_RESOLUTION;
+use crate::error::MVisualError;
+use web_sys::WebGlRenderingContext as GL;
+
+// A level is kept until the object's size is this far past the size where the next one takes over, so objects sitting right at a boundary don't flicker between two meshes
+pub const LOD_HYSTERESIS: f32 = 0.15;
+
+// Grid levels are switched so their cells stay under this many pixels across
+const PIXELS_PER_GRID_CELL: f32 = 6.;
+// Coarser than this and a surface loses its shape rather than just detail
+const MIN_LOD_GRID_CELLS: usize = 16;
+const GRID_STRIDES: [usize; 4] = [8, 4, 2, 1];
+
+/*
+Picks one of several levels of detail, ordered coarsest first, from an object's size on screen.
+*/
+pub struct LodSelector {
+    // Largest size (in pixels) each level but the finest is good for
+    max_sizes: Vec<f32>,
+    level: usize,
+}
+
+impl LodSelector {
+    /**
+     * `max_sizes` has one entry less than there are levels and has to be increasing. Starts at the coarsest level, the first `select` moves straight to the right one.
+     */
+    pub fn new(max_sizes: Vec<f32>) -> Self {
+        Self { max_sizes, level: 0 }
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /**
+     * Moves to the level for an object `size` pixels across and returns it. Only moves once the size is `LOD_HYSTERESIS` past a boundary.
+     */
+    pub fn select(&mut self, size: f32) -> usize {
+        while self.level < self.max_sizes.len() && size > self.max_sizes[self.level] * (1. + LOD_HYSTERESIS) {
+            self.level += 1;
+        }
+        while self.level > 0 && size < self.max_sizes[self.level - 1] * (1. - LOD_HYSTERESIS) {
+            self.level -= 1;
+        }
+        self.level
+    }
+}
+
+/**
+ * How many pixels across a sphere of view space `radius`, `depth` in front of the camera, is drawn on a canvas `canvas_height` pixels tall. Infinite when the camera is inside it or it is behind the camera, the finest level is wanted there.
+ */
+pub fn projected_size(radius: f32, depth: f32, canvas_height: f32) -> f32 {
+    if depth <= radius.max(Z_NEAR) {
+        return f32::INFINITY;
+    }
+    radius * canvas_height / (depth * (FIELD_OF_VIEW / 2.).tan())
+}
+
+/*
+Triangle indices for every level of detail of a grid surface, coarsest first. All levels draw from the same (n + 1) x (n + 1) vertices, coarser ones skip rows and columns (see `math::get_grid_indices_strided`), so only the indices are duplicated.
+*/
+pub struct GridLevels {
+    levels: Vec<IndexBuffer>,
+    lod: LodSelector,
+}
+
+impl GridLevels {
+    /**
+     * Errors when the grid needs 32 bit indices and the context doesn't support them.
+     */
+    pub fn new(gl: &Gl, n: usize) -> Result<Self, MVisualError> {
+        if !gl.supports_uint_indices() && n > MAX_U16_SURFACE_RESOLUTION {
+            return Err(MVisualError::Input(format!(
+                "Surface resolution can be at most {} without 32 bit index support, got {}",
+                MAX_U16_SURFACE_RESOLUTION,
+                n
+            )));
+        }
+
+        // The finest level (stride 1) is always there
+        let strides: Vec<usize> = GRID_STRIDES
+            .iter()
+            .copied()
+            .filter(|&stride| stride == 1 || n / stride >= MIN_LOD_GRID_CELLS)
+            .collect();
+
+        let levels = strides
+            .iter()
+            .map(|&stride| {
+                let indices = math::get_grid_indices_strided(n, stride);
+                Ok(if gl.supports_uint_indices() {
+                    IndexBuffer::U32(GpuBuffer::new(gl, GL::ELEMENT_ARRAY_BUFFER, &indices, GL::STATIC_DRAW)?)
+                } else {
+                    let indices: Vec<u16> = indices.iter().map(|&index| index as u16).collect();
+                    IndexBuffer::U16(GpuBuffer::new(gl, GL::ELEMENT_ARRAY_BUFFER, &indices, GL::STATIC_DRAW)?)
+                })
+            })
+            .collect::<Result<Vec<_>, MVisualError>>()?;
+
+        // A level is good until its cells get too big, the surface is as many of them across as it has cells
+        let max_sizes = strides
+            .iter()
+            .take(strides.len() - 1)
+            .map(|&stride| PIXELS_PER_GRID_CELL * n.div_ceil(stride) as f32)
+            .collect();
+
+        Ok(Self {
+            levels,
+            lod: LodSelector::new(max_sizes),
+        })
+    }
+
+    /**
+     * Picks the level for a surface with view space `bounds`. Unknown bounds get the finest level.
+     */
+    pub fn select(&mut self, bounds: Option<Bounds>, canvas_height: f32) {
+        let size = match bounds {
+            Some(bounds) => projected_size(bounds.sphere.radius, -bounds.sphere.center.z, canvas_height),
+            None => f32::INFINITY,
+        };
+        self.lod.select(size);
+    }
+
+    /**
+     * The indices of the selected level.
+     */
+    pub fn indices(&self) -> &IndexBuffer {
+        &self.levels[self.lod.level()]
+    }
+}
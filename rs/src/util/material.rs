@@ -0,0 +1,56 @@
+/*
+How an object reacts to light. Colors are RGB on [0, 1].
+
+The final color is roughly:
+
+emissive + ambient * ambient light * color + sum over lights of (diffuse * N.L * color + specular * (N.H)^shininess) * light color
+
+Programs which take their color from somewhere else (a color map) ignore `color` but keep everything else.
+*/
+#[derive(Clone, Copy)]
+pub struct Material {
+    pub color: [f32; 3],
+    pub ambient: f32,
+    pub diffuse: f32,
+    // Only used by per fragment (Blinn-Phong) shading
+    pub specular: f32,
+    // Exponent of the highlight, larger is smaller and sharper
+    pub shininess: f32,
+    // Below 1 the object is drawn in the transparent pass
+    pub opacity: f32,
+    // Light given off by the object itself, added regardless of the lights
+    pub emissive: [f32; 3],
+    pub wireframe_color: [f32; 3],
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: [0.5, 0.5, 0.8], // Blue-ish
+            ambient: 1.,
+            diffuse: 1.,
+            specular: 0.5,
+            shininess: 32.,
+            opacity: 1.,
+            emissive: [0., 0., 0.],
+            wireframe_color: [0.5, 0.8, 0.5], // Green-ish
+        }
+    }
+}
+
+impl Material {
+    /**
+     * The material with `tint` added to the light it gives off, which is how selected objects are highlighted.
+     */
+    pub fn tinted(&self, tint: Option<[f32; 3]>) -> Material {
+        let tint = match tint {
+            Some(tint) => tint,
+            None => return *self,
+        };
+        let emissive = self.emissive;
+        Material {
+            emissive: [emissive[0] + tint[0], emissive[1] + tint[1], emissive[2] + tint[2]],
+            ..*self
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use super::constants::*;
 //use crate::log;
-use nalgebra::{Matrix4,Perspective3};
+use nalgebra::{Matrix3,Matrix4,Perspective3,Vector3};
 
+#[allow(clippy::too_many_arguments)]
 pub fn get_3d_projection_matrix(
     bottom: f32,
     top: f32,
@@ -100,7 +101,36 @@ pub fn get_3d_projection_matrix(
     let perspective: Perspective3<f32> =
         Perspective3::new(aspect_ratio, FIELD_OF_VIEW, Z_NEAR, Z_FAR);
 
-    return perspective.as_matrix() * mv;
+    perspective.as_matrix() * mv
+}
+
+/**
+ * View matrix for a camera orbiting the origin.
+ *
+ * Uses the same mouse driven rotations as `get_3d_projection_matrix` but without the 2D control box scaling so objects can be placed in world units.
+ *
+ * World Z is up (so surfaces z = f(x, y) stand the right way), the last rotation turns it into the Y up the camera expects. Dragging sideways spins the scene around Z.
+ */
+pub fn get_orbit_view_matrix(rotation_angle_x_axis: f32, rotation_angle_y_axis: f32) -> Matrix4<f32> {
+    Matrix4::new_translation(&Vector3::new(0., 0., Z_PLANE))
+        * Matrix4::from_axis_angle(&Vector3::x_axis(), rotation_angle_x_axis)
+        * Matrix4::from_axis_angle(&Vector3::y_axis(), rotation_angle_y_axis)
+        * Matrix4::from_axis_angle(&Vector3::x_axis(), -std::f32::consts::FRAC_PI_2)
+}
+
+/**
+ * Matrix for taking normals into view space, the inverse transpose of the model view's upper 3x3.
+ *
+ * Transforming normals by the model view itself only works for rotations and uniform scales. Stretching an object along one axis tilts its surface towards that axis, which is the opposite of what the stretch does to the normals. See: https://www.lighthouse3d.com/tutorials/glsl-12-tutorial/the-normal-matrix/
+ *
+ * A model view which can't be inverted (scaled by 0) has no sensible normals, its upper 3x3 is returned as is.
+ */
+pub fn get_normal_matrix(model_view: &Matrix4<f32>) -> Matrix3<f32> {
+    let upper: Matrix3<f32> = model_view.fixed_slice::<3, 3>(0, 0).into();
+    upper
+        .try_inverse()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or(upper)
 }
 
 /**
@@ -145,7 +175,7 @@ pub fn get_position_grid_n_by_n(n: usize) -> (Vec<f32>, Vec<u16>) {
             let start_pos_i = 3 * (z * n_plus_one + x);
 
             // Again -1 and square size is mimicking openGL grid, but could be something else
-            positions[start_pos_i + 0] = -1. + (x as f32) * square_size;
+            positions[start_pos_i] = -1. + (x as f32) * square_size;
             positions[start_pos_i + 1] = 0.;
             positions[start_pos_i + 2] = -1. + (z as f32) * square_size;
 
@@ -163,7 +193,7 @@ pub fn get_position_grid_n_by_n(n: usize) -> (Vec<f32>, Vec<u16>) {
                 let vertex_index_top_right = vertex_index_top_left + 1;
                 let vertex_index_bottom_right = vertex_index_bottom_left + 1;
 
-                indices[start_index_i + 0] = vertex_index_top_left;
+                indices[start_index_i] = vertex_index_top_left;
                 indices[start_index_i + 1] = vertex_index_bottom_left;
                 indices[start_index_i + 2] = vertex_index_bottom_right;
                 indices[start_index_i + 3] = vertex_index_top_left;
@@ -173,7 +203,36 @@ pub fn get_position_grid_n_by_n(n: usize) -> (Vec<f32>, Vec<u16>) {
         }
     }
 
-    return (positions, indices);
+    (positions, indices)
+}
+
+/**
+ * Triangle indices of `get_position_grid_n_by_n`'s grid (same winding) as u32, for grids too big for u16 indices.
+ */
+pub fn get_grid_indices_n_by_n(n: usize) -> Vec<u32> {
+    get_grid_indices_strided(n, 1)
+}
+
+/**
+ * Triangle indices of a coarser grid over the same (n + 1) x (n + 1) vertices, using every `stride`th row and column. The last row and column are always used, so the coarse grid covers the whole surface even when `stride` doesn't divide `n`.
+ */
+pub fn get_grid_indices_strided(n: usize, stride: usize) -> Vec<u32> {
+    let n_plus_one = n + 1;
+    let samples: Vec<usize> = (0..n).step_by(stride.max(1)).chain(std::iter::once(n)).collect();
+    let mut indices = Vec::with_capacity(6 * (samples.len() - 1) * (samples.len() - 1));
+
+    for rows in samples.windows(2) {
+        for columns in samples.windows(2) {
+            let top_left = (rows[0] * n_plus_one + columns[0]) as u32;
+            let bottom_left = (rows[1] * n_plus_one + columns[0]) as u32;
+            let top_right = (rows[0] * n_plus_one + columns[1]) as u32;
+            let bottom_right = (rows[1] * n_plus_one + columns[1]) as u32;
+
+            indices.extend_from_slice(&[top_left, bottom_left, bottom_right, top_left, bottom_right, top_right]);
+        }
+    }
+
+    indices
 }
 
 /*
@@ -192,7 +251,7 @@ pub fn translation_matrix(tx: f32, ty: f32, tz: f32) -> [f32; 16] {
     return_var[13] = ty;
     return_var[14] = tz;
 
-    return return_var;
+    return_var
 }
 
 pub fn scale_matrix(sx: f32, sy: f32, sz: f32) -> [f32; 16] {
@@ -203,7 +262,7 @@ pub fn scale_matrix(sx: f32, sy: f32, sz: f32) -> [f32; 16] {
     return_var[10] = sz;
     return_var[15] = 1.;
 
-    return return_var;
+    return_var
 }
 
 /*
@@ -232,5 +291,5 @@ pub fn mult_matrix_4(a: [f32; 16], b: [f32; 16]) -> [f32; 16] {
     return_var[14] = a[12] * b[2] + a[13] * b[6] + a[14] * b[10] + a[15] * b[14];
     return_var[15] = a[12] * b[3] + a[13] * b[7] + a[14] * b[11] + a[15] * b[15];
 
-    return return_var;
+    return_var
 }
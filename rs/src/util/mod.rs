@@ -1,9 +1,31 @@
 pub mod math;
 pub mod webgl;
+pub mod bounds;
+pub mod clock;
+pub mod colormap;
 pub mod constants;
+pub mod curve;
+pub mod export;
+pub mod expression;
+pub mod gl;
+pub mod gpu_buffer;
+pub mod glyph_atlas;
+pub mod lighting;
+pub mod lod;
+pub mod material;
+pub mod perf;
+pub mod picking;
+pub mod render_target;
+pub mod selection;
+pub mod ticks;
+pub mod timeline;
+
+pub mod bindings;
+
+pub mod axes;
+pub use axes::*;
 
 pub mod sphere;
 pub use sphere::*;
 
-pub mod wasm;
-pub use wasm::*;
\ No newline at end of file
+pub mod surface;
\ No newline at end of file
@@ -0,0 +1,134 @@
+use web_sys::WebGlRenderingContext as GL;
+
+// Upper edges (ms) of the frame time histogram's buckets, one more bucket holds anything slower
+pub const FRAME_TIME_BUCKETS: [f32; 7] = [8., 17., 25., 34., 50., 100., 250.];
+
+/*
+Work submitted to the GPU, counted by `Gl` as programs draw.
+*/
+#[derive(Clone, Copy, Default)]
+pub struct FrameCounters {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    pub lines: u64,
+    pub points: u64,
+    // Vertex, index, uniform buffer and texture data sent to the GPU
+    pub bytes_uploaded: u64,
+    // Program, buffer, texture, vertex array and capability switches
+    pub state_changes: u32,
+}
+
+impl FrameCounters {
+    /**
+     * Counts a draw of `count` vertices as `mode` primitives, `instances` times over.
+     */
+    pub fn add_draw(&mut self, mode: u32, count: i32, instances: i32) {
+        let count = count.max(0) as u64;
+        let instances = instances.max(0) as u64;
+        self.draw_calls += 1;
+        match mode {
+            GL::TRIANGLES => self.triangles += count / 3 * instances,
+            GL::TRIANGLE_STRIP | GL::TRIANGLE_FAN => self.triangles += count.saturating_sub(2) * instances,
+            GL::LINES => self.lines += count / 2 * instances,
+            GL::LINE_STRIP => self.lines += count.saturating_sub(1) * instances,
+            GL::LINE_LOOP if count > 1 => self.lines += count * instances,
+            GL::POINTS => self.points += count * instances,
+            _ => {}
+        }
+    }
+}
+
+/*
+Timings and counters of the frames drawn so far. A frame runs from the start of one `render` to the start of the next, so uploads made while animating in between count towards the frame they are drawn in.
+*/
+#[derive(Default)]
+pub struct PerfStats {
+    // Counters of the last finished frame
+    pub frame: FrameCounters,
+    // ms from the start of the frame before to the start of the last one
+    pub frame_time: f32,
+    // ms spent in the last `render`
+    pub render_time: f32,
+    // Frames in each bucket of `FRAME_TIME_BUCKETS`, then the slower ones
+    pub histogram: [u32; FRAME_TIME_BUCKETS.len() + 1],
+    pub frames: u32,
+    // Programs outside the camera's view in the last frame, skipped without drawing
+    pub culled: u32,
+    // performance.now() at the start of the current frame
+    frame_start: Option<f64>,
+}
+
+impl PerfStats {
+    pub fn start_frame(&mut self, now: f64) {
+        if let Some(last) = self.frame_start {
+            self.frame_time = (now - last) as f32;
+            let bucket = FRAME_TIME_BUCKETS
+                .iter()
+                .position(|&edge| self.frame_time < edge)
+                .unwrap_or(FRAME_TIME_BUCKETS.len());
+            self.histogram[bucket] += 1;
+        }
+        self.frame_start = Some(now);
+    }
+
+    pub fn end_frame(&mut self, now: f64, counters: FrameCounters) {
+        if let Some(start) = self.frame_start {
+            self.render_time = (now - start) as f32;
+        }
+        self.frame = counters;
+        self.frames += 1;
+    }
+
+    /**
+     * Clears the histogram and frame count, the next frame starts a new interval.
+     */
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /**
+     * Share of the frames (with a frame time) in each histogram bucket.
+     */
+    pub fn histogram_fractions(&self) -> Vec<f32> {
+        let total: u32 = self.histogram.iter().sum();
+        self.histogram
+            .iter()
+            .map(|&count| if total > 0 { count as f32 / total as f32 } else { 0. })
+            .collect()
+    }
+
+    /**
+     * The last frame as lines of text for the HUD.
+     */
+    pub fn summary(&self) -> Vec<String> {
+        let fps = if self.frame_time > 0. { 1000. / self.frame_time } else { 0. };
+        let frame = &self.frame;
+        vec![
+            format!("{:.0} fps  frame {:.1} ms  render {:.1} ms", fps, self.frame_time, self.render_time),
+            format!("{} draw calls  {} state changes", frame.draw_calls, frame.state_changes),
+            format!("{} triangles  {} lines  {} points", frame.triangles, frame.lines, frame.points),
+            format!("{} objects culled", self.culled),
+            format!("{} uploaded", format_bytes(frame.bytes_uploaded)),
+        ]
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024. * 1024.))
+    }
+}
+
+/**
+ * Current time in ms from `performance.now()`, or from `Date.now()` where that isn't available.
+ */
+pub fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map(|performance| performance.now())
+        .unwrap_or_else(js_sys::Date::now)
+}
@@ -0,0 +1,539 @@
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+use super::bounds::{Aabb, BoundingSphere};
+use super::surface::{ColorMode, Surface};
+
+// Triangles per BVH leaf, past this a node is split
+const BVH_LEAF_SIZE: usize = 4;
+// Below this the ray runs along the triangle's plane and misses it
+const PARALLEL_EPSILON: f32 = 1e-8;
+
+/*
+A ray `origin + t * direction`. The direction isn't normalized, so transforming a ray into another space (with `transformed`) keeps `t` the same at the same point, which lets hits from objects in different spaces be compared by `t` alone.
+*/
+#[derive(Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    /**
+     * The view space ray through a point on the canvas, `x` and `y` in pixels from the bottom left. Runs from the near plane at t = 0 to the far plane at t = 1.
+     */
+    pub fn from_screen(x: f32, y: f32, canvas_width: f32, canvas_height: f32, projection: &Matrix4<f32>) -> Option<Self> {
+        let inverse = projection.try_inverse()?;
+        let ndc_x = 2. * x / canvas_width - 1.;
+        let ndc_y = 2. * y / canvas_height - 1.;
+        let unproject = |ndc_z: f32| {
+            let point = inverse * Vector4::new(ndc_x, ndc_y, ndc_z, 1.);
+            point.xyz() / point.w
+        };
+
+        let near = unproject(-1.);
+        let far = unproject(1.);
+        Some(Ray {
+            origin: near,
+            direction: far - near,
+        })
+    }
+
+    pub fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + t * self.direction
+    }
+
+    /**
+     * The same ray in the space `transform` maps into, which has to be affine.
+     */
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        Ray {
+            origin: transform.transform_point(&Point3::from(self.origin)).coords,
+            direction: transform.transform_vector(&self.direction),
+        }
+    }
+
+    /**
+     * Entry and exit `t` of the ray through `sphere` (the entry is negative when the ray starts inside), `None` when it misses.
+     */
+    pub fn intersect_sphere(&self, sphere: &BoundingSphere) -> Option<(f32, f32)> {
+        let to_origin = self.origin - sphere.center;
+        let a = self.direction.norm_squared();
+        let b = 2. * self.direction.dot(&to_origin);
+        let c = to_origin.norm_squared() - sphere.radius * sphere.radius;
+        let discriminant = b * b - 4. * a * c;
+        if a == 0. || discriminant < 0. {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        Some(((-b - root) / (2. * a), (-b + root) / (2. * a)))
+    }
+
+    /*
+    Slab test, `inverse_direction` is 1 / direction per axis (infinite along axes the ray doesn't move on).
+    */
+    fn intersect_aabb(&self, aabb: &Aabb, inverse_direction: &Vector3<f32>, max_t: f32) -> bool {
+        let mut near = 0f32;
+        let mut far = max_t;
+        for axis in 0..3 {
+            // A ray which doesn't move along the axis is in the slab all along or never, and would make 0 * infinity below when it starts on a face
+            if self.direction[axis] == 0. {
+                if self.origin[axis] < aabb.min[axis] || self.origin[axis] > aabb.max[axis] {
+                    return false;
+                }
+                continue;
+            }
+            let t0 = (aabb.min[axis] - self.origin[axis]) * inverse_direction[axis];
+            let t1 = (aabb.max[axis] - self.origin[axis]) * inverse_direction[axis];
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        near <= far
+    }
+
+    /**
+     * Möller–Trumbore intersection with both sides of a triangle, returns `t` and the barycentric weights of `b` and `c`. See: https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/moller-trumbore-ray-triangle-intersection.html
+     */
+    pub fn intersect_triangle(&self, a: &Vector3<f32>, b: &Vector3<f32>, c: &Vector3<f32>) -> Option<(f32, f32, f32)> {
+        let edge_ab = b - a;
+        let edge_ac = c - a;
+        let p = self.direction.cross(&edge_ac);
+        let determinant = edge_ab.dot(&p);
+        if determinant.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+
+        let inverse_determinant = 1. / determinant;
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(&p) * inverse_determinant;
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(&edge_ab);
+        let v = self.direction.dot(&q) * inverse_determinant;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+
+        let t = edge_ac.dot(&q) * inverse_determinant;
+        Some((t, u, v))
+    }
+}
+
+/*
+Where a ray hit a triangle of a `Bvh`.
+*/
+pub struct TriangleHit {
+    // Index of the triangle in the indices the BVH was built from, i.e. its first index / 3
+    pub triangle: usize,
+    pub t: f32,
+    // Weights of the triangle's three vertices at the hit
+    pub barycentric: [f32; 3],
+}
+
+struct BvhNode {
+    aabb: Aabb,
+    // Leaves hold `count` triangles from `start` in `Bvh::triangles`. Inner nodes have `count` 0, their first child straight after them and the second at `start`
+    start: usize,
+    count: usize,
+}
+
+/*
+Bounding volume hierarchy over a triangle mesh, so a ray only tests the triangles in boxes it passes through rather than all of them.
+
+Nodes are split at the median of their triangles' centers along their longest side, which is quick to build and good enough for the evenly spread triangles of a surface.
+*/
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    // Original triangle indices, reordered so each leaf's are together
+    triangles: Vec<usize>,
+    // Corners of each original triangle
+    corners: Vec<[Vector3<f32>; 3]>,
+}
+
+impl Bvh {
+    /**
+     * Builds over the triangles `indices` makes of the (x, y, z) triples in `positions`.
+     */
+    pub fn new(positions: &[f32], indices: &[u32]) -> Self {
+        let vertex = |index: u32| {
+            let start = 3 * index as usize;
+            Vector3::new(positions[start], positions[start + 1], positions[start + 2])
+        };
+        let corners: Vec<[Vector3<f32>; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| [vertex(triangle[0]), vertex(triangle[1]), vertex(triangle[2])])
+            .collect();
+        let centers: Vec<Vector3<f32>> = corners.iter().map(|[a, b, c]| (a + b + c) / 3.).collect();
+
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            triangles: (0..corners.len()).collect(),
+            corners,
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.build(0, bvh.triangles.len(), &centers);
+        }
+        bvh
+    }
+
+    /*
+    Adds the node for `triangles[start..end]` and everything below it.
+    */
+    fn build(&mut self, start: usize, end: usize, centers: &[Vector3<f32>]) {
+        let aabb = self.triangles[start..end]
+            .iter()
+            .flat_map(|&triangle| self.corners[triangle].iter())
+            .fold(None, |aabb: Option<Aabb>, corner| {
+                Some(match aabb {
+                    Some(aabb) => Aabb { min: aabb.min.inf(corner), max: aabb.max.sup(corner) },
+                    None => Aabb { min: *corner, max: *corner },
+                })
+            })
+            // `build` is never called on an empty range
+            .unwrap();
+
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode { aabb, start, count: end - start });
+        if end - start <= BVH_LEAF_SIZE {
+            return;
+        }
+
+        let extent = aabb.max - aabb.min;
+        let axis = extent.imax();
+        let middle = (start + end) / 2;
+        self.triangles[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            centers[a][axis].partial_cmp(&centers[b][axis]).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        self.build(start, middle, centers);
+        let second = self.nodes.len();
+        self.build(middle, end, centers);
+        self.nodes[node].start = second;
+        self.nodes[node].count = 0;
+    }
+
+    /**
+     * The nearest triangle the ray hits with `t` in [0, `max_t`].
+     */
+    pub fn intersect(&self, ray: &Ray, max_t: f32) -> Option<TriangleHit> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inverse_direction = ray.direction.map(|value| 1. / value);
+        let mut nearest: Option<TriangleHit> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let limit = nearest.as_ref().map_or(max_t, |hit| hit.t);
+            if !ray.intersect_aabb(&node.aabb, &inverse_direction, limit) {
+                continue;
+            }
+
+            if node.count == 0 {
+                stack.push(index + 1);
+                stack.push(node.start);
+                continue;
+            }
+
+            for &triangle in &self.triangles[node.start..node.start + node.count] {
+                let [a, b, c] = &self.corners[triangle];
+                if let Some((t, u, v)) = ray.intersect_triangle(a, b, c) {
+                    let limit = nearest.as_ref().map_or(max_t, |hit| hit.t);
+                    if t >= 0. && t <= limit {
+                        nearest = Some(TriangleHit {
+                            triangle,
+                            t,
+                            barycentric: [1. - u - v, u, v],
+                        });
+                    }
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+/*
+What a pick found on an object. Positions and normals are in the object's own coordinates, the ones its data was given in.
+*/
+pub struct PickHit {
+    // Ray parameter of the hit, comparable between objects since they are all hit by the same ray
+    pub t: f32,
+    pub position: Vector3<f32>,
+    // Unit length, facing back along the ray
+    pub normal: Vector3<f32>,
+    // Meshes report the triangle hit and the weights of its vertices
+    pub triangle: Option<usize>,
+    pub barycentric: Option<[f32; 3]>,
+    // Which of several instances was hit
+    pub instance: Option<usize>,
+    // Where on the object's parameter domain: [0, 1] across a surface's x and y ranges, longitude and latitude (radians) on a sphere
+    pub parameters: Option<[f32; 2]>,
+    // The value the object is colored by, at the hit
+    pub value: Option<f32>,
+}
+
+/**
+ * Picks a grid surface through a BVH built over it. `ray` is in the surface's model space.
+ */
+pub fn pick_surface(surface: &Surface, bvh: &Bvh, ray: &Ray, color_mode: ColorMode) -> Option<PickHit> {
+    let hit = bvh.intersect(ray, 1.)?;
+    let corners: Vec<usize> = (0..3).map(|corner| surface.indices[3 * hit.triangle + corner] as usize).collect();
+    let interpolate = |data: &[f32], components: usize, component: usize| {
+        corners
+            .iter()
+            .zip(hit.barycentric.iter())
+            .map(|(&vertex, &weight)| weight * data[components * vertex + component])
+            .sum::<f32>()
+    };
+
+    let position = Vector3::new(
+        interpolate(&surface.vertices, 3, 0),
+        interpolate(&surface.vertices, 3, 1),
+        interpolate(&surface.vertices, 3, 2),
+    );
+    let normal = Vector3::new(
+        interpolate(&surface.normals, 3, 0),
+        interpolate(&surface.normals, 3, 1),
+        interpolate(&surface.normals, 3, 2),
+    );
+    let parameters = [
+        (position.x - surface.x_range.0) / (surface.x_range.1 - surface.x_range.0),
+        (position.y - surface.y_range.0) / (surface.y_range.1 - surface.y_range.0),
+    ];
+
+    Some(PickHit {
+        t: hit.t,
+        position,
+        normal: facing(normal, ray),
+        triangle: Some(hit.triangle),
+        barycentric: Some(hit.barycentric),
+        instance: None,
+        parameters: Some(parameters),
+        value: Some(interpolate(surface.color_values(color_mode), 1, 0)),
+    })
+}
+
+/**
+ * `normal` normalized and flipped if needed to face back along `ray`, surfaces can be seen (and picked) from both sides.
+ */
+pub fn facing(normal: Vector3<f32>, ray: &Ray) -> Vector3<f32> {
+    let normal = normal.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::z);
+    if normal.dot(&ray.direction) > 0. {
+        -normal
+    } else {
+        normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::bounds::Bounds;
+    use super::super::surface::SurfaceFunction;
+    use nalgebra::Perspective3;
+
+    const A: Vector3<f32> = Vector3::new(0., 0., 0.);
+    const B: Vector3<f32> = Vector3::new(1., 0., 0.);
+    const C: Vector3<f32> = Vector3::new(0., 1., 0.);
+
+    fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+        Ray {
+            origin: Vector3::from(origin),
+            direction: Vector3::from(direction),
+        }
+    }
+
+    fn inverse(ray: &Ray) -> Vector3<f32> {
+        ray.direction.map(|value| 1. / value)
+    }
+
+    /*
+    Every triangle tested, the nearest hit with t in [0, max_t].
+    */
+    fn brute_force(positions: &[f32], indices: &[u32], ray: &Ray, max_t: f32) -> Option<(usize, f32)> {
+        let vertex = |index: u32| Vector3::new(positions[3 * index as usize], positions[3 * index as usize + 1], positions[3 * index as usize + 2]);
+        indices
+            .chunks_exact(3)
+            .enumerate()
+            .filter_map(|(triangle, corners)| {
+                let (t, _, _) = ray.intersect_triangle(&vertex(corners[0]), &vertex(corners[1]), &vertex(corners[2]))?;
+                if (0. ..=max_t).contains(&t) {
+                    Some((triangle, t))
+                } else {
+                    None
+                }
+            })
+            .fold(None, |nearest: Option<(usize, f32)>, hit| match nearest {
+                Some(nearest) if nearest.1 <= hit.1 => Some(nearest),
+                _ => Some(hit),
+            })
+    }
+
+    #[test]
+    fn bvh_finds_what_testing_every_triangle_finds() {
+        let surface = SurfaceFunction::parse("sin(3 * x) * cos(2 * y) / 2", 24, (-1., 1.), (-1., 1.))
+            .unwrap()
+            .sample(0.)
+            .unwrap();
+        let bvh = Bvh::new(&surface.vertices, &surface.indices);
+        let aabb = Bounds::from_positions(&surface.vertices).unwrap().aabb;
+
+        // Rays from above the surface to below it at a slant, over the whole grid and a little past its edges
+        let steps = 23;
+        let mut hits = 0;
+        for i in 0..=steps {
+            for j in 0..=steps {
+                let s = -0.1 + 1.2 * i as f32 / steps as f32;
+                let r = -0.1 + 1.2 * j as f32 / steps as f32;
+                let x = aabb.min.x + s * (aabb.max.x - aabb.min.x);
+                let y = aabb.min.y + r * (aabb.max.y - aabb.min.y);
+                let from = Vector3::new(x, y, aabb.max.z + 1.);
+                let to = Vector3::new(x + 0.3 * (aabb.max.x - aabb.min.x), y - 0.2, aabb.min.z - 1.);
+                let ray = Ray { origin: from, direction: to - from };
+
+                for &max_t in [1., 0.4].iter() {
+                    let expected = brute_force(&surface.vertices, &surface.indices, &ray, max_t);
+                    let actual = bvh.intersect(&ray, max_t).map(|hit| (hit.triangle, hit.t));
+                    match (expected, actual) {
+                        (None, None) => {}
+                        (Some((_, expected_t)), Some((_, actual_t))) => {
+                            hits += 1;
+                            assert!((expected_t - actual_t).abs() < 1e-6, "Ray {} {}: t {} but the BVH found {}", i, j, expected_t, actual_t);
+                        }
+                        _ => panic!("Ray {} {}: every triangle gives {:?} but the BVH {:?}", i, j, expected, actual),
+                    }
+                }
+            }
+        }
+        // Enough rays landed for the comparison to mean something
+        assert!(hits > steps * steps / 2);
+    }
+
+    /*
+    Straight down through every vertex, so the rays run along the faces of the BVH's boxes and through triangle corners.
+    */
+    #[test]
+    fn bvh_hits_rays_along_grid_lines() {
+        let surface = SurfaceFunction::parse("x * y", 8, (-1., 1.), (-1., 1.)).unwrap().sample(0.).unwrap();
+        let bvh = Bvh::new(&surface.vertices, &surface.indices);
+        for vertex in surface.vertices.chunks_exact(3) {
+            let ray = ray([vertex[0], vertex[1], 5.], [0., 0., -10.]);
+            let hit = bvh.intersect(&ray, 1.).unwrap_or_else(|| panic!("Missed the vertex at {:?}", vertex));
+            assert!((ray.at(hit.t).z - vertex[2]).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn bvh_reports_the_triangle_and_where_on_it() {
+        let bvh = Bvh::new(&[0., 0., 0., 1., 0., 0., 0., 1., 0., 1., 1., 0.], &[0, 1, 2, 1, 3, 2]);
+        let hit = bvh.intersect(&ray([0.8, 0.7, 1.], [0., 0., -2.]), 1.).unwrap();
+        assert_eq!(hit.triangle, 1);
+        assert!((hit.t - 0.5).abs() < 1e-6);
+        assert!((hit.barycentric.iter().sum::<f32>() - 1.).abs() < 1e-6);
+
+        assert!(Bvh::new(&[], &[]).intersect(&ray([0., 0., 1.], [0., 0., -1.]), 1.).is_none());
+    }
+
+    #[test]
+    fn rays_through_an_edge_or_a_corner_hit() {
+        let (t, u, v) = ray([0.5, 0., 1.], [0., 0., -1.]).intersect_triangle(&A, &B, &C).unwrap();
+        assert_eq!((t, u, v), (1., 0.5, 0.));
+        assert!(ray([0., 0., 1.], [0., 0., -1.]).intersect_triangle(&A, &B, &C).is_some());
+        // The shared diagonal of two triangles isn't a gap between them
+        let bvh = Bvh::new(&[0., 0., 0., 1., 0., 0., 0., 1., 0., 1., 1., 0.], &[0, 1, 2, 1, 3, 2]);
+        assert!(bvh.intersect(&ray([0.5, 0.5, 1.], [0., 0., -2.]), 1.).is_some());
+    }
+
+    #[test]
+    fn rays_just_outside_miss() {
+        assert!(ray([0.6, 0.6, 1.], [0., 0., -1.]).intersect_triangle(&A, &B, &C).is_none());
+        assert!(ray([-0.01, 0.5, 1.], [0., 0., -1.]).intersect_triangle(&A, &B, &C).is_none());
+    }
+
+    #[test]
+    fn rays_parallel_to_the_triangle_miss() {
+        // Above it, and in its plane straight through it
+        assert!(ray([0.2, 0.2, 1.], [1., 0., 0.]).intersect_triangle(&A, &B, &C).is_none());
+        assert!(ray([-1., 0.2, 0.], [1., 0., 0.]).intersect_triangle(&A, &B, &C).is_none());
+    }
+
+    #[test]
+    fn hits_behind_the_origin_are_not_picked() {
+        let away = ray([0.2, 0.2, 1.], [0., 0., 1.]);
+        let (t, _, _) = away.intersect_triangle(&A, &B, &C).unwrap();
+        assert!(t < 0.);
+
+        let bvh = Bvh::new(&[0., 0., 0., 1., 0., 0., 0., 1., 0.], &[0, 1, 2]);
+        assert!(bvh.intersect(&away, 1.).is_none());
+        // Nor ones past `max_t`
+        assert!(bvh.intersect(&ray([0.2, 0.2, 1.], [0., 0., -0.5]), 1.).is_none());
+    }
+
+    #[test]
+    fn slab_test() {
+        let aabb = Aabb {
+            min: Vector3::new(-1., -1., -1.),
+            max: Vector3::new(1., 1., 1.),
+        };
+        let through = ray([-3., 0.5, 0.2], [6., 0., 0.]);
+        assert!(through.intersect_aabb(&aabb, &inverse(&through), 1.));
+        // Stops short of it
+        assert!(!through.intersect_aabb(&aabb, &inverse(&through), 0.3));
+
+        let beside = ray([-3., 1.5, 0.], [6., 0., 0.]);
+        assert!(!beside.intersect_aabb(&aabb, &inverse(&beside), 1.));
+
+        let behind = ray([3., 0., 0.], [1., 0., 0.]);
+        assert!(!behind.intersect_aabb(&aabb, &inverse(&behind), 10.));
+
+        let diagonal = ray([-2., -2., -2.], [1., 1., 1.]);
+        assert!(diagonal.intersect_aabb(&aabb, &inverse(&diagonal), 10.));
+
+        // Starting inside
+        let inside = ray([0., 0., 0.], [0.1, 0., 0.]);
+        assert!(inside.intersect_aabb(&aabb, &inverse(&inside), 1.));
+    }
+
+    #[test]
+    fn slab_test_on_a_face_plane() {
+        // Moving along x only, in the plane of the box's top face and then just above it
+        let aabb = Aabb {
+            min: Vector3::new(-1., -1., -1.),
+            max: Vector3::new(1., 1., 1.),
+        };
+        let on_face = ray([-3., 0., 1.], [6., 0., 0.]);
+        assert!(on_face.intersect_aabb(&aabb, &inverse(&on_face), 1.));
+        let above = ray([-3., 0., 1.01], [6., 0., 0.]);
+        assert!(!above.intersect_aabb(&aabb, &inverse(&above), 1.));
+    }
+
+    #[test]
+    fn screen_rays_run_from_the_near_to_the_far_plane() {
+        let projection = Perspective3::new(2., 1., 0.5, 50.).to_homogeneous();
+        let center = Ray::from_screen(100., 50., 200., 100., &projection).unwrap();
+        assert!((center.origin - Vector3::new(0., 0., -0.5)).norm() < 1e-4);
+        assert!((center.at(1.) - Vector3::new(0., 0., -50.)).norm() < 1e-2);
+    }
+
+    #[test]
+    fn screen_rays_pass_through_their_pixel() {
+        let projection = Perspective3::new(2., 1., 0.5, 50.).to_homogeneous();
+        let (x, y) = (30., 80.);
+        let ray = Ray::from_screen(x, y, 200., 100., &projection).unwrap();
+        for &t in [0., 0.3, 1.].iter() {
+            let clip = projection * ray.at(t).push(1.);
+            let ndc = clip.xy() / clip.w;
+            assert!((ndc.x - (2. * x / 200. - 1.)).abs() < 1e-4, "x at t = {}", t);
+            assert!((ndc.y - (2. * y / 100. - 1.)).abs() < 1e-4, "y at t = {}", t);
+        }
+        // From the bottom left, so y up is up the canvas
+        assert!(ray.direction.x < 0. && ray.direction.y > 0.);
+    }
+
+    #[test]
+    fn screen_rays_need_an_invertible_projection() {
+        assert!(Ray::from_screen(0., 0., 1., 1., &Matrix4::zeros()).is_none());
+    }
+}
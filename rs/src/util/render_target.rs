@@ -0,0 +1,103 @@
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use crate::error::MVisualError;
+
+/*
+An offscreen framebuffer to render into instead of the canvas, at a size of its own. Color is an RGBA texture (WebGL1 renderbuffers have no 8 bit RGBA format for `readPixels` to read back) and depth a 16 bit renderbuffer.
+
+Nothing here is rebuilt after a context loss, targets are made for one job and deleted after it.
+*/
+pub struct RenderTarget {
+    framebuffer: WebGlFramebuffer,
+    color: WebGlTexture,
+    depth: WebGlRenderbuffer,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(gl: &WebGlRenderingContext, width: u32, height: u32) -> Result<Self, MVisualError> {
+        let framebuffer = gl
+            .create_framebuffer()
+            .ok_or_else(|| MVisualError::Buffer(String::from("Failed to create framebuffer")))?;
+        let color = gl
+            .create_texture()
+            .ok_or_else(|| MVisualError::Buffer(String::from("Failed to create texture")))?;
+        let depth = gl
+            .create_renderbuffer()
+            .ok_or_else(|| MVisualError::Buffer(String::from("Failed to create renderbuffer")))?;
+
+        gl.bind_texture(GL::TEXTURE_2D, Some(&color));
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            width as i32,
+            height as i32,
+            0,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            None,
+        ).map_err(|error| MVisualError::Buffer(format!("Failed to allocate render target: {:?}", error)))?;
+        // Not mipmapped, and sizes needn't be powers of two
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+
+        gl.bind_renderbuffer(GL::RENDERBUFFER, Some(&depth));
+        gl.renderbuffer_storage(GL::RENDERBUFFER, GL::DEPTH_COMPONENT16, width as i32, height as i32);
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(GL::FRAMEBUFFER, GL::COLOR_ATTACHMENT0, GL::TEXTURE_2D, Some(&color), 0);
+        gl.framebuffer_renderbuffer(GL::FRAMEBUFFER, GL::DEPTH_ATTACHMENT, GL::RENDERBUFFER, Some(&depth));
+        let status = gl.check_framebuffer_status(GL::FRAMEBUFFER);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        let target = Self { framebuffer, color, depth, width, height };
+        if status != GL::FRAMEBUFFER_COMPLETE {
+            target.delete(gl);
+            return Err(MVisualError::Buffer(format!(
+                "Can't render offscreen at {}x{} (framebuffer status {:#x})",
+                width, height, status
+            )));
+        }
+        Ok(target)
+    }
+
+    /**
+     * Directs drawing into the target, over all of it. Bind the default framebuffer (`None`) to draw to the canvas again.
+     */
+    pub fn bind(&self, gl: &WebGlRenderingContext) {
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&self.framebuffer));
+        gl.viewport(0, 0, self.width as i32, self.height as i32);
+    }
+
+    /**
+     * The target's pixels as RGBA bytes, top row first (GL reads from the bottom up). The target has to be bound.
+     */
+    pub fn read_pixels(&self, gl: &WebGlRenderingContext) -> Result<Vec<u8>, MVisualError> {
+        let row = 4 * self.width as usize;
+        let mut pixels = vec![0; row * self.height as usize];
+        gl.read_pixels_with_opt_u8_array(
+            0,
+            0,
+            self.width as i32,
+            self.height as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            Some(&mut pixels),
+        ).map_err(|error| MVisualError::Buffer(format!("Failed to read pixels: {:?}", error)))?;
+
+        let mut flipped = Vec::with_capacity(pixels.len());
+        for bottom_up in pixels.chunks(row).rev() {
+            flipped.extend_from_slice(bottom_up);
+        }
+        Ok(flipped)
+    }
+
+    pub fn delete(self, gl: &WebGlRenderingContext) {
+        gl.delete_framebuffer(Some(&self.framebuffer));
+        gl.delete_texture(Some(&self.color));
+        gl.delete_renderbuffer(Some(&self.depth));
+    }
+}
@@ -0,0 +1,116 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use crate::error::MVisualError;
+
+// Added to the light a selected object gives off, a warm tint which shows on any color map
+pub const DEFAULT_HIGHLIGHT_COLOR: [f32; 3] = [0.3, 0.22, 0.];
+
+/*
+Which objects are selected, by id, in the order they were selected.
+*/
+pub struct Selection {
+    ids: Vec<u32>,
+    pub highlight_color: [f32; 3],
+}
+
+/*
+The ids a change to the selection added and removed. Only made when something did change.
+*/
+pub struct SelectionChange {
+    pub added: Vec<u32>,
+    pub removed: Vec<u32>,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            ids: Vec::new(),
+            highlight_color: DEFAULT_HIGHLIGHT_COLOR,
+        }
+    }
+}
+
+impl Selection {
+    pub fn ids(&self) -> &[u32] {
+        &self.ids
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.ids.contains(&id)
+    }
+
+    /**
+     * What a click does: selects only the object `hit`, or nothing on a miss. With `additive` (shift held) the object hit is toggled instead and a miss keeps the selection.
+     */
+    pub fn click(&mut self, hit: Option<u32>, additive: bool) -> Option<SelectionChange> {
+        match (hit, additive) {
+            (Some(id), true) if self.contains(id) => self.deselect(id),
+            (Some(id), _) => self.select(id, additive),
+            (None, true) => None,
+            (None, false) => self.clear(),
+        }
+    }
+
+    /**
+     * Selects `id`, on its own unless `additive`.
+     */
+    pub fn select(&mut self, id: u32, additive: bool) -> Option<SelectionChange> {
+        let mut ids = if additive { self.ids.clone() } else { Vec::new() };
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+        self.replace(ids)
+    }
+
+    pub fn deselect(&mut self, id: u32) -> Option<SelectionChange> {
+        let ids = self.ids.iter().copied().filter(|&selected| selected != id).collect();
+        self.replace(ids)
+    }
+
+    pub fn clear(&mut self) -> Option<SelectionChange> {
+        self.replace(Vec::new())
+    }
+
+    fn replace(&mut self, ids: Vec<u32>) -> Option<SelectionChange> {
+        let added: Vec<u32> = ids.iter().copied().filter(|id| !self.ids.contains(id)).collect();
+        let removed: Vec<u32> = self.ids.iter().copied().filter(|id| !ids.contains(id)).collect();
+        self.ids = ids;
+        if added.is_empty() && removed.is_empty() {
+            None
+        } else {
+            Some(SelectionChange { added, removed })
+        }
+    }
+}
+
+/*
+A call to the page's selection listener, made from a microtask by `queue`. Calls from the page hold the `MVisual` (wasm-bindgen borrows it) until they return, so a listener called straight away couldn't call back into the visual, to recolor what was selected say.
+*/
+#[must_use]
+pub struct SelectionEvent {
+    pub listener: js_sys::Function,
+    pub detail: js_sys::Object,
+}
+
+impl SelectionEvent {
+    /**
+     * Calls the listener once the current JS call has returned. What it throws is logged, there is no caller left to hand it to.
+     */
+    pub fn queue(self) -> Result<(), MVisualError> {
+        let window = web_sys::window().ok_or_else(|| MVisualError::Context(String::from("No window to call the selection listener from")))?;
+        let call = Closure::once_into_js(move || {
+            if let Err(error) = self.listener.call1(&JsValue::NULL, &self.detail) {
+                web_sys::console::error_1(&error);
+            }
+        });
+        window.queue_microtask(call.unchecked_ref());
+        Ok(())
+    }
+}
+
+/**
+ * Queues the event a `Visual` call returned, if it returned one.
+ */
+pub fn notify(event: Result<Option<SelectionEvent>, MVisualError>) -> Result<(), MVisualError> {
+    event?.map_or(Ok(()), SelectionEvent::queue)
+}
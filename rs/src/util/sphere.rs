@@ -1,5 +1,6 @@
 use std::f32::consts::PI;
 
+#[derive(Clone)]
 pub struct Sphere {
     radius: f32,
     v_sectors: u16,
@@ -24,7 +25,7 @@ impl Sphere {
         }
 
         let mut value = Self {
-            radius: radius,
+            radius,
             v_sectors: 2 + resolution,
             h_sectors: 3 + resolution,
             vertices: Vec::new(),
@@ -35,7 +36,7 @@ impl Sphere {
 
         value.recalculate();
 
-        return value;
+        value
     }
 
     // https://stackoverflow.com/a/969880/11325551
@@ -0,0 +1,313 @@
+use super::expression::Expression;
+use super::math::get_grid_indices_n_by_n;
+use crate::error::MVisualError;
+
+// Largest grid which still fits u16 indices ((n + 1)^2 <= 65536), bigger grids need 32 bit index support
+pub const MAX_U16_SURFACE_RESOLUTION: usize = 255;
+// About a million vertices
+pub const MAX_SURFACE_RESOLUTION: usize = 1023;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    // Color by z
+    Height,
+    // Color by a second value given per vertex (falls back to height when there is none)
+    Scalar,
+    // Color by the steepness of the surface, |grad z|
+    Slope,
+}
+
+impl ColorMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "height" => Some(ColorMode::Height),
+            "scalar" => Some(ColorMode::Scalar),
+            "slope" => Some(ColorMode::Slope),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMode::Height => "height",
+            ColorMode::Scalar => "scalar",
+            ColorMode::Slope => "slope",
+        }
+    }
+}
+
+/*
+A height field z = f(x, y) sampled on a regular (resolution + 1) by (resolution + 1) grid.
+
+Values are stored row by row, x changes fastest:
+
+Row y=0: z(0, 0), z(1, 0), ... z(n, 0)
+Row y=1: z(0, 1), z(1, 1), ... z(n, 1)
+
+Which matches the vertex layout of `get_position_grid_n_by_n` (with its Z rows standing in for y) so its index pattern can be reused.
+*/
+#[derive(Clone)]
+pub struct Surface {
+    pub resolution: usize,
+    pub x_range: (f32, f32),
+    pub y_range: (f32, f32),
+    pub heights: Vec<f32>,
+    pub scalars: Option<Vec<f32>>,
+    pub vertices: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub slopes: Vec<f32>,
+    // u32 so grids past `MAX_U16_SURFACE_RESOLUTION` fit
+    pub indices: Vec<u32>,
+}
+
+impl Surface {
+    /**
+     * Builds a surface from sampled heights (and optionally a second scalar per vertex).
+     *
+     * Returns an error when the samples don't form a square grid of at least 2x2 and at most `MAX_SURFACE_RESOLUTION + 1` on a side.
+     */
+    pub fn new(
+        heights: Vec<f32>,
+        scalars: Option<Vec<f32>>,
+        x_range: (f32, f32),
+        y_range: (f32, f32),
+    ) -> Result<Self, MVisualError> {
+        let side = (heights.len() as f64).sqrt().round() as usize;
+        if side * side != heights.len() || side < 2 {
+            return Err(MVisualError::Input(format!("Surface heights must form a square grid of at least 2x2, got {} values", heights.len())));
+        }
+        if side - 1 > MAX_SURFACE_RESOLUTION {
+            return Err(MVisualError::Input(format!("Surface resolution can be at most {}, got {}", MAX_SURFACE_RESOLUTION, side - 1)));
+        }
+        if let Some(scalars) = &scalars {
+            if scalars.len() != heights.len() {
+                return Err(MVisualError::Input(format!("Expected {} surface scalars, got {}", heights.len(), scalars.len())));
+            }
+        }
+
+        let mut value = Self {
+            resolution: side - 1,
+            x_range,
+            y_range,
+            heights,
+            scalars,
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            slopes: Vec::new(),
+            indices: Vec::new(),
+        };
+
+        value.recalculate();
+        value.indices = get_grid_indices_n_by_n(value.resolution);
+
+        Ok(value)
+    }
+
+    /**
+     * Replaces the heights (same resolution) and recomputes the vertices, normals and slopes from them. Indices don't change, so only the vertex data needs uploading again.
+     */
+    pub fn set_heights(&mut self, heights: Vec<f32>) -> Result<(), MVisualError> {
+        if heights.len() != self.heights.len() {
+            return Err(MVisualError::Input(format!("Expected {} surface heights, got {}", self.heights.len(), heights.len())));
+        }
+        self.heights = heights;
+        self.recalculate();
+        Ok(())
+    }
+
+    /**
+     * Raw (not normalized) per vertex values used for coloring.
+     */
+    pub fn color_values(&self, mode: ColorMode) -> &[f32] {
+        match (mode, &self.scalars) {
+            (ColorMode::Scalar, Some(scalars)) => scalars,
+            (ColorMode::Slope, _) => &self.slopes,
+            _ => &self.heights,
+        }
+    }
+
+    fn recalculate(&mut self) {
+        let n = self.resolution;
+        let n_plus_one = n + 1;
+        let dx = (self.x_range.1 - self.x_range.0) / n as f32;
+        let dy = (self.y_range.1 - self.y_range.0) / n as f32;
+
+        let height = |i: usize, j: usize| self.heights[j * n_plus_one + i];
+
+        let mut vertices = Vec::with_capacity(3 * self.heights.len());
+        let mut normals = Vec::with_capacity(3 * self.heights.len());
+        let mut slopes = Vec::with_capacity(self.heights.len());
+
+        for j in 0..n_plus_one {
+            for i in 0..n_plus_one {
+                let (x, y) = grid_position(i, j, n, self.x_range, self.y_range);
+                vertices.push(x);
+                vertices.push(y);
+                vertices.push(height(i, j));
+
+                /*
+                Central differences, falling back to one sided differences on the edges of the grid.
+                */
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(n));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(n));
+                let dz_dx = if dx != 0. {
+                    (height(i1, j) - height(i0, j)) / ((i1 - i0) as f32 * dx)
+                } else {
+                    0.
+                };
+                let dz_dy = if dy != 0. {
+                    (height(i, j1) - height(i, j0)) / ((j1 - j0) as f32 * dy)
+                } else {
+                    0.
+                };
+
+                // The normal of z = f(x, y) is (-df/dx, -df/dy, 1)
+                let length = (dz_dx * dz_dx + dz_dy * dz_dy + 1.).sqrt();
+                normals.push(-dz_dx / length);
+                normals.push(-dz_dy / length);
+                normals.push(1. / length);
+
+                slopes.push((dz_dx * dz_dx + dz_dy * dz_dy).sqrt());
+            }
+        }
+
+        self.vertices = vertices;
+        self.normals = normals;
+        self.slopes = slopes;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Normals {
+    // Exact, from the expression's derivatives
+    Analytic,
+    // Estimated from neighbouring heights, one grid step apart
+    FiniteDifference,
+}
+
+impl Normals {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "analytic" => Some(Normals::Analytic),
+            "finite_difference" => Some(Normals::FiniteDifference),
+            _ => None,
+        }
+    }
+}
+
+/*
+A surface given by an expression in x, y and t, sampled again whenever t changes.
+*/
+#[derive(Clone)]
+pub struct SurfaceFunction {
+    pub expression: Expression,
+    pub resolution: usize,
+    pub x_range: (f32, f32),
+    pub y_range: (f32, f32),
+}
+
+impl SurfaceFunction {
+    // Order of the values `Expression::evaluate` takes
+    pub const VARIABLES: [&'static str; 3] = ["x", "y", "t"];
+
+    pub fn parse(
+        source: &str,
+        resolution: usize,
+        x_range: (f32, f32),
+        y_range: (f32, f32),
+    ) -> Result<Self, MVisualError> {
+        if !(1..=MAX_SURFACE_RESOLUTION).contains(&resolution) {
+            return Err(MVisualError::Input(format!("Surface resolution must be between 1 and {}, got {}", MAX_SURFACE_RESOLUTION, resolution)));
+        }
+
+        Ok(Self {
+            expression: Expression::parse(source, &Self::VARIABLES)?,
+            resolution,
+            x_range,
+            y_range,
+        })
+    }
+
+    /**
+     * Whether the surface changes with t.
+     */
+    pub fn is_animated(&self) -> bool {
+        self.expression.uses_variable(2)
+    }
+
+    /**
+     * Heights on the grid at time `t`, laid out like `Surface::heights`. Values which aren't finite (e.g. log of a negative number) are flattened to 0.
+     */
+    pub fn heights(&self, t: f32) -> Vec<f32> {
+        let n = self.resolution;
+        let mut heights = Vec::with_capacity((n + 1) * (n + 1));
+        for j in 0..=n {
+            for i in 0..=n {
+                let (x, y) = grid_position(i, j, n, self.x_range, self.y_range);
+                let z = self.expression.evaluate(&[x, y, t]);
+                heights.push(if z.is_finite() { z } else { 0. });
+            }
+        }
+        heights
+    }
+
+    pub fn sample(&self, t: f32) -> Result<Surface, MVisualError> {
+        Surface::new(self.heights(t), None, self.x_range, self.y_range)
+    }
+
+    /**
+     * Defines for the "surface_function" shader chunk, which evaluates the surface on the GPU.
+     */
+    pub fn glsl_defines(&self, normals: Normals) -> Vec<(&'static str, String)> {
+        let variables = Self::VARIABLES;
+        let mut defines = vec![("SURFACE_HEIGHT", self.expression.to_glsl(&variables))];
+        if normals == Normals::Analytic {
+            defines.push(("ANALYTIC_NORMALS", String::from("1")));
+            defines.push(("SURFACE_DZ_DX", self.expression.derivative(0).to_glsl(&variables)));
+            defines.push(("SURFACE_DZ_DY", self.expression.derivative(1).to_glsl(&variables)));
+        }
+        defines
+    }
+
+    /**
+     * Distance between neighbouring grid points in x and y.
+     */
+    pub fn grid_step(&self) -> [f32; 2] {
+        let n = self.resolution as f32;
+        [(self.x_range.1 - self.x_range.0) / n, (self.y_range.1 - self.y_range.0) / n]
+    }
+}
+
+/**
+ * Normalizes values onto [0, 1], returning them with the (min, max) they were scaled from.
+ */
+pub fn normalize_values(values: &[f32]) -> (Vec<f32>, (f32, f32)) {
+    let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let range = max - min;
+
+    let normalized = values
+        .iter()
+        .map(|value| if range > 0. { (value - min) / range } else { 0.5 })
+        .collect();
+
+    (normalized, (min, max))
+}
+
+/**
+ * Normalizes values onto [0, 1] using a `range` found earlier, values outside of it are clamped.
+ */
+pub fn normalize_values_in(values: &[f32], range: (f32, f32)) -> Vec<f32> {
+    let (min, max) = range;
+    values
+        .iter()
+        .map(|value| if max > min { ((value - min) / (max - min)).clamp(0., 1.) } else { 0.5 })
+        .collect()
+}
+
+fn grid_position(i: usize, j: usize, n: usize, x_range: (f32, f32), y_range: (f32, f32)) -> (f32, f32) {
+    (
+        x_range.0 + (x_range.1 - x_range.0) * i as f32 / n as f32,
+        y_range.0 + (y_range.1 - y_range.0) * j as f32 / n as f32,
+    )
+}
@@ -0,0 +1,109 @@
+/*
+Tick placement based on the "nice numbers" algorithm from Paul Heckbert's Graphics Gems article "Nice Numbers for Graph Labels".
+
+The idea is to round the range and the step between ticks to 1, 2, 5 or 10 times a power of ten so the labels are easy to read.
+*/
+
+#[derive(Clone)]
+pub struct Ticks {
+    pub step: f32,
+    pub major: Vec<f32>,
+    pub minor: Vec<f32>,
+}
+
+/**
+ * Find a "nice" number close to `value`.
+ *
+ * When `round` is true the closest nice number is picked, otherwise the smallest nice number greater than or equal to `value` is.
+ */
+pub fn nice_number(value: f32, round: bool) -> f32 {
+    let exponent = value.log10().floor();
+    let fraction = value / 10_f32.powf(exponent);
+
+    let nice_fraction = if round {
+        if fraction < 1.5 {
+            1.
+        } else if fraction < 3. {
+            2.
+        } else if fraction < 7. {
+            5.
+        } else {
+            10.
+        }
+    } else if fraction <= 1. {
+        1.
+    } else if fraction <= 2. {
+        2.
+    } else if fraction <= 5. {
+        5.
+    } else {
+        10.
+    };
+
+    nice_fraction * 10_f32.powf(exponent)
+}
+
+/**
+ * Calculates major and minor ticks inside of [min, max] aiming for about `target_count` major ticks.
+ *
+ * Minor ticks subdivide each major step into 4 (for steps starting with a 2) or 5 pieces and never overlap a major tick.
+ */
+pub fn nice_ticks(min: f32, max: f32, target_count: usize) -> Ticks {
+    let (min, max) = if min <= max { (min, max) } else { (max, min) };
+    let range = max - min;
+
+    // A degenerate range still gets a single tick
+    if range <= f32::EPSILON || target_count < 2 {
+        return Ticks {
+            step: 0.,
+            major: vec![min],
+            minor: Vec::new(),
+        };
+    }
+
+    let step = nice_number(nice_number(range, false) / (target_count - 1) as f32, true);
+
+    let leading_digit = (step / 10_f32.powf(step.log10().floor())).round();
+    let subdivisions = if leading_digit as i32 == 2 { 4 } else { 5 };
+    let minor_step = step / subdivisions as f32;
+
+    // Small tolerance so ticks sitting right on the bounds are not lost to rounding
+    let tolerance = step * 1e-4;
+
+    let mut major = Vec::new();
+    let mut minor = Vec::new();
+
+    let first = (min / step).ceil() as i32;
+    let last = (max / step).floor() as i32;
+    for i in (first - 1)..(last + 1) {
+        let major_value = i as f32 * step;
+        if major_value >= min - tolerance && major_value <= max + tolerance {
+            major.push(major_value);
+        }
+
+        for j in 1..subdivisions {
+            let minor_value = major_value + j as f32 * minor_step;
+            if minor_value >= min - tolerance && minor_value <= max + tolerance {
+                minor.push(minor_value);
+            }
+        }
+    }
+
+    Ticks { step, major, minor }
+}
+
+/**
+ * Formats a tick value with just enough decimals to tell neighbouring ticks apart.
+ */
+pub fn format_tick(value: f32, step: f32) -> String {
+    let decimals = if step > 0. {
+        (-step.log10().floor()).max(0.) as usize
+    } else {
+        0
+    };
+
+    // Avoids printing "-0"
+    let value = if value.abs() < step * 1e-3 { 0. } else { value };
+
+    format!("{:.*}", decimals, value)
+}
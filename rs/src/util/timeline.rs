@@ -0,0 +1,539 @@
+use super::material::Material;
+use crate::error::MVisualError;
+use nalgebra::{UnitQuaternion, Vector3};
+use std::f32::consts::PI;
+
+// Radians short of a half turn apart at which two camera poses count as opposite views
+const OPPOSITE_TOLERANCE: f32 = 1e-3;
+
+/*
+How a value moves from one keyframe to the next, as a map from the fraction of the time between them onto the fraction of the way between the values.
+*/
+#[derive(Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    // Holds the previous value and jumps on the keyframe
+    Step,
+    // Cubic, slow at the start
+    EaseIn,
+    // Cubic, slow at the end
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "step" => Some(Easing::Step),
+            "ease-in" => Some(Easing::EaseIn),
+            "ease-out" => Some(Easing::EaseOut),
+            "ease-in-out" => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, s: f32) -> f32 {
+        let s = s.clamp(0., 1.);
+        match self {
+            Easing::Linear => s,
+            Easing::Step => if s < 1. { 0. } else { 1. },
+            Easing::EaseIn => s * s * s,
+            Easing::EaseOut => 1. - (1. - s).powi(3),
+            Easing::EaseInOut => {
+                if s < 0.5 {
+                    4. * s * s * s
+                } else {
+                    1. - (2. - 2. * s).powi(3) / 2.
+                }
+            }
+        }
+    }
+}
+
+/*
+Values a track can move between. `s` is already eased, 0 gives `self` and 1 gives `other`.
+*/
+pub trait Interpolate: Clone {
+    fn interpolate(&self, other: &Self, s: f32) -> Self;
+}
+
+impl Interpolate for Vec<f32> {
+    fn interpolate(&self, other: &Self, s: f32) -> Self {
+        self.iter()
+            .zip(other.iter())
+            .map(|(a, b)| a + (b - a) * s)
+            .collect()
+    }
+}
+
+/*
+Where the orbit camera looks from, the same two angles dragging the mouse changes (see `AppState`).
+*/
+#[derive(Clone, Copy)]
+pub struct CameraPose {
+    pub rotation_x: f32,
+    pub rotation_y: f32,
+}
+
+impl CameraPose {
+    fn orientation(&self) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.rotation_x)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.rotation_y)
+    }
+
+    /*
+    Splits an orientation back into rotation x * rotation y * roll and drops the roll, which the orbit camera can't show.
+
+    Every orientation has two such splits, the one with less roll is kept. Angles are moved by whole turns to be nearest `near`, so a pose taken apart and put back together comes out as the angles it started with rather than their equivalents on (-pi, pi].
+    */
+    fn from_orientation(orientation: &UnitQuaternion<f32>, near: &CameraPose) -> Self {
+        let m = orientation.to_rotation_matrix();
+        let m = m.matrix();
+
+        let rotation_y = m[(0, 2)].clamp(-1., 1.).asin();
+        let rotation_x = (-m[(1, 2)]).atan2(m[(2, 2)]);
+        let roll = (-m[(0, 1)]).atan2(m[(0, 0)]);
+
+        // The other split goes over the top, with the camera turned upside down by the roll
+        let (rotation_x, rotation_y) = if roll.abs() > PI / 2. {
+            (rotation_x + PI, PI - rotation_y)
+        } else {
+            (rotation_x, rotation_y)
+        };
+
+        Self {
+            rotation_x: nearest_turn(rotation_x, near.rotation_x),
+            rotation_y: nearest_turn(rotation_y, near.rotation_y),
+        }
+    }
+}
+
+impl Interpolate for CameraPose {
+    /*
+    Slerps the orientations, so the camera turns at a steady rate along the shortest way round. Keyframes which only differ in one angle turn about that axis alone.
+    */
+    fn interpolate(&self, other: &Self, s: f32) -> Self {
+        // Angles lerped, only to pick which whole turn the result is given in
+        let near = CameraPose {
+            rotation_x: self.rotation_x + (other.rotation_x - self.rotation_x) * s,
+            rotation_y: self.rotation_y + (other.rotation_y - self.rotation_y) * s,
+        };
+
+        // Views exactly opposite each other have no shortest way round (slerp would pick one arbitrarily), turn through the lerped angles instead
+        let (from, to) = (self.orientation(), other.orientation());
+        if from.angle_to(&to) > PI - OPPOSITE_TOLERANCE {
+            return near;
+        }
+        Self::from_orientation(&from.slerp(&to, s), &near)
+    }
+}
+
+/*
+`angle` plus or minus whole turns, whichever is closest to `near`.
+*/
+fn nearest_turn(angle: f32, near: f32) -> f32 {
+    angle + 2. * PI * ((near - angle) / (2. * PI)).round()
+}
+
+#[derive(Clone)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    // Used on the way into this keyframe from the one before
+    pub easing: Easing,
+}
+
+/*
+Keyframes for one value, in time order.
+*/
+#[derive(Clone)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<T: Interpolate> Track<T> {
+    /**
+     * Adds a keyframe, replacing any already at the same time.
+     */
+    pub fn insert(&mut self, key: Keyframe<T>) {
+        match self.keys.iter().position(|other| other.time >= key.time) {
+            Some(i) if self.keys[i].time == key.time => self.keys[i] = key,
+            Some(i) => self.keys.insert(i, key),
+            None => self.keys.push(key),
+        }
+    }
+
+    /**
+     * The value at `time`. Before the first keyframe it is the first value and after the last the last one.
+     */
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let first = self.keys.first()?;
+        if time <= first.time {
+            return Some(first.value.clone());
+        }
+
+        // Index of the first keyframe after `time`
+        let next = match self.keys.iter().position(|key| key.time > time) {
+            Some(next) => next,
+            None => return self.keys.last().map(|key| key.value.clone()),
+        };
+        let (from, to) = (&self.keys[next - 1], &self.keys[next]);
+        let s = (time - from.time) / (to.time - from.time);
+        Some(from.value.interpolate(&to.value, to.easing.apply(s)))
+    }
+
+    /**
+     * (first, last) keyframe times, `None` while empty.
+     */
+    pub fn span(&self) -> Option<(f32, f32)> {
+        Some((self.keys.first()?.time, self.keys.last()?.time))
+    }
+
+    /*
+    Whether time going from `from` to `to` touched the track. Tracks are only applied while this holds, so that once the animation has run past them whatever they set can be changed by hand again, but a seek which jumps right over a track still lands on its end value.
+    */
+    fn is_touched(&self, from: Option<f32>, to: f32) -> bool {
+        let (first, last) = match self.span() {
+            Some(span) => span,
+            None => return false,
+        };
+        let from = from.unwrap_or(to);
+        from.min(to) <= last && from.max(to) >= first
+    }
+}
+
+/*
+Object settings which can be keyframed, on objects picked by their id.
+
+An object's parameters (a surface's function and ranges, a curve's points) aren't, changing them rebuilds the object rather than moving a value. Shapes which change over time use the animation time `t` in their expression instead.
+*/
+#[derive(Clone, Copy, PartialEq)]
+pub enum Property {
+    Color,
+    Opacity,
+    Emissive,
+    Ambient,
+    Diffuse,
+    Specular,
+    Shininess,
+    WireframeColor,
+    // Stretch along the object's own axes, see `Program::set_scale`
+    Scale,
+}
+
+impl Property {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "color" => Some(Property::Color),
+            "opacity" => Some(Property::Opacity),
+            "emissive" => Some(Property::Emissive),
+            "ambient" => Some(Property::Ambient),
+            "diffuse" => Some(Property::Diffuse),
+            "specular" => Some(Property::Specular),
+            "shininess" => Some(Property::Shininess),
+            "wireframe-color" => Some(Property::WireframeColor),
+            "scale" => Some(Property::Scale),
+            _ => None,
+        }
+    }
+
+    /**
+     * Number of values in one keyframe, 3 for colors and scales.
+     */
+    pub fn components(&self) -> usize {
+        match self {
+            Property::Color | Property::Emissive | Property::WireframeColor | Property::Scale => 3,
+            _ => 1,
+        }
+    }
+
+    /**
+     * Writes `values` into `material`. Returns false for properties which aren't part of the material.
+     */
+    pub fn apply_to_material(&self, material: &mut Material, values: &[f32]) -> bool {
+        let color = || [values[0], values[1], values[2]];
+        match self {
+            Property::Color => material.color = color(),
+            Property::Opacity => material.opacity = values[0].clamp(0., 1.),
+            Property::Emissive => material.emissive = color(),
+            Property::Ambient => material.ambient = values[0],
+            Property::Diffuse => material.diffuse = values[0],
+            Property::Specular => material.specular = values[0],
+            Property::Shininess => material.shininess = values[0],
+            Property::WireframeColor => material.wireframe_color = color(),
+            Property::Scale => return false,
+        }
+        true
+    }
+}
+
+struct ObjectTrack {
+    id: u32,
+    property: Property,
+    track: Track<Vec<f32>>,
+}
+
+/*
+What the timeline sets at one time. Only the tracks the time touched are in it.
+*/
+pub struct TimelineFrame {
+    pub camera: Option<CameraPose>,
+    // (object id, property, values)
+    pub objects: Vec<(u32, Property, Vec<f32>)>,
+}
+
+/*
+Keyframed camera and object settings, played by the animation clock. Scripts like "rotate to this view, then stretch the sphere, then fade in the wireframe" are keyframes at increasing times, with each track only taking over while the time is within its keyframes.
+*/
+#[derive(Default)]
+pub struct Timeline {
+    camera: Track<CameraPose>,
+    objects: Vec<ObjectTrack>,
+    // Time of the last `advance`, to tell which tracks were passed over since
+    last_time: Option<f32>,
+}
+
+impl Timeline {
+    pub fn add_camera_keyframe(&mut self, key: Keyframe<CameraPose>) {
+        self.camera.insert(key);
+    }
+
+    pub fn add_object_keyframe(
+        &mut self,
+        id: u32,
+        property: Property,
+        key: Keyframe<Vec<f32>>,
+    ) -> Result<(), MVisualError> {
+        if key.value.len() != property.components() {
+            return Err(MVisualError::Input(format!(
+                "Keyframe needs {} values for this property, got {}",
+                property.components(),
+                key.value.len()
+            )));
+        }
+
+        match self
+            .objects
+            .iter_mut()
+            .find(|object| object.id == id && object.property == property)
+        {
+            Some(object) => object.track.insert(key),
+            None => {
+                let mut track = Track::default();
+                track.insert(key);
+                self.objects.push(ObjectTrack { id, property, track });
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Removes the keyframes of object `id`, for when the object itself goes.
+     */
+    pub fn remove_object(&mut self, id: u32) {
+        self.objects.retain(|object| object.id != id);
+    }
+
+    /**
+     * Removes every keyframe, leaving the camera and objects as they are.
+     */
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /**
+     * Time of the last keyframe, 0 while there are none.
+     */
+    pub fn duration(&self) -> f32 {
+        self.objects
+            .iter()
+            .filter_map(|object| object.track.span())
+            .chain(self.camera.span())
+            .map(|(_, last)| last)
+            .fold(0., f32::max)
+    }
+
+    /**
+     * Moves the timeline on to `time`, returning what to set.
+     */
+    pub fn advance(&mut self, time: f32) -> TimelineFrame {
+        let from = self.last_time.replace(time);
+
+        let camera = if self.camera.is_touched(from, time) {
+            self.camera.sample(time)
+        } else {
+            None
+        };
+        let objects = self
+            .objects
+            .iter()
+            .filter(|object| object.track.is_touched(from, time))
+            .filter_map(|object| {
+                object.track.sample(time).map(|values| (object.id, object.property, values))
+            })
+            .collect();
+
+        TimelineFrame { camera, objects }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASINGS: [Easing; 5] = [Easing::Linear, Easing::Step, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut];
+
+    fn pose(rotation_x: f32, rotation_y: f32) -> CameraPose {
+        CameraPose { rotation_x, rotation_y }
+    }
+
+    fn assert_pose(actual: CameraPose, expected: CameraPose) {
+        assert!(
+            (actual.rotation_x - expected.rotation_x).abs() < 1e-4 && (actual.rotation_y - expected.rotation_y).abs() < 1e-4,
+            "Expected ({}, {}) but got ({}, {})",
+            expected.rotation_x, expected.rotation_y, actual.rotation_x, actual.rotation_y
+        );
+    }
+
+    fn key(time: f32, value: f32, easing: Easing) -> Keyframe<Vec<f32>> {
+        Keyframe { time, value: vec![value], easing }
+    }
+
+    fn track(keys: &[(f32, f32)]) -> Track<Vec<f32>> {
+        let mut track = Track::default();
+        for (time, value) in keys.iter() {
+            track.insert(key(*time, *value, Easing::Linear));
+        }
+        track
+    }
+
+    #[test]
+    fn poses_survive_a_round_trip_through_their_orientation() {
+        // Including angles past a half turn, and looking over the top
+        for &(x, y) in [(0., 0.), (0.4, -0.3), (2.5, 1.2), (-3., 2.), (7., 0.5), (1., -2.5)].iter() {
+            let original = pose(x, y);
+            assert_pose(CameraPose::from_orientation(&original.orientation(), &original), original);
+        }
+    }
+
+    #[test]
+    fn one_angle_changing_turns_about_that_axis() {
+        assert_pose(pose(0., 0.).interpolate(&pose(0.8, 0.), 0.5), pose(0.4, 0.));
+        assert_pose(pose(0.3, 0.).interpolate(&pose(0.3, 1.), 0.25), pose(0.3, 0.25));
+        assert_pose(pose(0., 0.).interpolate(&pose(0., 1.), 1.), pose(0., 1.));
+    }
+
+    #[test]
+    fn angles_stay_in_the_turn_they_were_given_in() {
+        let turn = 2. * PI;
+        assert_pose(pose(turn, 0.).interpolate(&pose(turn + 0.5, 0.), 0.5), pose(turn + 0.25, 0.));
+    }
+
+    #[test]
+    fn opposite_views_turn_through_the_lerped_angles() {
+        assert_pose(pose(0., 0.).interpolate(&pose(0., PI), 0.5), pose(0., PI / 2.));
+        assert_pose(pose(0., 0.).interpolate(&pose(PI, 0.), 0.25), pose(PI / 4., 0.));
+    }
+
+    #[test]
+    fn easings_start_at_0_and_end_at_1() {
+        for easing in EASINGS.iter() {
+            assert_eq!(easing.apply(0.), 0.);
+            assert_eq!(easing.apply(1.), 1.);
+            // Clamped outside
+            assert_eq!(easing.apply(-1.), 0.);
+            assert_eq!(easing.apply(2.), 1.);
+        }
+    }
+
+    #[test]
+    fn easings_have_their_shapes() {
+        assert_eq!(Easing::Linear.apply(0.3), 0.3);
+        assert_eq!(Easing::Step.apply(0.99), 0.);
+        assert!(Easing::EaseIn.apply(0.3) < 0.3);
+        assert!(Easing::EaseOut.apply(0.3) > 0.3);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert!((Easing::EaseInOut.apply(0.2) + Easing::EaseInOut.apply(0.8) - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn keyframes_are_kept_in_time_order() {
+        let track = track(&[(2., 20.), (0., 0.), (1., 10.)]);
+        let times: Vec<f32> = track.keys.iter().map(|key| key.time).collect();
+        assert_eq!(times, vec![0., 1., 2.]);
+    }
+
+    #[test]
+    fn a_keyframe_at_the_same_time_replaces_the_old_one() {
+        let track = track(&[(0., 0.), (1., 10.), (1., 5.)]);
+        assert_eq!(track.keys.len(), 2);
+        assert_eq!(track.sample(1.), Some(vec![5.]));
+    }
+
+    #[test]
+    fn samples_hold_the_ends_and_interpolate_between() {
+        assert_eq!(Track::<Vec<f32>>::default().sample(0.), None);
+
+        let track = track(&[(1., 10.), (3., 30.)]);
+        assert_eq!(track.sample(0.), Some(vec![10.]));
+        assert_eq!(track.sample(2.5), Some(vec![25.]));
+        assert_eq!(track.sample(3.), Some(vec![30.]));
+        assert_eq!(track.sample(9.), Some(vec![30.]));
+    }
+
+    #[test]
+    fn the_easing_into_a_keyframe_is_used() {
+        let mut track = track(&[(0., 0.), (1., 10.)]);
+        track.insert(key(2., 20., Easing::Step));
+        assert_eq!(track.sample(0.5), Some(vec![5.]));
+        assert_eq!(track.sample(1.9), Some(vec![10.]));
+        assert_eq!(track.sample(2.), Some(vec![20.]));
+    }
+
+    #[test]
+    fn tracks_are_touched_by_time_passing_through_or_over_them() {
+        let track = track(&[(1., 0.), (2., 1.)]);
+        // Inside, from the first advance or from either side
+        assert!(track.is_touched(None, 1.5));
+        assert!(track.is_touched(Some(0.), 1.));
+        assert!(track.is_touched(Some(3.), 1.5));
+        // Jumping over it either way
+        assert!(track.is_touched(Some(0.), 5.));
+        assert!(track.is_touched(Some(5.), 0.));
+        // Entirely before or after
+        assert!(!track.is_touched(Some(0.), 0.5));
+        assert!(!track.is_touched(Some(2.5), 4.));
+        assert!(!track.is_touched(None, 4.));
+        assert!(!Track::<Vec<f32>>::default().is_touched(None, 0.));
+    }
+
+    #[test]
+    fn the_timeline_only_sets_tracks_it_touched() {
+        let mut timeline = Timeline::default();
+        timeline.add_object_keyframe(0, Property::Opacity, key(0., 0., Easing::Linear)).unwrap();
+        timeline.add_object_keyframe(0, Property::Opacity, key(1., 1., Easing::Linear)).unwrap();
+        timeline.add_object_keyframe(1, Property::Diffuse, key(3., 0.5, Easing::Linear)).unwrap();
+        assert_eq!(timeline.duration(), 3.);
+
+        let ids = |frame: TimelineFrame| frame.objects.iter().map(|(id, _, _)| *id).collect::<Vec<u32>>();
+        assert_eq!(ids(timeline.advance(0.5)), vec![0]);
+        assert_eq!(ids(timeline.advance(2.)), vec![0]);
+        assert!(ids(timeline.advance(2.5)).is_empty());
+        assert_eq!(ids(timeline.advance(4.)), vec![1]);
+    }
+
+    #[test]
+    fn keyframes_need_a_value_per_component() {
+        let mut timeline = Timeline::default();
+        assert!(timeline.add_object_keyframe(0, Property::Color, key(0., 1., Easing::Linear)).is_err());
+        assert!(timeline.add_object_keyframe(0, Property::Opacity, key(0., 1., Easing::Linear)).is_ok());
+    }
+}
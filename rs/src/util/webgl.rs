@@ -1,20 +1,73 @@
+use std::cell::Cell;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
 use web_sys::WebGlRenderingContext as GL;
 use web_sys::*;
+use super::colormap::{ColorMap, COLOR_MAP_TEXTURE_SIZE};
+use super::gl::{Backend, Gl};
+use crate::shaders::preprocessor;
+use crate::error::MVisualError;
 
-pub fn initialize_webgl_context() -> Result<WebGlRenderingContext, JsValue> {
-    let window = window().unwrap();
-    let document = window.document().unwrap();
-    let canvas = document.get_element_by_id("rustCanvas").unwrap();
-    let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
-    let gl: WebGlRenderingContext = canvas.get_context("webgl")?.unwrap().dyn_into()?;
+/*
+What happened to the context since it was created, set by the "webglcontextlost" and "webglcontextrestored" listeners. Both events can come and go between two frames (a hidden tab gets no frames), so they are remembered rather than polled for.
+*/
+#[derive(Clone, Default)]
+pub struct ContextStatus {
+    lost: Rc<Cell<bool>>,
+    // Set until the restored context has been rebuilt
+    restored: Rc<Cell<bool>>,
+}
+
+impl ContextStatus {
+    /**
+     * Whether drawing has to wait, the context is lost or came back without anything on it yet.
+     */
+    pub fn is_unusable(&self) -> bool {
+        self.lost.get() || self.restored.get()
+    }
+
+    /**
+     * Whether the context came back since the last call and is still there, so now is the time to rebuild it.
+     */
+    pub fn take_restored(&self) -> bool {
+        !self.lost.get() && self.restored.replace(false)
+    }
+}
+
+/**
+ * Creates the context on the `rustCanvas` element, see `Gl::new` for how `backend` falls back.
+ */
+pub fn initialize_webgl_context(backend: Backend) -> Result<(Gl, ContextStatus), MVisualError> {
+    let window = window().ok_or_else(|| MVisualError::Context(String::from("No window")))?;
+    let document = window
+        .document()
+        .ok_or_else(|| MVisualError::Context(String::from("No document")))?;
+    let canvas = document
+        .get_element_by_id("rustCanvas")
+        .ok_or_else(|| MVisualError::Context(String::from("No element with the id rustCanvas")))?;
+    let canvas: web_sys::HtmlCanvasElement = canvas
+        .dyn_into::<web_sys::HtmlCanvasElement>()
+        .map_err(|_| MVisualError::Context(String::from("rustCanvas is not a canvas")))?;
+    let gl = Gl::new(&canvas, backend)?;
 
-    attach_mouse_down_handler(&canvas)?;
-    attach_mouse_up_handler(&canvas)?;
-    attach_mouse_move_handler(&canvas)?;
+    attach_mouse_down_handler(&canvas).map_err(MVisualError::context)?;
+    attach_mouse_up_handler(&canvas).map_err(MVisualError::context)?;
+    attach_mouse_move_handler(&canvas).map_err(MVisualError::context)?;
+    let status = ContextStatus::default();
+    attach_context_lost_handler(&canvas, &status).map_err(MVisualError::context)?;
+    attach_context_restored_handler(&canvas, &status).map_err(MVisualError::context)?;
 
+    initialize_state(&gl);
+
+    Ok((gl, status))
+}
+
+/**
+ * Global state every program expects, set on creation and again after the context is restored (a restored context starts from the defaults).
+ */
+pub fn initialize_state(gl: &WebGlRenderingContext) {
     /*
     The following will enable culling of faces that are pointed away the camera (must be defined counter clockwise)
     */
@@ -33,26 +86,77 @@ pub fn initialize_webgl_context() -> Result<WebGlRenderingContext, JsValue> {
 
     // Clear everything
     gl.clear_depth(1.);
-
-    Ok(gl)
 }
 
 pub fn link_program(
     gl: &WebGlRenderingContext,
     vert_source: &str,
     frag_source: &str,
-) -> Result<WebGlProgram, String> {
+) -> Result<WebGlProgram, MVisualError> {
+    link_program_with_defines(gl, vert_source, frag_source, &[])
+}
+
+/**
+ * Preprocesses both sources with `defines` (see `preprocessor::preprocess`), then compiles and links them.
+ *
+ * Compile and link errors come back with the offending sources annotated by line number.
+ */
+pub fn link_program_with_defines(
+    gl: &WebGlRenderingContext,
+    vert_source: &str,
+    frag_source: &str,
+    defines: &[(&str, &str)],
+) -> Result<WebGlProgram, MVisualError> {
+    link(gl, vert_source, frag_source, defines, false)
+}
+
+/**
+ * Like `link_program_with_defines` but translates both shaders to GLSL ES 3.0 first (see `preprocessor::to_glsl_300_es`), only for WebGL2 contexts.
+ */
+pub fn link_program_300_es(
+    gl: &WebGlRenderingContext,
+    vert_source: &str,
+    frag_source: &str,
+    defines: &[(&str, &str)],
+) -> Result<WebGlProgram, MVisualError> {
+    link(gl, vert_source, frag_source, defines, true)
+}
+
+fn link(
+    gl: &WebGlRenderingContext,
+    vert_source: &str,
+    frag_source: &str,
+    defines: &[(&str, &str)],
+    glsl_300_es: bool,
+) -> Result<WebGlProgram, MVisualError> {
+    let translate = |source: String, stage| {
+        if glsl_300_es {
+            preprocessor::to_glsl_300_es(&source, stage)
+        } else {
+            source
+        }
+    };
+
     let program = gl
         .create_program()
-        .ok_or_else(|| String::from("Error creating program"))?;
+        .ok_or_else(|| MVisualError::Shader(String::from("Error creating program")))?;
 
-    let vert_shader = compile_shader(&gl, GL::VERTEX_SHADER, vert_source).unwrap();
+    let vert_source = preprocessor::preprocess(vert_source, defines).map_err(MVisualError::Shader)?;
+    let vert_source = translate(vert_source, preprocessor::Stage::Vertex);
+    let vert_shader = compile_shader(gl, GL::VERTEX_SHADER, &vert_source)
+        .map_err(|error| MVisualError::Shader(format!("Unable to compile vertex shader: {}", error)))?;
 
-    let frag_shader = compile_shader(&gl, GL::FRAGMENT_SHADER, frag_source).unwrap();
+    let frag_source = preprocessor::preprocess(frag_source, defines).map_err(MVisualError::Shader)?;
+    let frag_source = translate(frag_source, preprocessor::Stage::Fragment);
+    let frag_shader = compile_shader(gl, GL::FRAGMENT_SHADER, &frag_source)
+        .map_err(|error| MVisualError::Shader(format!("Unable to compile fragment shader: {}", error)))?;
 
     gl.attach_shader(&program, &vert_shader);
     gl.attach_shader(&program, &frag_shader);
     gl.link_program(&program);
+    // Only flags them, they are freed along with the program
+    gl.delete_shader(Some(&vert_shader));
+    gl.delete_shader(Some(&frag_shader));
 
     if gl
         .get_program_parameter(&program, WebGlRenderingContext::LINK_STATUS)
@@ -61,9 +165,14 @@ pub fn link_program(
     {
         Ok(program)
     } else {
-        Err(gl
+        let log = gl
             .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+            .unwrap_or_else(|| String::from("Unknown error creating program object"));
+        gl.delete_program(Some(&program));
+        Err(MVisualError::Shader(format!(
+            "Unable to link program: {}",
+            preprocessor::annotate_link_errors(&vert_source, &frag_source, &log)
+        )))
     }
 }
 
@@ -85,9 +194,11 @@ fn compile_shader(
     {
         Ok(shader)
     } else {
-        Err(gl
+        let log = gl
             .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unable to get shader info log")))
+            .unwrap_or_else(|| String::from("Unable to get shader info log"));
+        gl.delete_shader(Some(&shader));
+        Err(preprocessor::annotate_errors(source, &log))
     }
 }
 
@@ -112,7 +223,7 @@ fn attach_mouse_down_handler(canvas: &HtmlCanvasElement) -> Result<(), JsValue>
      */
     handler.forget();
 
-    return Ok(());
+    Ok(())
 }
 
 fn attach_mouse_up_handler(canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
@@ -136,7 +247,7 @@ fn attach_mouse_up_handler(canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
      */
     handler.forget();
 
-    return Ok(());
+    Ok(())
 }
 
 fn attach_mouse_move_handler(canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
@@ -153,3 +264,74 @@ fn attach_mouse_move_handler(canvas: &HtmlCanvasElement) -> Result<(), JsValue>
 
     Ok(())
 }
+
+fn attach_context_lost_handler(canvas: &HtmlCanvasElement, status: &ContextStatus) -> Result<(), JsValue> {
+    /*
+    The browser only tries to restore a lost context (firing "webglcontextrestored") when the loss is prevented.
+    */
+    let lost = status.lost.clone();
+    let handler = move |event: web_sys::Event| {
+        event.prevent_default();
+        lost.set(true);
+    };
+
+    let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>);
+    canvas.add_event_listener_with_callback("webglcontextlost", handler.as_ref().unchecked_ref())?;
+    handler.forget();
+
+    Ok(())
+}
+
+fn attach_context_restored_handler(canvas: &HtmlCanvasElement, status: &ContextStatus) -> Result<(), JsValue> {
+    // Nothing can be rebuilt from in here, `MVisual::update` does that on the next frame
+    let status = status.clone();
+    let handler = move |_: web_sys::Event| {
+        status.lost.set(false);
+        status.restored.set(true);
+    };
+
+    let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>);
+    canvas.add_event_listener_with_callback("webglcontextrestored", handler.as_ref().unchecked_ref())?;
+    handler.forget();
+
+    Ok(())
+}
+
+/*
+Uploads a color map as a single row RGB texture, `texture` is created when None.
+*/
+pub fn fill_color_map_texture(
+    gl: &Gl,
+    texture: Option<WebGlTexture>,
+    color_map: &ColorMap,
+) -> Result<WebGlTexture, MVisualError> {
+    let texture = match texture {
+        Some(texture) => texture,
+        None => gl
+            .create_texture()
+            .ok_or_else(|| MVisualError::Buffer(String::from("Failed to create texture")))?,
+    };
+
+    gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+    // Rows of RGB pixels are not 4 byte aligned
+    gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
+    let pixels = color_map.to_rgb_bytes();
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        GL::TEXTURE_2D,
+        0,
+        GL::RGB as i32,
+        COLOR_MAP_TEXTURE_SIZE as i32,
+        1,
+        0,
+        GL::RGB,
+        GL::UNSIGNED_BYTE,
+        Some(&pixels),
+    ).map_err(|error| MVisualError::Buffer(format!("Failed to fill color map texture: {:?}", error)))?;
+    gl.count_upload(pixels.len());
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+
+    Ok(texture)
+}
@@ -0,0 +1,1024 @@
+use wasm_bindgen::JsValue;
+use web_sys::WebGlRenderingContext as GL;
+use nalgebra::{Perspective3, Vector3};
+use crate::programs::Program;
+use crate::error::MVisualError;
+use crate::{app_state, programs, scene, util};
+
+/*
+Everything behind an `MVisual`, in one place so the frame loop can share it with the handle the host page holds. The methods do what the `MVisual` methods of the same name are documented to.
+*/
+pub struct Visual {
+    gl: util::gl::Gl,
+    // In the order they were added
+    objects: Vec<SceneObject>,
+    // Ids are never reused, so this only goes up
+    next_id: u32,
+    selection: util::selection::Selection,
+    // Called with every change to the selection
+    selection_listener: Option<js_sys::Function>,
+    labels: programs::Text3D,
+    overlay: programs::Overlay2D,
+    // Used by everything which colors by value
+    color_map: util::colormap::ColorMap,
+    scene: scene::Scene,
+    // Everything is rebuilt once the GPU context comes back from being lost
+    context: util::webgl::ContextStatus,
+    stats: util::perf::PerfStats,
+}
+
+impl Visual {
+    pub fn new(backend: util::gl::Backend) -> Result<Self, MVisualError> {
+        let (gl, context) = util::webgl::initialize_webgl_context(backend)?;
+
+        let axes = programs::Axes3D::new(
+            &gl,
+            Vector3::new(-0.6, -0.6, -0.6),
+            Vector3::new(0.6, 0.6, 0.6),
+            true,
+        )?;
+
+        let mut labels = programs::Text3D::new(&gl)?;
+        for label in axes.labels() {
+            labels.add_label(label);
+        }
+
+        let programs: Vec<Box<dyn programs::common::Program>> = vec![
+            // Box::new(programs::Graph3D::new(&gl)),
+            Box::new(programs::Sphere3D::new(&gl, true)?),
+            Box::new(axes),
+        ];
+
+        let overlay = programs::Overlay2D::new(&gl)?;
+
+        let mut visual = Self {
+            gl,
+            objects: Vec::new(),
+            next_id: 0,
+            selection: util::selection::Selection::default(),
+            selection_listener: None,
+            labels,
+            overlay,
+            color_map: util::colormap::ColorMap::default(),
+            scene: scene::Scene::default(),
+            context,
+            stats: util::perf::PerfStats::default(),
+        };
+        for program in programs {
+            visual.add_object(program);
+        }
+        Ok(visual)
+    }
+
+    pub fn canvas(&self) -> Option<web_sys::HtmlCanvasElement> {
+        self.gl.canvas()
+    }
+
+    pub fn backend(&self) -> String {
+        String::from(self.gl.backend().name())
+    }
+
+    pub fn update(&mut self, time: f32, height: f32, width: f32) -> Result<(), MVisualError> {
+        app_state::update_dyanmic_data(time, height, width);
+
+        if self.context.take_restored() {
+            self.restore()?;
+        }
+        if self.context.is_unusable() {
+            return Ok(());
+        }
+
+        if self.scene.clock.advance(time) {
+            self.step_animation(self.scene.clock.time())?;
+        }
+
+        self.gl.viewport(0, 0, width as i32, height as i32);
+        Ok(())
+    }
+
+    pub fn render(&mut self) {
+        // Every call would fail anyway, `update` rebuilds once the context is back
+        if self.context.is_unusable() {
+            return;
+        }
+
+        self.stats.start_frame(util::perf::now());
+        self.select_detail();
+        self.update_legend();
+        if let Some(hud) = &mut self.overlay.hud {
+            hud.lines = self.stats.summary();
+            hud.histogram = self.stats.histogram_fractions();
+        }
+        self.stats.culled = self.draw();
+        self.stats.end_frame(util::perf::now(), self.gl.take_counters());
+    }
+
+    fn select_detail(&mut self) {
+        let curr_state = app_state::get_curr_state();
+        for object in &mut self.objects {
+            object.program.select_detail(&curr_state);
+        }
+    }
+
+    /*
+    One row per named object, in the colors they have now so material changes and keyframes show up straight away.
+    */
+    fn update_legend(&mut self) {
+        self.overlay.legend = self
+            .objects
+            .iter()
+            .filter_map(|object| {
+                let name = object.name.clone()?;
+                let color = object.program.legend_color()?;
+                Some(programs::LegendEntry { name, color })
+            })
+            .collect();
+    }
+
+    /*
+    Draws everything without touching the stats, so exports don't show up in them. Returns how many programs were culled.
+    */
+    fn draw(&self) -> u32 {
+        self.gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+        // Shared by every lit program, so uploaded once here instead of in each of them
+        self.gl.upload_lighting(&self.scene.lighting);
+
+        let curr_state = app_state::get_curr_state();
+
+        // Every program projects the same way, so one view space frustum does for all of them
+        let projection = projection(&curr_state);
+        let frustum = util::bounds::Frustum::from_matrix(projection.as_matrix());
+        let (visible, culled): (Vec<_>, Vec<_>) = self.objects.iter().map(|object| &object.program).partition(|program| {
+            program
+                .view_bounds(&curr_state)
+                .is_none_or(|bounds| frustum.intersects(&bounds))
+        });
+
+        let (transparent, opaque): (Vec<_>, Vec<_>) = visible
+            .into_iter()
+            .partition(|program| program.is_transparent());
+
+        for program in opaque {
+            program.render(
+                &self.gl,
+                &curr_state,
+                &self.scene,
+            );
+        }
+
+        /*
+        Blending is order dependent, so transparent programs go back to front after everything opaque. They test against the depth buffer but don't write to it, otherwise the nearer of two overlapping transparent objects would hide the other.
+
+        Sorting is per object, objects which intersect each other can still blend in the wrong order.
+        */
+        if !transparent.is_empty() {
+            let mut transparent: Vec<(f32, &Box<dyn Program>)> = transparent
+                .into_iter()
+                .map(|program| (program.view_depth(&curr_state), program))
+                .collect();
+            transparent.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            self.gl.enable(GL::BLEND);
+            self.gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+            self.gl.depth_mask(false);
+            for (_, program) in transparent {
+                program.render(
+                    &self.gl,
+                    &curr_state,
+                    &self.scene,
+                );
+            }
+            self.gl.depth_mask(true);
+            self.gl.disable(GL::BLEND);
+        }
+
+        // Drawn last so the overlay and labels sit on top of everything else
+        self.overlay.render(&self.gl, &curr_state, &self.scene);
+        self.labels.render_with_labels(
+            &self.gl,
+            &curr_state,
+            &self.overlay.labels(&curr_state),
+        );
+
+        culled.len() as u32
+    }
+
+    pub fn add_label(&mut self, text: &str, x: f32, y: f32, z: f32) -> usize {
+        self.labels.add_label(programs::Label::new(text, programs::Anchor::World(Vector3::new(x, y, z))))
+    }
+
+    pub fn add_screen_label(&mut self, text: &str, x: f32, y: f32) -> usize {
+        let mut label = programs::Label::new(text, programs::Anchor::Screen(x, y));
+        label.align = programs::TextAlign::Left;
+        self.labels.add_label(label)
+    }
+
+    fn add_light(
+        &mut self,
+        kind: util::lighting::LightKind,
+        vector: Vector3<f32>,
+        intensity: f32,
+    ) -> Result<usize, MVisualError> {
+        let light = util::lighting::Light {
+            intensity,
+            ..util::lighting::Light::new(kind, vector)
+        };
+        self.scene.lighting.add_light(light).ok_or_else(|| {
+            MVisualError::Input(format!("At most {} lights are supported", util::lighting::MAX_LIGHTS))
+        })
+    }
+
+    fn light_mut(&mut self, index: usize) -> Result<&mut util::lighting::Light, MVisualError> {
+        self.scene
+            .lighting
+            .lights
+            .get_mut(index)
+            .ok_or_else(|| MVisualError::Input(format!("No light at index {}", index)))
+    }
+
+    fn update_material(
+        &mut self,
+        id: u32,
+        update: impl FnOnce(&mut util::material::Material),
+    ) -> Result<(), MVisualError> {
+        let program = program_with_id(&mut self.objects, id)?;
+        let mut material = program
+            .material()
+            .ok_or_else(|| MVisualError::Input(format!("Object {} has no material", id)))?;
+
+        update(&mut material);
+        program.set_material(material);
+        Ok(())
+    }
+
+    /*
+    Moves everything animated to animation time `time`.
+    */
+    fn step_animation(&mut self, time: f32) -> Result<(), MVisualError> {
+        for object in self.objects.iter_mut() {
+            object.program.animate(&self.gl, time)?;
+        }
+        self.apply_timeline(time);
+        Ok(())
+    }
+
+    /*
+    Renders each frame of an export offscreen at the export's size and passes its pixels (RGBA, top row first) to `each`. The animation is stepped to each frame's time directly, the clock only finds out afterwards, and the camera and canvas size are put back when done.
+    */
+    fn capture_frames(
+        &mut self,
+        settings: &util::export::ExportSettings,
+        mut each: impl FnMut(&mut [u8]) -> Result<(), MVisualError>,
+    ) -> Result<(), MVisualError> {
+        if self.context.is_unusable() {
+            return Err(MVisualError::Context(String::from("Can't export while the WebGL context is lost")));
+        }
+
+        let target = util::render_target::RenderTarget::new(&self.gl, settings.width, settings.height)?;
+        let before = app_state::get_curr_state();
+        // Stats are about the live view, they have no place in the exported frames
+        let hud = self.overlay.hud.take();
+        // Labels, the overlay and the aspect ratio all follow the canvas size
+        app_state::update_dyanmic_data(before.time, settings.height as f32, settings.width as f32);
+        target.bind(&self.gl);
+
+        let result = (0..settings.frame_count()).try_for_each(|index| {
+            self.step_animation(settings.frame_time(index))?;
+            self.select_detail();
+            self.update_legend();
+            self.draw();
+
+            let mut pixels = target.read_pixels(&self.gl)?;
+            // Blending transparent objects also blends alpha, the canvas is shown opaque so the frames should be too
+            for pixel in pixels.chunks_mut(4) {
+                pixel[3] = 255;
+            }
+            each(&mut pixels)
+        });
+
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        target.delete(&self.gl);
+        app_state::update_dyanmic_data(before.time, before.canvas_height, before.canvas_width);
+        app_state::update_rotation(before.rotation_x_axis, before.rotation_y_axis);
+        self.gl.viewport(0, 0, before.canvas_width as i32, before.canvas_height as i32);
+        // Programs were left at the last frame, the next `update` puts them back at the clock's time
+        self.scene.clock.resync();
+        self.overlay.hud = hud;
+        self.gl.take_counters();
+
+        result
+    }
+
+    /*
+    Sets what the timeline's keyframes give at `time`, keyframes were checked against their objects as they were added.
+    */
+    fn apply_timeline(&mut self, time: f32) {
+        let frame = self.scene.timeline.advance(time);
+
+        if let Some(pose) = frame.camera {
+            app_state::update_rotation(pose.rotation_x, pose.rotation_y);
+        }
+        for (id, property, values) in frame.objects {
+            let program = match program_with_id(&mut self.objects, id) {
+                Ok(program) => program,
+                Err(_) => continue,
+            };
+            if property == util::timeline::Property::Scale {
+                program.set_scale(Vector3::new(values[0], values[1], values[2]));
+            } else if let Some(mut material) = program.material() {
+                property.apply_to_material(&mut material, &values);
+                program.set_material(material);
+            }
+        }
+    }
+
+    /*
+    Everything on the GPU is gone after a context loss, the context's state and every program are rebuilt from the CPU side copies of their data.
+    */
+    fn restore(&mut self) -> Result<(), MVisualError> {
+        self.gl.restore()?;
+        util::webgl::initialize_state(&self.gl);
+
+        for object in self.objects.iter_mut() {
+            object.program.restore(&self.gl, &self.color_map)?;
+        }
+        self.labels.restore(&self.gl, &self.color_map)?;
+        self.overlay.restore(&self.gl, &self.color_map)?;
+        Ok(())
+    }
+
+    fn apply_color_map(&mut self, color_map: util::colormap::ColorMap) -> Result<(), MVisualError> {
+        for object in self.objects.iter_mut() {
+            object.program.set_color_map(&self.gl, &color_map)?;
+        }
+        if let Some(colorbar) = &mut self.overlay.colorbar {
+            colorbar.color_map = color_map.clone();
+        }
+        self.color_map = color_map;
+        Ok(())
+    }
+
+    pub fn clear_labels(&mut self) {
+        self.labels.clear_labels();
+    }
+
+    pub fn set_colorbar(&mut self, min: f32, max: f32, title: &str) {
+        let corner = match &self.overlay.colorbar {
+            Some(colorbar) => colorbar.corner,
+            None => programs::Corner::BottomRight,
+        };
+
+        self.overlay.colorbar = Some(programs::Colorbar {
+            color_map: self.color_map.clone(),
+            min,
+            max,
+            title: String::from(title),
+            corner,
+        });
+    }
+
+    pub fn set_color_map(&mut self, name: &str) -> Result<(), MVisualError> {
+        let color_map = util::colormap::ColorMap::from_name(name).ok_or_else(|| {
+            MVisualError::Input(format!(
+                "Unknown color map: {}, expected one of {}",
+                name,
+                util::colormap::COLOR_MAP_NAMES.join(", ")
+            ))
+        })?;
+        self.apply_color_map(color_map)
+    }
+
+    pub fn set_custom_color_map(&mut self, colors: Vec<f32>) -> Result<(), MVisualError> {
+        if colors.len() < 6 || !colors.len().is_multiple_of(3) {
+            return Err(MVisualError::Input(String::from("A custom color map needs at least two RGB colors")));
+        }
+
+        let colors: Vec<[f32; 3]> = colors
+            .chunks(3)
+            .map(|color| [color[0], color[1], color[2]])
+            .collect();
+        self.apply_color_map(util::colormap::ColorMap::from_colors(&colors))
+    }
+
+    pub fn add_surface(
+        &mut self,
+        heights: Vec<f32>,
+        scalars: Vec<f32>,
+        domain: Vec<f32>,
+        color_mode: &str,
+    ) -> Result<u32, MVisualError> {
+        if domain.len() != 4 {
+            return Err(MVisualError::Input(String::from("Surface domain must be [x_min, x_max, y_min, y_max]")));
+        }
+        let color_mode = util::surface::ColorMode::from_name(color_mode)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown color mode: {}", color_mode)))?;
+        let scalars = if scalars.is_empty() { None } else { Some(scalars) };
+
+        let surface = util::surface::Surface::new(
+            heights,
+            scalars,
+            (domain[0], domain[1]),
+            (domain[2], domain[3]),
+        )?;
+
+        let surface = programs::Surface3D::new(&self.gl, surface, color_mode, &self.color_map)?;
+        let (min, max) = surface.value_range();
+        self.set_colorbar(min, max, color_mode.name());
+
+        Ok(self.add_object(Box::new(surface)))
+    }
+
+    pub fn add_surface_expression(
+        &mut self,
+        expression: &str,
+        domain: Vec<f32>,
+        resolution: usize,
+        color_mode: &str,
+    ) -> Result<u32, MVisualError> {
+        if domain.len() != 4 {
+            return Err(MVisualError::Input(String::from("Surface domain must be [x_min, x_max, y_min, y_max]")));
+        }
+        let color_mode = util::surface::ColorMode::from_name(color_mode)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown color mode: {}", color_mode)))?;
+
+        let function = util::surface::SurfaceFunction::parse(
+            expression,
+            resolution,
+            (domain[0], domain[1]),
+            (domain[2], domain[3]),
+        )?;
+
+        let surface = programs::Surface3D::from_function(
+            &self.gl,
+            function,
+            self.scene.clock.time(),
+            color_mode,
+            &self.color_map,
+        )?;
+        let (min, max) = surface.value_range();
+        self.set_colorbar(min, max, color_mode.name());
+
+        Ok(self.add_object(Box::new(surface)))
+    }
+
+    pub fn add_gpu_surface(
+        &mut self,
+        expression: &str,
+        domain: Vec<f32>,
+        resolution: usize,
+        color_mode: &str,
+        normals: &str,
+    ) -> Result<u32, MVisualError> {
+        if domain.len() != 4 {
+            return Err(MVisualError::Input(String::from("Surface domain must be [x_min, x_max, y_min, y_max]")));
+        }
+        let color_mode = util::surface::ColorMode::from_name(color_mode)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown color mode: {}", color_mode)))?;
+        let normals = util::surface::Normals::from_name(normals)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown normals: {}, expected analytic or finite_difference", normals)))?;
+
+        let function = util::surface::SurfaceFunction::parse(
+            expression,
+            resolution,
+            (domain[0], domain[1]),
+            (domain[2], domain[3]),
+        )?;
+
+        let surface = programs::GpuSurface3D::new(
+            &self.gl,
+            function,
+            normals,
+            self.scene.clock.time(),
+            color_mode,
+            &self.color_map,
+        )?;
+        let (min, max) = surface.value_range();
+        self.set_colorbar(min, max, color_mode.name());
+
+        Ok(self.add_object(Box::new(surface)))
+    }
+
+    pub fn add_curve(
+        &mut self,
+        x: &str,
+        y: &str,
+        z: &str,
+        range: Vec<f32>,
+        samples: usize,
+    ) -> Result<u32, MVisualError> {
+        if range.len() != 2 {
+            return Err(MVisualError::Input(String::from("Curve range must be [s_min, s_max]")));
+        }
+
+        let function = util::curve::CurveFunction::parse(x, y, z, (range[0], range[1]), samples)?;
+        let curve = programs::Curve3D::new(&self.gl, function, self.scene.clock.time())?;
+
+        Ok(self.add_object(Box::new(curve)))
+    }
+
+    pub fn play(&mut self) {
+        self.scene.clock.play();
+    }
+
+    pub fn pause(&mut self) {
+        self.scene.clock.pause();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.scene.clock.is_playing()
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.scene.clock.seek(time);
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.scene.clock.set_speed(speed);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.scene.clock.speed()
+    }
+
+    pub fn animation_time(&self) -> f32 {
+        self.scene.clock.time()
+    }
+
+    pub fn add_camera_keyframe(&mut self, time: f32, rotation_x: f32, rotation_y: f32, easing: &str) -> Result<(), MVisualError> {
+        let easing = easing_from_name(easing)?;
+        self.scene.timeline.add_camera_keyframe(util::timeline::Keyframe {
+            time,
+            value: util::timeline::CameraPose { rotation_x, rotation_y },
+            easing,
+        });
+        Ok(())
+    }
+
+    pub fn camera_rotation(&self) -> Vec<f32> {
+        let state = app_state::get_curr_state();
+        vec![state.rotation_x_axis, state.rotation_y_axis]
+    }
+
+    pub fn add_keyframe(
+        &mut self,
+        id: u32,
+        property: &str,
+        time: f32,
+        values: Vec<f32>,
+        easing: &str,
+    ) -> Result<(), MVisualError> {
+        let property = util::timeline::Property::from_name(property)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown keyframe property: {}", property)))?;
+        let easing = easing_from_name(easing)?;
+
+        let program = program_with_id(&mut self.objects, id)?;
+        if property == util::timeline::Property::Scale {
+            if !program.is_scalable() {
+                return Err(MVisualError::Input(format!("Object {} can't be scaled", id)));
+            }
+        } else if program.material().is_none() {
+            return Err(MVisualError::Input(format!("Object {} has no material", id)));
+        }
+
+        self.scene.timeline.add_object_keyframe(
+            id,
+            property,
+            util::timeline::Keyframe { time, value: values, easing },
+        )
+    }
+
+    pub fn clear_timeline(&mut self) {
+        self.scene.timeline.clear();
+    }
+
+    pub fn timeline_duration(&self) -> f32 {
+        self.scene.timeline.duration()
+    }
+
+    pub fn export_animation(
+        &mut self,
+        format: &str,
+        fps: f32,
+        duration: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, MVisualError> {
+        let format = util::export::AnimationFormat::from_name(format)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown animation format: {}, expected gif or apng", format)))?;
+        let settings = util::export::ExportSettings::new(fps, duration, width, height)?;
+
+        let mut bytes = Vec::new();
+        let mut writer = util::export::AnimationWriter::new(format, &settings, &mut bytes)?;
+        self.capture_frames(&settings, |pixels| writer.add_frame(&settings, pixels))?;
+        writer.finish()?;
+        Ok(bytes)
+    }
+
+    pub fn export_frames(&mut self, fps: f32, duration: f32, width: u32, height: u32) -> Result<js_sys::Array, MVisualError> {
+        let settings = util::export::ExportSettings::new(fps, duration, width, height)?;
+
+        let frames = js_sys::Array::new();
+        self.capture_frames(&settings, |pixels| {
+            let png = util::export::encode_png(settings.width, settings.height, pixels)?;
+            frames.push(&js_sys::Uint8Array::from(&png[..]));
+            Ok(())
+        })?;
+        Ok(frames)
+    }
+
+    pub fn add_spheres(&mut self, positions: Vec<f32>, radius: f32) -> Result<u32, MVisualError> {
+        if positions.is_empty() || !positions.len().is_multiple_of(3) {
+            return Err(MVisualError::Input(String::from("Sphere positions must be one or more (x, y, z) triples")));
+        }
+        if !(radius > 0. && radius.is_finite()) {
+            return Err(MVisualError::Input(format!("Sphere radius must be positive, got {}", radius)));
+        }
+
+        let spheres = programs::Sphere3D::with_instances(&self.gl, radius, positions, false)?;
+        Ok(self.add_object(Box::new(spheres)))
+    }
+
+    pub fn set_shading(&mut self, id: u32, shading: &str) -> Result<(), MVisualError> {
+        let shading = programs::Shading::from_name(shading)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown shading: {}", shading)))?;
+
+        let gl = &self.gl;
+        program_with_id(&mut self.objects, id)?.set_shading(gl, shading)
+    }
+
+    pub fn set_scale(&mut self, id: u32, x: f32, y: f32, z: f32) -> Result<(), MVisualError> {
+        program_with_id(&mut self.objects, id)?.set_scale(Vector3::new(x, y, z));
+        Ok(())
+    }
+
+    pub fn set_material_color(&mut self, id: u32, r: f32, g: f32, b: f32) -> Result<(), MVisualError> {
+        self.update_material(id, |material| material.color = [r, g, b])
+    }
+
+    pub fn set_material_reflectance(
+        &mut self,
+        id: u32,
+        ambient: f32,
+        diffuse: f32,
+        specular: f32,
+        shininess: f32,
+    ) -> Result<(), MVisualError> {
+        self.update_material(id, |material| {
+            material.ambient = ambient;
+            material.diffuse = diffuse;
+            material.specular = specular;
+            material.shininess = shininess;
+        })
+    }
+
+    pub fn set_material_opacity(&mut self, id: u32, opacity: f32) -> Result<(), MVisualError> {
+        self.update_material(id, |material| material.opacity = opacity.clamp(0., 1.))
+    }
+
+    pub fn set_material_emissive(&mut self, id: u32, r: f32, g: f32, b: f32) -> Result<(), MVisualError> {
+        self.update_material(id, |material| material.emissive = [r, g, b])
+    }
+
+    pub fn set_wireframe_color(&mut self, id: u32, r: f32, g: f32, b: f32) -> Result<(), MVisualError> {
+        self.update_material(id, |material| material.wireframe_color = [r, g, b])
+    }
+
+    pub fn add_directional_light(&mut self, x: f32, y: f32, z: f32, intensity: f32) -> Result<usize, MVisualError> {
+        self.add_light(util::lighting::LightKind::Directional, Vector3::new(x, y, z), intensity)
+    }
+
+    pub fn add_point_light(&mut self, x: f32, y: f32, z: f32, intensity: f32) -> Result<usize, MVisualError> {
+        self.add_light(util::lighting::LightKind::Point, Vector3::new(x, y, z), intensity)
+    }
+
+    pub fn set_light_position(&mut self, index: usize, x: f32, y: f32, z: f32) -> Result<(), MVisualError> {
+        self.light_mut(index)?.vector = Vector3::new(x, y, z);
+        Ok(())
+    }
+
+    pub fn set_light_color(&mut self, index: usize, r: f32, g: f32, b: f32) -> Result<(), MVisualError> {
+        self.light_mut(index)?.color = [r, g, b];
+        Ok(())
+    }
+
+    pub fn set_light_intensity(&mut self, index: usize, intensity: f32) -> Result<(), MVisualError> {
+        self.light_mut(index)?.intensity = intensity;
+        Ok(())
+    }
+
+    pub fn clear_lights(&mut self) {
+        self.scene.lighting.lights.clear();
+    }
+
+    pub fn set_ambient_light(&mut self, r: f32, g: f32, b: f32) {
+        self.scene.lighting.ambient = [r, g, b];
+    }
+
+    pub fn set_light_follows_camera(&mut self, follow_camera: bool) {
+        self.scene.lighting.follow_camera = follow_camera;
+    }
+
+    pub fn hide_colorbar(&mut self) {
+        self.overlay.colorbar = None;
+    }
+
+    pub fn set_colorbar_corner(&mut self, corner: &str) -> Result<(), MVisualError> {
+        let corner = programs::Corner::from_name(corner)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown corner: {}", corner)))?;
+        if let Some(colorbar) = &mut self.overlay.colorbar {
+            colorbar.corner = corner;
+        }
+        Ok(())
+    }
+
+    pub fn set_object_name(&mut self, id: u32, name: &str) -> Result<(), MVisualError> {
+        let object = self
+            .objects
+            .iter_mut()
+            .find(|object| object.id == id)
+            .ok_or_else(|| MVisualError::Input(format!("No object with id {}", id)))?;
+        object.name = if name.is_empty() { None } else { Some(String::from(name)) };
+        Ok(())
+    }
+
+    pub fn set_legend_corner(&mut self, corner: &str) -> Result<(), MVisualError> {
+        self.overlay.legend_corner = programs::Corner::from_name(corner)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown corner: {}", corner)))?;
+        Ok(())
+    }
+
+    pub fn show_stats(&mut self, corner: &str) -> Result<(), MVisualError> {
+        let corner = programs::Corner::from_name(corner)
+            .ok_or_else(|| MVisualError::Input(format!("Unknown corner: {}", corner)))?;
+        self.overlay.hud = Some(programs::Hud {
+            corner,
+            lines: Vec::new(),
+            histogram: Vec::new(),
+        });
+        Ok(())
+    }
+
+    pub fn hide_stats(&mut self) {
+        self.overlay.hud = None;
+    }
+
+    pub fn frame_stats(&self) -> Result<js_sys::Object, MVisualError> {
+        let stats = &self.stats;
+        let frame = &stats.frame;
+        let histogram = js_sys::Object::new();
+        let edges: js_sys::Array = util::perf::FRAME_TIME_BUCKETS.iter().map(|&edge| JsValue::from(edge)).collect();
+        let counts: js_sys::Array = stats.histogram.iter().map(|&count| JsValue::from(count)).collect();
+        set_field(&histogram, "edges", edges.into())?;
+        set_field(&histogram, "counts", counts.into())?;
+
+        let object = js_sys::Object::new();
+        set_field(&object, "frames", stats.frames.into())?;
+        set_field(&object, "frameTime", stats.frame_time.into())?;
+        set_field(&object, "renderTime", stats.render_time.into())?;
+        set_field(&object, "drawCalls", frame.draw_calls.into())?;
+        // Past 2^53 would need a BigInt, no frame gets near that
+        set_field(&object, "triangles", (frame.triangles as f64).into())?;
+        set_field(&object, "lines", (frame.lines as f64).into())?;
+        set_field(&object, "points", (frame.points as f64).into())?;
+        set_field(&object, "bytesUploaded", (frame.bytes_uploaded as f64).into())?;
+        set_field(&object, "stateChanges", frame.state_changes.into())?;
+        set_field(&object, "culled", stats.culled.into())?;
+        set_field(&object, "histogram", histogram.into())?;
+
+        Ok(object)
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+    }
+
+    pub fn pick(&mut self, x: f32, y: f32) -> Result<JsValue, MVisualError> {
+        match self.nearest_hit(x, y) {
+            Some((id, hit, distance)) => hit_object(id, &hit, distance),
+            None => Ok(JsValue::NULL),
+        }
+    }
+
+    pub fn pick_at_mouse(&mut self) -> Result<JsValue, MVisualError> {
+        let curr_state = app_state::get_curr_state();
+        // The stored mouse y runs up from the bottom, `pick` takes it from the top
+        self.pick(curr_state.mouse_x, curr_state.canvas_height - curr_state.mouse_y)
+    }
+
+    /*
+    The id of the nearest object under the canvas point (`x`, `y`), what was hit on it and how far from the camera.
+    */
+    fn nearest_hit(&mut self, x: f32, y: f32) -> Option<(u32, util::picking::PickHit, f32)> {
+        let curr_state = app_state::get_curr_state();
+        // Canvas coordinates run down from the top, the ray wants them up from the bottom
+        let ray = util::picking::Ray::from_screen(
+            x,
+            curr_state.canvas_height - y,
+            curr_state.canvas_width,
+            curr_state.canvas_height,
+            projection(&curr_state).as_matrix(),
+        )?;
+
+        let mut nearest: Option<(u32, util::picking::PickHit)> = None;
+        for object in self.objects.iter_mut() {
+            let limit = nearest.as_ref().map_or(1., |(_, hit)| hit.t);
+            // Skips building anything for objects the ray can't hit, or only behind what it already has
+            let reachable = object.program.view_bounds(&curr_state).is_none_or(|bounds| {
+                ray.intersect_sphere(&bounds.sphere)
+                    .is_some_and(|(entry, exit)| exit >= 0. && entry <= limit)
+            });
+            if !reachable {
+                continue;
+            }
+            if let Some(hit) = object.program.pick(&ray, &curr_state) {
+                if hit.t <= limit {
+                    nearest = Some((object.id, hit));
+                }
+            }
+        }
+
+        // The camera sits at the view space origin
+        nearest.map(|(id, hit)| {
+            let distance = ray.at(hit.t).norm();
+            (id, hit, distance)
+        })
+    }
+
+    pub fn remove_object(&mut self, id: u32) -> Result<Option<util::selection::SelectionEvent>, MVisualError> {
+        let index = self
+            .objects
+            .iter()
+            .position(|object| object.id == id)
+            .ok_or_else(|| MVisualError::Input(format!("No object with id {}", id)))?;
+        let mut object = self.objects.remove(index);
+        object.program.release(&self.gl);
+        self.scene.timeline.remove_object(id);
+
+        let change = self.selection.deselect(id);
+        self.apply_selection_change(change, JsValue::NULL)
+    }
+
+    pub fn object_ids(&self) -> Vec<u32> {
+        self.objects.iter().map(|object| object.id).collect()
+    }
+
+    pub fn select(&mut self, id: u32, additive: bool) -> Result<Option<util::selection::SelectionEvent>, MVisualError> {
+        program_with_id(&mut self.objects, id)?;
+        let change = self.selection.select(id, additive);
+        self.apply_selection_change(change, JsValue::NULL)
+    }
+
+    pub fn deselect(&mut self, id: u32) -> Result<Option<util::selection::SelectionEvent>, MVisualError> {
+        let change = self.selection.deselect(id);
+        self.apply_selection_change(change, JsValue::NULL)
+    }
+
+    pub fn clear_selection(&mut self) -> Result<Option<util::selection::SelectionEvent>, MVisualError> {
+        let change = self.selection.clear();
+        self.apply_selection_change(change, JsValue::NULL)
+    }
+
+    pub fn selected(&self) -> Vec<u32> {
+        self.selection.ids().to_vec()
+    }
+
+    /*
+    Selects what is under the canvas point (`x`, `y`) the way a click does, see `Selection::click`.
+    */
+    pub fn click_select(&mut self, x: f32, y: f32, additive: bool) -> Result<Option<util::selection::SelectionEvent>, MVisualError> {
+        let hit = self.nearest_hit(x, y);
+        let change = self.selection.click(hit.as_ref().map(|(id, _, _)| *id), additive);
+        let hit = match hit {
+            Some((id, hit, distance)) => hit_object(id, &hit, distance)?,
+            None => JsValue::NULL,
+        };
+        self.apply_selection_change(change, hit)
+    }
+
+    pub fn set_highlight_color(&mut self, r: f32, g: f32, b: f32) {
+        self.selection.highlight_color = [r, g, b];
+        let tint = Some(self.selection.highlight_color);
+        let selection = &self.selection;
+        for object in self.objects.iter_mut().filter(|object| selection.contains(object.id)) {
+            object.program.set_highlight(tint);
+        }
+    }
+
+    pub fn set_selection_listener(&mut self, listener: Option<js_sys::Function>) {
+        self.selection_listener = listener;
+    }
+
+    /*
+    Highlights what a change selected and unhighlights what it deselected, then makes the event for the listener (when there is a change and a listener). `hit` is the pick which caused the change, null when it wasn't a click.
+    */
+    fn apply_selection_change(
+        &mut self,
+        change: Option<util::selection::SelectionChange>,
+        hit: JsValue,
+    ) -> Result<Option<util::selection::SelectionEvent>, MVisualError> {
+        let change = match change {
+            Some(change) => change,
+            None => return Ok(None),
+        };
+        for object in self.objects.iter_mut() {
+            if change.added.contains(&object.id) {
+                object.program.set_highlight(Some(self.selection.highlight_color));
+            } else if change.removed.contains(&object.id) {
+                object.program.set_highlight(None);
+            }
+        }
+
+        let listener = match &self.selection_listener {
+            Some(listener) => listener.clone(),
+            None => return Ok(None),
+        };
+        let ids = |ids: &[u32]| -> JsValue { ids.iter().map(|&id| JsValue::from(id)).collect::<js_sys::Array>().into() };
+        let detail = js_sys::Object::new();
+        set_field(&detail, "selected", ids(self.selection.ids()))?;
+        set_field(&detail, "added", ids(&change.added))?;
+        set_field(&detail, "removed", ids(&change.removed))?;
+        set_field(&detail, "hit", hit)?;
+        Ok(Some(util::selection::SelectionEvent { listener, detail }))
+    }
+
+    fn add_object(&mut self, program: Box<dyn programs::Program>) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.objects.push(SceneObject { id, name: None, program });
+        id
+    }
+}
+
+/*
+A program with the id it was added under. Ids stay with their object when others are removed, so the page, keyframes and the selection can all hold on to them.
+*/
+struct SceneObject {
+    id: u32,
+    // Shown in the legend when set
+    name: Option<String>,
+    program: Box<dyn programs::Program>,
+}
+
+fn hit_object(id: u32, hit: &util::picking::PickHit, distance: f32) -> Result<JsValue, MVisualError> {
+    let vector = |values: &[f32]| -> JsValue {
+        values.iter().map(|&value| JsValue::from(value)).collect::<js_sys::Array>().into()
+    };
+
+    let object = js_sys::Object::new();
+    set_field(&object, "object", id.into())?;
+    set_field(&object, "distance", distance.into())?;
+    set_field(&object, "position", vector(hit.position.as_slice()))?;
+    set_field(&object, "normal", vector(hit.normal.as_slice()))?;
+    if let Some(triangle) = hit.triangle {
+        set_field(&object, "triangle", (triangle as u32).into())?;
+    }
+    if let Some(barycentric) = hit.barycentric {
+        set_field(&object, "barycentric", vector(&barycentric))?;
+    }
+    if let Some(instance) = hit.instance {
+        set_field(&object, "instance", (instance as u32).into())?;
+    }
+    if let Some(parameters) = hit.parameters {
+        set_field(&object, "parameters", vector(&parameters))?;
+    }
+    if let Some(value) = hit.value {
+        set_field(&object, "value", value.into())?;
+    }
+
+    Ok(object.into())
+}
+
+fn projection(curr_state: &app_state::AppState) -> Perspective3<f32> {
+    Perspective3::new(
+        curr_state.canvas_width / curr_state.canvas_height,
+        util::constants::FIELD_OF_VIEW,
+        util::constants::Z_NEAR,
+        util::constants::Z_FAR,
+    )
+}
+
+fn program_with_id(
+    objects: &mut [SceneObject],
+    id: u32,
+) -> Result<&mut Box<dyn programs::Program>, MVisualError> {
+    objects
+        .iter_mut()
+        .find(|object| object.id == id)
+        .map(|object| &mut object.program)
+        .ok_or_else(|| MVisualError::Input(format!("No object with id {}", id)))
+}
+
+fn set_field(object: &js_sys::Object, key: &str, value: JsValue) -> Result<(), MVisualError> {
+    js_sys::Reflect::set(object, &JsValue::from_str(key), &value)
+        .map(|_| ())
+        .map_err(MVisualError::context)
+}
+
+fn easing_from_name(name: &str) -> Result<util::timeline::Easing, MVisualError> {
+    util::timeline::Easing::from_name(name).ok_or_else(|| {
+        MVisualError::Input(format!(
+            "Unknown easing: {}, expected linear, step, ease-in, ease-out or ease-in-out",
+            name
+        ))
+    })
+}
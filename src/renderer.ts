@@ -7,46 +7,25 @@
 
 import rust from "../pkg/m_visual"
 
+// Held for the life of the page, the frame loop stops once the visual is freed
+let visual: any;
+
 // @ts-ignore
 rust.then(m => {
   const canvas = document.getElementById('rustCanvas') as HTMLCanvasElement;
-  const gl = canvas.getContext('webgl', { antialias: true });
-  if (!canvas || !gl) {
-    alert('Failed to init WebGL');
+  // The context is created in Rust (WebGL2 when available), a canvas only has one so don't create it here
+  if (!canvas) {
+    alert('Failed to find the canvas');
     return;
   }
 
-  const FPS_THROTTLE = 1000.0 / 30.0; // Milliseconds / frames
-  const visual = new m.MVisual();
-  const initialTime = Date.now();
-  var lastDrawTime = -1; // In ms
-
-  function render() {
-    window.requestAnimationFrame(render);
-    const currTime = Date.now();
-
-    if (currTime > lastDrawTime + FPS_THROTTLE && canvas && gl){
-      lastDrawTime = currTime;
-
-      // Check for window resize to update the canvas size
-      if (window.innerHeight != canvas.height || window.innerWidth != canvas.width) {
-        canvas.height = window.innerHeight;
-        // canvas.clientHeight = window.innerHeight;
-        canvas.style.height = `${window.innerHeight}`;
-
-        canvas.width = window.innerWidth;
-        // canvas.clientWidth = window.innerWidth;
-        canvas.style.width = `${window.innerHeight}`;
-
-        gl.viewport(0, 0, window.innerWidth, window.innerHeight);
-      }
-
-      let elapsedTime = currTime - initialTime;
-      visual.update(elapsedTime, window.innerHeight, window.innerWidth);
-      visual.render()
-    }
+  try {
+    visual = new m.MVisual();
+    // Draws every frame and keeps the canvas's resolution matched to its CSS size
+    visual.start();
+  } catch (error) {
+    // error.name tells what failed, e.g. MVisualContextError or MVisualShaderError
+    alert(`Failed to start: ${error.name}: ${error.message}`);
+    return;
   }
-
-  // Initialize the loop
-  render();
 })
\ No newline at end of file